@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE picture_metadata (
+    picture_id INTEGER PRIMARY KEY UNIQUE NOT NULL,
+    taken_at VARCHAR(20),
+    camera_make VARCHAR(100),
+    camera_model VARCHAR(100),
+    lens VARCHAR(100),
+    exposure_time VARCHAR(20),
+    f_number DOUBLE,
+    iso INTEGER,
+    focal_length DOUBLE,
+    orientation INTEGER,
+    latitude DOUBLE,
+    longitude DOUBLE,
+    FOREIGN KEY(picture_id) REFERENCES pictures(id) ON DELETE CASCADE
+)
\ No newline at end of file
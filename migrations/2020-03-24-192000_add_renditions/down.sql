@@ -0,0 +1,19 @@
+-- This file should undo anything in `up.sql`
+PRAGMA foreign_keys=off;
+
+ALTER TABLE thumbs RENAME TO _thumbs_old;
+
+CREATE TABLE thumbs (
+    picture_id INTEGER PRIMARY KEY UNIQUE NOT NULL,
+    picture_hash VARCHAR(40) NOT NULL,
+    FOREIGN KEY(picture_id) REFERENCES pictures(id) ON DELETE CASCADE
+);
+
+INSERT INTO thumbs (picture_id, picture_hash)
+  SELECT picture_id, picture_hash
+  FROM _thumbs_old
+  WHERE rendition = 'small';
+
+DROP TABLE _thumbs_old;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+PRAGMA foreign_keys=off;
+
+ALTER TABLE thumbs RENAME TO _thumbs_old;
+
+CREATE TABLE thumbs (
+    picture_id INTEGER NOT NULL,
+    rendition VARCHAR(50) NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    fit VARCHAR(10) NOT NULL,
+    picture_hash VARCHAR(40) NOT NULL,
+    CONSTRAINT thumb_pk PRIMARY KEY(picture_id, rendition),
+    FOREIGN KEY(picture_id) REFERENCES pictures(id) ON DELETE CASCADE
+);
+
+INSERT INTO thumbs (picture_id, rendition, width, height, fit, picture_hash)
+  SELECT picture_id, 'small', 100, 100, 'contain', picture_hash
+  FROM _thumbs_old;
+
+DROP TABLE _thumbs_old;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
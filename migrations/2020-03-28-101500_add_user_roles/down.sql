@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+PRAGMA foreign_keys=off;
+
+ALTER TABLE users RENAME TO _users_old;
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR(50) UNIQUE NOT NULL,
+    email VARCHAR(254) UNIQUE NOT NULL,
+    password VARCHAR(40) NOT NULL,
+    verification VARCHAR(20) UNIQUE
+);
+
+INSERT INTO users (id, username, email, password, verification)
+  SELECT id, username, email, password, verification
+  FROM _users_old;
+
+DROP TABLE _users_old;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
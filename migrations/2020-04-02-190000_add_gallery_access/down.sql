@@ -0,0 +1,23 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE gallery_access;
+
+PRAGMA foreign_keys=off;
+
+ALTER TABLE gallerys RENAME TO _gallerys_old;
+
+CREATE TABLE gallerys (
+    id  INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name VARCHAR(200) NOT NULL,
+    directory VARCHAR(255) UNIQUE,
+    parent INTEGER,
+    FOREIGN KEY(parent) REFERENCES gallerys(id),
+    UNIQUE(name, parent)
+);
+
+INSERT INTO gallerys (id, name, directory, parent)
+  SELECT id, name, directory, parent
+  FROM _gallerys_old;
+
+DROP TABLE _gallerys_old;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
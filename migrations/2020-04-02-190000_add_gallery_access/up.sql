@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE gallerys ADD COLUMN visibility VARCHAR(20) NOT NULL DEFAULT 'inherit';
+
+CREATE TABLE gallery_access (
+    gallery_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    CONSTRAINT gallery_access_pk PRIMARY KEY(gallery_id, user_id),
+    FOREIGN KEY(gallery_id) REFERENCES gallerys(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
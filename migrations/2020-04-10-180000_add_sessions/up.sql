@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE sessions (
+    id VARCHAR(40) PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    created BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    expires BIGINT NOT NULL,
+    user_agent VARCHAR(255),
+    ip VARCHAR(45),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_user_id ON sessions(user_id);
\ No newline at end of file
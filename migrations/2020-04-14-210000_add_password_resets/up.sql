@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE password_resets (
+    token_hash VARCHAR(40) PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    expires BIGINT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE login_failures (
+    kind VARCHAR(10) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failure BIGINT NOT NULL,
+    locked_until BIGINT NOT NULL,
+    CONSTRAINT login_failures_pk PRIMARY KEY(kind, subject)
+);
\ No newline at end of file
@@ -0,0 +1,23 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE recovery_codes;
+
+PRAGMA foreign_keys=off;
+
+CREATE TABLE _users_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR(50) UNIQUE NOT NULL,
+    email VARCHAR(254) UNIQUE NOT NULL,
+    password VARCHAR(255) NOT NULL,
+    verification VARCHAR(20) UNIQUE,
+    role VARCHAR(20) NOT NULL DEFAULT 'user'
+);
+
+INSERT INTO _users_new (id, username, email, password, verification, role)
+  SELECT id, username, email, password, verification, role
+  FROM users;
+
+DROP TABLE users;
+
+ALTER TABLE _users_new RENAME TO users;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN totp_secret VARCHAR(64);
+
+CREATE TABLE recovery_codes (
+    user_id INTEGER NOT NULL,
+    code_hash VARCHAR(40) NOT NULL,
+    CONSTRAINT recovery_codes_pk PRIMARY KEY(user_id, code_hash),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE share_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    token_hash VARCHAR(40) UNIQUE NOT NULL,
+    gallery_id INTEGER,
+    picture_id INTEGER,
+    recursive BOOLEAN NOT NULL DEFAULT 0,
+    created_by INTEGER NOT NULL,
+    created BIGINT NOT NULL,
+    expires BIGINT,
+    password VARCHAR(255),
+    allow_download BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY(gallery_id) REFERENCES gallerys(id) ON DELETE CASCADE,
+    FOREIGN KEY(picture_id) REFERENCES pictures(id) ON DELETE CASCADE,
+    FOREIGN KEY(created_by) REFERENCES users(id) ON DELETE CASCADE
+);
\ No newline at end of file
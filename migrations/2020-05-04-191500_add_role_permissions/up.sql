@@ -0,0 +1,47 @@
+-- Your SQL goes here
+CREATE TABLE roles (
+    name VARCHAR(20) PRIMARY KEY NOT NULL
+);
+
+CREATE TABLE role_permissions (
+    role VARCHAR(20) NOT NULL,
+    permission VARCHAR(20) NOT NULL,
+    CONSTRAINT role_permissions_pk PRIMARY KEY(role, permission),
+    FOREIGN KEY(role) REFERENCES roles(name) ON DELETE CASCADE
+);
+
+INSERT INTO roles (name) VALUES ('admin'), ('editor'), ('viewer');
+
+INSERT INTO role_permissions (role, permission) VALUES
+  ('admin', 'view'),
+  ('admin', 'upload'),
+  ('admin', 'edit_metadata'),
+  ('admin', 'delete'),
+  ('admin', 'administer'),
+  ('editor', 'view'),
+  ('editor', 'upload'),
+  ('editor', 'edit_metadata'),
+  ('editor', 'delete'),
+  ('viewer', 'view');
+
+PRAGMA foreign_keys=off;
+
+CREATE TABLE _users_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR(50) UNIQUE NOT NULL,
+    email VARCHAR(254) UNIQUE NOT NULL,
+    password VARCHAR(255) NOT NULL,
+    verification VARCHAR(20) UNIQUE,
+    role VARCHAR(20) NOT NULL DEFAULT 'viewer' REFERENCES roles(name),
+    totp_secret VARCHAR(64)
+);
+
+INSERT INTO _users_new (id, username, email, password, verification, role, totp_secret)
+  SELECT id, username, email, password, verification, CASE role WHEN 'user' THEN 'viewer' ELSE role END, totp_secret
+  FROM users;
+
+DROP TABLE users;
+
+ALTER TABLE _users_new RENAME TO users;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
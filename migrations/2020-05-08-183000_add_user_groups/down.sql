@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE gallery_group_access;
+DROP TABLE group_members;
+DROP TABLE user_groups;
\ No newline at end of file
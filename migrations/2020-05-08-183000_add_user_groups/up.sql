@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE user_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name VARCHAR(50) UNIQUE NOT NULL
+);
+
+CREATE TABLE group_members (
+    group_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    CONSTRAINT group_members_pk PRIMARY KEY(group_id, user_id),
+    FOREIGN KEY(group_id) REFERENCES user_groups(id) ON DELETE CASCADE,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE gallery_group_access (
+    gallery_id INTEGER NOT NULL,
+    group_id INTEGER NOT NULL,
+    CONSTRAINT gallery_group_access_pk PRIMARY KEY(gallery_id, group_id),
+    FOREIGN KEY(gallery_id) REFERENCES gallerys(id) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES user_groups(id) ON DELETE CASCADE
+);
\ No newline at end of file
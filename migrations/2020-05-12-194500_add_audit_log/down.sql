@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX audit_log_action;
+DROP INDEX audit_log_created;
+DROP TABLE audit_log;
\ No newline at end of file
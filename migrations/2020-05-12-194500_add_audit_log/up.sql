@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created BIGINT NOT NULL,
+    actor_id INTEGER,
+    action VARCHAR(50) NOT NULL,
+    target VARCHAR(100),
+    details TEXT,
+    ip VARCHAR(45)
+);
+
+CREATE INDEX audit_log_created ON audit_log(created);
+CREATE INDEX audit_log_action ON audit_log(action);
\ No newline at end of file
@@ -0,0 +1,21 @@
+-- This file should undo anything in `up.sql`
+PRAGMA foreign_keys=off;
+
+DROP INDEX tags_parent;
+
+CREATE TABLE _tags_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tag_type INTEGER NOT NULL DEFAULT 1,
+    name VARCHAR(100) NOT NULL UNIQUE
+);
+
+INSERT OR IGNORE INTO _tags_new (id, tag_type, name)
+  SELECT id, tag_type, name
+  FROM tags
+  ORDER BY id;
+
+DROP TABLE tags;
+
+ALTER TABLE _tags_new RENAME TO tags;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+PRAGMA foreign_keys=off;
+
+CREATE TABLE _tags_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tag_type INTEGER NOT NULL DEFAULT 1,
+    name VARCHAR(100) NOT NULL,
+    parent INTEGER,
+    FOREIGN KEY(parent) REFERENCES tags(id),
+    UNIQUE(name, parent)
+);
+
+INSERT INTO _tags_new (id, tag_type, name)
+  SELECT id, CASE WHEN tag_type BETWEEN 1 AND 5 THEN tag_type ELSE 1 END, name
+  FROM tags;
+
+DROP TABLE tags;
+
+ALTER TABLE _tags_new RENAME TO tags;
+
+CREATE INDEX tags_parent ON tags(parent);
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Pictures scanned before metadata was extracted are unchanged on disk and would be
+-- skipped by the scanner forever. Resetting size and hash makes the next scan read them again.
+UPDATE pictures
+  SET sha1 = '', filesize = -1
+  WHERE id NOT IN (SELECT picture_id FROM picture_metadata);
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Rotated pictures were stored with the dimensions and thumbnails of the unrotated file.
+-- Dropping their thumbnails and resetting size and hash makes the next scan store the
+-- turned dimensions and regenerate the thumbnails.
+DELETE FROM thumbs
+  WHERE picture_id IN (SELECT picture_id FROM picture_metadata WHERE orientation > 1);
+
+UPDATE pictures
+  SET sha1 = '', filesize = -1
+  WHERE id IN (SELECT picture_id FROM picture_metadata WHERE orientation > 1);
\ No newline at end of file
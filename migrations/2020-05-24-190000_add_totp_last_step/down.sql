@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+PRAGMA foreign_keys=off;
+
+CREATE TABLE _users_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR(50) UNIQUE NOT NULL,
+    email VARCHAR(254) UNIQUE NOT NULL,
+    password VARCHAR(255) NOT NULL,
+    verification VARCHAR(20) UNIQUE,
+    role VARCHAR(20) NOT NULL DEFAULT 'viewer' REFERENCES roles(name),
+    totp_secret VARCHAR(64)
+);
+
+INSERT INTO _users_new (id, username, email, password, verification, role, totp_secret)
+  SELECT id, username, email, password, verification, role, totp_secret
+  FROM users;
+
+DROP TABLE users;
+
+ALTER TABLE _users_new RENAME TO users;
+
+PRAGMA foreign_keys=on;
\ No newline at end of file
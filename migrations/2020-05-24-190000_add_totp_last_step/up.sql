@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use crate::auth::login::ClientInfo;
+use crate::database::model::NewAuditEntry;
+use crate::database::provider;
+use colored::Colorize;
+
+pub const LOGIN: &'static str = "login";
+pub const LOGIN_FAILED: &'static str = "login_failed";
+pub const GALLERY_DELETE: &'static str = "gallery_delete";
+/// Galleries and pictures removed by scanning because they vanished from disk.
+pub const GALLERY_REMOVE: &'static str = "gallery_remove";
+pub const PICTURE_REMOVE: &'static str = "picture_remove";
+pub const VISIBILITY_CHANGE: &'static str = "visibility_change";
+pub const ACCESS_GRANT: &'static str = "access_grant";
+pub const ACCESS_REVOKE: &'static str = "access_revoke";
+pub const ROLE_CHANGE: &'static str = "role_change";
+pub const USER_DELETE: &'static str = "user_delete";
+pub const GROUP_CREATE: &'static str = "group_create";
+pub const GROUP_DELETE: &'static str = "group_delete";
+pub const GROUP_MEMBER_ADD: &'static str = "group_member_add";
+pub const GROUP_MEMBER_REMOVE: &'static str = "group_member_remove";
+
+/// What an action was done to. Stored as `<kind>:<id>`, e.g. `gallery:12`.
+pub enum Target {
+    Gallery(i32),
+    Picture(i32),
+    User(i32),
+    Group(i32),
+    /// A failed login with a name that doesn't belong to any user. People
+    /// sometimes type their password into the name field, so the name isn't
+    /// stored at all, not even hashed, as short inputs are easy to guess back.
+    Login,
+}
+
+impl Target {
+    pub fn key(&self) -> String {
+        use Target::*;
+        match self {
+            Gallery(id) => format!("gallery:{}", id),
+            Picture(id) => format!("picture:{}", id),
+            User(id) => format!("user:{}", id),
+            Group(id) => format!("group:{}", id),
+            Login => "login".to_string(),
+        }
+    }
+}
+
+/// Records an action. `actor` is `None` for actions of the server itself or
+/// the command line. Failing to write the entry is only reported, so the
+/// audited action isn't aborted because of it.
+pub fn record(actor: Option<i32>, action: &str, target: Target, details: Option<String>, client: &ClientInfo) {
+    let entry = NewAuditEntry {
+        created: crate::auth::now(),
+        actor_id: actor,
+        action: action.to_string(),
+        target: Some(target.key()),
+        details,
+        ip: client.ip.clone(),
+    };
+    if let Err(e) = provider::audit_log::insert(&entry) {
+        eprintln!("{} {} {:?}", "! Cannot write audit log:".yellow(), action, e);
+    }
+}
+
+/// Records an action done without a client, e.g. by scanning or the command line.
+pub fn record_local(action: &str, target: Target, details: Option<String>) {
+    record(None, action, target, details, &ClientInfo::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::ClientInfo;
+    use crate::database::provider::audit_log::AuditFilter;
+
+    #[test]
+    fn record() {
+        crate::testing::setup_database();
+        let client = ClientInfo {
+            user_agent: None,
+            ip: Some("127.0.0.1".to_string()),
+        };
+        super::record(Some(3), super::GALLERY_DELETE, super::Target::Gallery(7), Some("Holidays".to_string()), &client);
+        let entries = crate::database::provider::audit_log::query(&AuditFilter::default(), 0, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor_id, Some(3));
+        assert_eq!(entries[0].target, Some("gallery:7".to_string()));
+        assert_eq!(entries[0].ip, Some("127.0.0.1".to_string()));
+    }
+
+    #[test]
+    fn login_target_hides_name() {
+        assert_eq!(super::Target::Login.key(), "login");
+    }
+}
\ No newline at end of file
@@ -1,33 +1,66 @@
+use rsgen::OutputCharsType::LatinAlphabetAndNumeric;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Random alphanumeric string for session ids, salts and tokens.
+pub fn random_string(length: usize) -> String {
+    rsgen::gen_random_string(length, LatinAlphabetAndNumeric {
+        use_upper_case: true,
+        use_lower_case: true,
+    })
+}
+
+/// Current unix time in seconds.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
 pub mod login {
 
+    use crate::auth::{now, random_string};
+    use crate::auth::token::TokenScope;
     use crate::database::provider;
     use sha::utils::{Digest, DigestExt};
-    use rsgen::OutputCharsType::LatinAlphabetAndNumeric;
 
-    use std::collections::HashMap;
-    use std::sync::{Arc, Mutex};
-    use crate::database::model::User;
+    use crate::config::Config;
+    use crate::database::model::{Session, User};
+    use colored::Colorize;
+    use std::time::Duration;
 
-    lazy_static! {
-        static ref SESSIONS: Arc<Mutex<HashMap<String, LoginUser>>> = Arc::new(Mutex::new(HashMap::new()));
-    }
+    const SALT_LENGTH: usize = 16;
+    const SESSION_ID_LENGTH: usize = 32;
+    /// Minimum seconds between two updates of a session's last seen time,
+    /// so not every request results in a database write.
+    const TOUCH_INTERVAL: i64 = 60;
 
     #[derive(Clone)]
     pub struct LoginUser {
         pub id: i32,
+        pub role: String,
+        /// What the user may do in this request. Sessions always have `TokenScope::Full`.
+        pub scope: TokenScope,
     }
 
     impl From<User> for LoginUser {
         fn from(u: User) -> Self {
             Self {
-                id: u.id
+                id: u.id,
+                role: u.role,
+                scope: TokenScope::Full,
             }
         }
     }
 
+    #[derive(Debug)]
     pub enum LoginError {
-        UserNotFound,
+        NotVerified,
+        /// Too many failed attempts. Contains the seconds until the next try is allowed.
+        Locked(i64),
+        /// The password was correct, but the user with this id has to enter a TOTP code.
+        SecondFactorRequired(i32),
+        /// TOTP is already turned on and has to be disabled before enrolling a new secret.
+        SecondFactorEnabled,
         Database(crate::database::Error),
+        Hash(argon2::Error),
     }
 
     impl From<crate::database::Error> for LoginError {
@@ -36,62 +69,1057 @@ pub mod login {
         }
     }
 
+    impl From<argon2::Error> for LoginError {
+        fn from(e: argon2::Error) -> Self {
+            LoginError::Hash(e)
+        }
+    }
+
     pub enum LoginIdentifier {
         Username(String),
         Email(String),
     }
 
-    pub fn create_session(ident: LoginIdentifier, password: &str) -> Result<Option<String>, LoginError> {
+    impl From<&str> for LoginIdentifier {
+        /// Usernames cannot contain an `@`, so anything with one is an email address.
+        fn from(login: &str) -> Self {
+            if login.contains('@') {
+                LoginIdentifier::Email(login.to_string())
+            } else {
+                LoginIdentifier::Username(login.to_string())
+            }
+        }
+    }
+
+    /// The client a session is created for, shown to users listing their sessions.
+    #[derive(Clone, Debug, Default)]
+    pub struct ClientInfo {
+        pub user_agent: Option<String>,
+        pub ip: Option<String>,
+    }
+
+    /// Logs a user in. Unknown users and wrong passwords both result in `Ok(None)`,
+    /// so callers can't tell which accounts exist.
+    pub fn create_session(ident: LoginIdentifier, password: &str, client: &ClientInfo) -> Result<Option<String>, LoginError> {
         use LoginIdentifier::*;
-        let user = match ident {
-            Username(username) => provider::user::by_username(&username),
-            Email(email) => provider::user::by_email(&email),
-        }?;
+        let (user, login) = match ident {
+            Username(username) => (provider::user::by_username(&username)?, username),
+            Email(email) => (provider::user::by_email(&email)?, email),
+        };
+        let subjects = throttle::subjects(user.as_ref(), &login, client);
+        let user_id = user.as_ref().map(|u| u.id);
+        if let Some(until) = throttle::locked_until(&subjects)? {
+            return Err(LoginError::Locked(until - now()));
+        }
         if let Some(user) = user {
-            if user.password.eq(&hash_pw(password)) {
-                let sess_id = create_session_id();
-                let mut sessions = SESSIONS.lock().unwrap();
-                sessions.insert(sess_id.clone(), user.into());
-                Ok(Some(sess_id))
-            } else {
-                Ok(None)
+            if verify_pw(&user.password, password)? {
+                if user.verification.is_some() {
+                    return Err(LoginError::NotVerified);
+                }
+                if is_legacy_hash(&user.password) {
+                    provider::user::update_password(&user.id, &hash_pw(password)?)?;
+                }
+                if user.totp_secret.is_some() {
+                    // Failures are only cleared by `totp::verify_login` once the code is valid too
+                    return Err(LoginError::SecondFactorRequired(user.id));
+                }
+                throttle::succeeded(&subjects)?;
+                return Ok(Some(start_session(&user, client)?));
             }
         } else {
-            Err(LoginError::UserNotFound)
+            // Hash anyway so the response time doesn't reveal unknown users
+            hash_pw(password)?;
         }
+        throttle::failed(&subjects)?;
+        let target = match user_id {
+            Some(user_id) => crate::audit::Target::User(user_id),
+            None => crate::audit::Target::Login,
+        };
+        crate::audit::record(user_id, crate::audit::LOGIN_FAILED, target, None, client);
+        Ok(None)
+    }
+
+    pub fn start_session(user: &User, client: &ClientInfo) -> Result<String, LoginError> {
+        let now = now();
+        let session = Session {
+            id: create_session_id()?,
+            user_id: user.id,
+            created: now,
+            last_seen: now,
+            expires: now + lifetime(),
+            user_agent: client.user_agent.clone(),
+            ip: client.ip.clone(),
+        };
+        provider::session::insert(&session)?;
+        crate::audit::record(Some(user.id), crate::audit::LOGIN, crate::audit::Target::User(user.id), None, client);
+        Ok(session.id)
     }
 
+    /// Finds the user logged in with `session_id`. Every use of a session
+    /// pushes its expiry back by `Config.session_lifetime`.
     pub fn user_for_session(session_id: &str) -> Option<LoginUser> {
-        let sessions = SESSIONS.lock().unwrap();
-        return sessions.get(session_id).map(|a| a.clone())
+        let session = provider::session::by_id(session_id).ok()??;
+        let now = now();
+        if session.expires <= now {
+            let _ = provider::session::delete(session_id);
+            return None;
+        }
+        if now - session.last_seen >= TOUCH_INTERVAL {
+            let _ = provider::session::touch(session_id, now, now + lifetime());
+        }
+        provider::user::by_id(&session.user_id).ok().map(LoginUser::from)
+    }
+
+    pub fn end_session(session_id: &str) -> Result<(), LoginError> {
+        provider::session::delete(session_id)?;
+        Ok(())
+    }
+
+    /// Logs the user out on all devices. Returns the number of ended sessions.
+    pub fn end_all_sessions(user: &LoginUser) -> Result<usize, LoginError> {
+        Ok(provider::session::delete_by_user(&user.id)?)
     }
 
-    pub fn end_session(session_id: &str) {
-        let mut sessions = SESSIONS.lock().unwrap();
-        sessions.remove(session_id);
+    /// Starts a background thread deleting expired sessions, password resets and login failures every
+    /// `Config.session_reap_interval` seconds.
+    pub fn start_reaper(conf: &'static Config) {
+        std::thread::spawn(move || {
+            loop {
+                match provider::session::delete_expired(now()) {
+                    Ok(0) => {},
+                    Ok(count) => println!("{} {}", "Removed expired sessions:".blue(), count),
+                    Err(e) => eprintln!("{} {:?}", "! Cannot remove expired sessions:".yellow(), e),
+                }
+                if let Err(e) = provider::password_reset::delete_expired(now()) {
+                    eprintln!("{} {:?}", "! Cannot remove expired password resets:".yellow(), e);
+                }
+                if let Err(e) = provider::login_failure::delete_stale(now(), conf.login_failure_window as i64) {
+                    eprintln!("{} {:?}", "! Cannot remove old login failures:".yellow(), e);
+                }
+                if conf.audit_retention > 0 {
+                    let before = now() - conf.audit_retention as i64 * 24 * 60 * 60;
+                    match provider::audit_log::delete_before(before) {
+                        Ok(0) => {},
+                        Ok(count) => println!("{} {}", "Removed old audit log entries:".blue(), count),
+                        Err(e) => eprintln!("{} {:?}", "! Cannot remove old audit log entries:".yellow(), e),
+                    }
+                }
+                std::thread::sleep(Duration::from_secs(conf.session_reap_interval));
+            }
+        });
     }
 
-    fn create_session_id() -> String {
-        let sessions = SESSIONS.lock().unwrap();
+    fn create_session_id() -> Result<String, LoginError> {
         loop {
-            let id = rsgen::gen_random_string(20, LatinAlphabetAndNumeric {
-                use_upper_case: true,
-                use_lower_case: true,
-            });
-            if sessions.get(&id).is_none() {
-                return id;
+            let id = random_string(SESSION_ID_LENGTH);
+            if provider::session::by_id(&id)?.is_none() {
+                return Ok(id);
             }
         }
     }
 
-    fn hash_pw(password: &str) -> String {
+    fn lifetime() -> i64 {
+        crate::config::get().session_lifetime as i64
+    }
+
+    /// Hashes `password` with Argon2id and a random salt. The result is in the
+    /// PHC string format, so the parameters can change without breaking old hashes.
+    pub fn hash_pw(password: &str) -> Result<String, LoginError> {
+        let salt = random_string(SALT_LENGTH);
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            ..argon2::Config::default()
+        };
+        Ok(argon2::hash_encoded(password.as_bytes(), salt.as_bytes(), &config)?)
+    }
+
+    pub fn verify_pw(hash: &str, password: &str) -> Result<bool, LoginError> {
+        if is_legacy_hash(hash) {
+            Ok(hash.eq(&legacy_hash_pw(password)))
+        } else {
+            Ok(argon2::verify_encoded(hash, password.as_bytes())?)
+        }
+    }
+
+    /// Passwords stored before switching to Argon2 are unsalted SHA-1 hex digests.
+    fn is_legacy_hash(hash: &str) -> bool {
+        hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    fn legacy_hash_pw(password: &str) -> String {
         sha::sha1::Sha1::default().digest(password.as_bytes()).to_hex()
     }
 
+    #[cfg(test)]
+    pub fn session_for(user: User) -> String {
+        start_session(&user, &ClientInfo::default()).unwrap()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::{ClientInfo, LoginIdentifier, LoginUser};
+        use crate::auth::permission::ROLE_VIEWER;
+        use crate::database::provider;
+
+        #[test]
+        fn hash_and_verify() {
+            let hash = super::hash_pw("secret").unwrap();
+            assert!(hash.starts_with("$argon2id$"));
+            assert_ne!(hash, super::hash_pw("secret").unwrap());
+            assert!(super::verify_pw(&hash, "secret").unwrap());
+            assert!(!super::verify_pw(&hash, "wrong").unwrap());
+        }
+
+        #[test]
+        fn legacy_hash_upgraded_on_login() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("legacy", ROLE_VIEWER).unwrap();
+            provider::user::update_password(&user.id, &super::legacy_hash_pw("secret")).unwrap();
+            let session = super::create_session(LoginIdentifier::Username("legacy".to_string()), "secret", &ClientInfo::default());
+            assert!(session.unwrap().is_some());
+            let upgraded = provider::user::by_id(&user.id).unwrap().password;
+            assert!(upgraded.starts_with("$argon2id$"));
+            let session = super::create_session(LoginIdentifier::Username("legacy".to_string()), "secret", &ClientInfo::default());
+            assert!(session.unwrap().is_some());
+        }
+
+        #[test]
+        fn login_by_username_or_email() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+            provider::user::update_password(&user.id, &super::hash_pw("secret").unwrap()).unwrap();
+            for login in vec!["user", "user@example.com"] {
+                let session = super::create_session(LoginIdentifier::from(login), "secret", &ClientInfo::default());
+                assert!(session.unwrap().is_some());
+            }
+            let wrong_password = super::create_session(LoginIdentifier::from("user"), "wrong", &ClientInfo::default());
+            assert!(wrong_password.unwrap().is_none());
+            let unknown_user = super::create_session(LoginIdentifier::from("nobody"), "secret", &ClientInfo::default());
+            assert!(unknown_user.unwrap().is_none());
+        }
+
+        #[test]
+        fn failed_login_audit_hides_login() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+            super::create_session(LoginIdentifier::from("user"), "wrong", &ClientInfo::default()).unwrap();
+            super::create_session(LoginIdentifier::from("my password"), "wrong", &ClientInfo::default()).unwrap();
+            let entries = provider::audit_log::query(&Default::default(), 0, 10).unwrap();
+            let targets: Vec<_> = entries.into_iter().filter_map(|e| e.target).collect();
+            assert_eq!(targets.len(), 2);
+            assert!(targets.contains(&format!("user:{}", user.id)));
+            assert!(targets.contains(&"login".to_string()));
+            assert!(targets.iter().all(|t| !t.contains("password")));
+        }
+
+        #[test]
+        fn expired_session_rejected() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+            let session = super::session_for(user.clone());
+            assert_eq!(super::user_for_session(&session).map(|u| u.id), Some(user.id));
+            provider::session::touch(&session, 0, crate::auth::now() - 1).unwrap();
+            assert!(super::user_for_session(&session).is_none());
+            assert!(provider::session::by_id(&session).unwrap().is_none());
+        }
+
+        #[test]
+        fn end_all_sessions() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+            let first = super::session_for(user.clone());
+            let second = super::session_for(user.clone());
+            assert_eq!(super::end_all_sessions(&LoginUser::from(user)).unwrap(), 2);
+            assert!(super::user_for_session(&first).is_none());
+            assert!(super::user_for_session(&second).is_none());
+        }
+    }
+
+}
+
+/// Tracks failed logins per account and IP address. After
+/// `Config.login_max_failures` failures the account or address is locked, starting
+/// with `Config.login_lockout` seconds and doubling with every further failure.
+pub mod throttle {
+
+    use crate::auth::login::{ClientInfo, LoginError};
+    use crate::auth::now;
+    use crate::database::model::{LoginFailure, User};
+    use crate::database::provider;
+
+    pub const KIND_ACCOUNT: &'static str = "account";
+    pub const KIND_IP: &'static str = "ip";
+    pub const KIND_TOTP: &'static str = "totp";
+    pub const KIND_SHARE: &'static str = "share";
+
+    /// Accounts are tracked by id, so logging in by username or email count the same.
+    /// Unknown logins are tracked by name and behave exactly like existing accounts.
+    pub fn subjects(user: Option<&User>, login: &str, client: &ClientInfo) -> Vec<(&'static str, String)> {
+        let account = match user {
+            Some(user) => account_subject(&user.id),
+            None => format!("name:{}", login.to_lowercase()),
+        };
+        let mut subjects = vec![(KIND_ACCOUNT, account)];
+        if let Some(ip) = &client.ip {
+            subjects.push((KIND_IP, ip.clone()));
+        }
+        subjects
+    }
+
+    /// Wrong second factor codes are counted separately from wrong passwords, so
+    /// knowing the password doesn't help guessing codes.
+    pub fn second_factor_subjects(user: &User, client: &ClientInfo) -> Vec<(&'static str, String)> {
+        let mut subjects = vec![(KIND_TOTP, account_subject(&user.id))];
+        if let Some(ip) = &client.ip {
+            subjects.push((KIND_IP, ip.clone()));
+        }
+        subjects
+    }
+
+    /// Passwords of share links are guessed without any account, so they are
+    /// counted per link instead.
+    pub fn share_subjects(link_id: &i32, client: &ClientInfo) -> Vec<(&'static str, String)> {
+        let mut subjects = vec![(KIND_SHARE, format!("link:{}", link_id))];
+        if let Some(ip) = &client.ip {
+            subjects.push((KIND_IP, ip.clone()));
+        }
+        subjects
+    }
+
+    /// Returns the time until which logins are refused, if any subject is locked.
+    pub fn locked_until(subjects: &[(&'static str, String)]) -> Result<Option<i64>, LoginError> {
+        let now = now();
+        let mut until = None;
+        for (kind, subject) in subjects {
+            if let Some(failure) = provider::login_failure::by_subject(kind, subject)? {
+                if failure.locked_until > now {
+                    until = until.max(Some(failure.locked_until));
+                }
+            }
+        }
+        Ok(until)
+    }
+
+    pub fn failed(subjects: &[(&'static str, String)]) -> Result<(), LoginError> {
+        let conf = crate::config::get();
+        let now = now();
+        for (kind, subject) in subjects {
+            let failures = match provider::login_failure::by_subject(kind, subject)? {
+                Some(f) if now - f.last_failure < conf.login_failure_window as i64 => f.failures + 1,
+                _ => 1,
+            };
+            let locked_until = if failures as u32 >= conf.login_max_failures {
+                now + lockout(failures as u32 - conf.login_max_failures, conf.login_lockout, conf.login_max_lockout)
+            } else {
+                0
+            };
+            provider::login_failure::save(&LoginFailure {
+                kind: kind.to_string(),
+                subject: subject.clone(),
+                failures,
+                last_failure: now,
+                locked_until,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn succeeded(subjects: &[(&'static str, String)]) -> Result<(), LoginError> {
+        for (kind, subject) in subjects {
+            provider::login_failure::delete(kind, subject)?;
+        }
+        Ok(())
+    }
+
+    /// Lifts the lock of an account, e.g. after an admin verified the owner.
+    pub fn unlock(user: &User) -> Result<(), LoginError> {
+        provider::login_failure::delete(KIND_ACCOUNT, &account_subject(&user.id))?;
+        provider::login_failure::delete(KIND_TOTP, &account_subject(&user.id))?;
+        Ok(())
+    }
+
+    fn account_subject(user_id: &i32) -> String {
+        format!("id:{}", user_id)
+    }
+
+    fn lockout(extra_failures: u32, base: u64, max: u64) -> i64 {
+        let factor = 1u64.checked_shl(extra_failures).unwrap_or(u64::max_value());
+        base.saturating_mul(factor).min(max) as i64
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::{ClientInfo, LoginError, LoginIdentifier};
+        use crate::auth::permission::ROLE_VIEWER;
+        use crate::database::provider;
+
+        fn login(login: &str, password: &str, ip: &str) -> Result<Option<String>, LoginError> {
+            let client = ClientInfo {
+                user_agent: None,
+                ip: Some(ip.to_string()),
+            };
+            crate::auth::login::create_session(LoginIdentifier::from(login), password, &client)
+        }
+
+        #[test]
+        fn lockout_doubles() {
+            assert_eq!(super::lockout(0, 60, 3600), 60);
+            assert_eq!(super::lockout(1, 60, 3600), 120);
+            assert_eq!(super::lockout(3, 60, 3600), 480);
+            assert_eq!(super::lockout(10, 60, 3600), 3600);
+            assert_eq!(super::lockout(100, 60, 3600), 3600);
+        }
+
+        #[test]
+        fn account_locked_after_failures() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+            provider::user::update_password(&user.id, &crate::auth::login::hash_pw("secret").unwrap()).unwrap();
+            let max = crate::config::get().login_max_failures;
+            for i in 0..max {
+                assert!(login("user", "wrong", &format!("10.0.0.{}", i)).unwrap().is_none());
+            }
+            match login("user@example.com", "secret", "10.0.1.1") {
+                Err(LoginError::Locked(seconds)) => assert!(seconds > 0),
+                _ => assert!(false),
+            }
+            super::unlock(&user).unwrap();
+            assert!(login("user", "secret", "10.0.1.1").unwrap().is_some());
+        }
+
+        #[test]
+        fn ip_locked_after_failures() {
+            crate::testing::setup_database();
+            let max = crate::config::get().login_max_failures;
+            for i in 0..max {
+                assert!(login(&format!("nobody{}", i), "wrong", "10.0.0.1").unwrap().is_none());
+            }
+            match login("somebody", "wrong", "10.0.0.1") {
+                Err(LoginError::Locked(_)) => {},
+                _ => assert!(false),
+            }
+            assert!(login("somebody", "wrong", "10.0.0.2").unwrap().is_none());
+        }
+    }
+}
+
+/// Time based one time passwords (RFC 6238) as optional second login factor.
+pub mod totp {
+
+    use crate::auth::{now, random_string, throttle};
+    use crate::auth::login::{ClientInfo, LoginError};
+    use crate::database::model::{RecoveryCode, User};
+    use crate::database::provider;
+    use base32::Alphabet;
+    use rocket::http::uri::Uri;
+    use sha::utils::{Digest, DigestExt};
+    use totp_lite::{totp_custom, Sha1, DEFAULT_STEP};
+
+    const ISSUER: &'static str = "Regal";
+    const SECRET_LENGTH: usize = 32;
+    const DIGITS: u32 = 6;
+    /// Time steps a code may be off, to allow for clock drift of the authenticator.
+    const ALLOWED_DRIFT: i64 = 1;
+    const RECOVERY_CODE_COUNT: usize = 10;
+    const RECOVERY_CODE_LENGTH: usize = 10;
+    const ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+    /// A new random base32 encoded secret.
+    pub fn generate_secret() -> String {
+        base32::encode(ALPHABET, random_string(SECRET_LENGTH).as_bytes())
+    }
+
+    /// The `otpauth://` URI authenticator apps read from the QR code.
+    pub fn provisioning_uri(secret: &str, username: &str) -> String {
+        format!("otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&digits={digits}&period={period}",
+                issuer = ISSUER,
+                user = Uri::percent_encode(username),
+                secret = secret,
+                digits = DIGITS,
+                period = DEFAULT_STEP)
+    }
+
+    pub fn qr_svg(uri: &str) -> Option<String> {
+        let code = qrcode::QrCode::new(uri.as_bytes()).ok()?;
+        Some(code.render::<qrcode::render::svg::Color>().min_dimensions(200, 200).build())
+    }
+
+    pub fn verify_code(secret: &str, code: &str, time: i64) -> bool {
+        matching_step(secret, code, time).is_some()
+    }
+
+    /// The time step `code` belongs to, if it is valid for `secret` around `time`.
+    fn matching_step(secret: &str, code: &str, time: i64) -> Option<i64> {
+        let key = base32::decode(ALPHABET, secret)?;
+        let code = code.trim();
+        (-ALLOWED_DRIFT..=ALLOWED_DRIFT)
+            .map(|drift| time + drift * DEFAULT_STEP as i64)
+            .filter(|t| *t >= 0)
+            .find(|t| totp_custom::<Sha1>(DEFAULT_STEP, DIGITS, &key, *t as u64).eq(code))
+            .map(|t| t / DEFAULT_STEP as i64)
+    }
+
+    /// Turns on TOTP once the user proved their authenticator produces valid codes
+    /// for `secret`. Returns the new recovery codes, or `None` for a wrong code.
+    /// An existing secret is never replaced, it has to be disabled with a valid code first.
+    pub fn enable(user: &User, secret: &str, code: &str) -> Result<Option<Vec<String>>, LoginError> {
+        if user.totp_secret.is_some() {
+            return Err(LoginError::SecondFactorEnabled);
+        }
+        let step = match matching_step(secret, code, now()) {
+            Some(step) => step,
+            None => return Ok(None),
+        };
+        provider::user::set_totp_secret(&user.id, Some(secret))?;
+        provider::user::accept_totp_step(&user.id, step)?;
+        Ok(Some(new_recovery_codes(&user.id)?))
+    }
+
+    pub fn disable(user: &User) -> Result<(), LoginError> {
+        provider::user::set_totp_secret(&user.id, None)?;
+        provider::recovery_code::delete_by_user(&user.id)?;
+        Ok(())
+    }
+
+    /// Replaces all recovery codes of the user. Only their hashes are stored, so
+    /// the returned codes have to be shown to the user right away.
+    pub fn new_recovery_codes(user_id: &i32) -> Result<Vec<String>, LoginError> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| random_string(RECOVERY_CODE_LENGTH).to_uppercase())
+            .collect();
+        let stored: Vec<RecoveryCode> = codes.iter()
+            .map(|code| RecoveryCode {
+                user_id: *user_id,
+                code_hash: hash_code(code),
+            })
+            .collect();
+        provider::recovery_code::replace_for_user(user_id, &stored)?;
+        Ok(codes)
+    }
+
+    /// Checks a TOTP or recovery code. Both can be used once.
+    pub fn verify(user: &User, code: &str) -> Result<bool, LoginError> {
+        let secret = match &user.totp_secret {
+            Some(secret) => secret,
+            None => return Ok(false),
+        };
+        if let Some(step) = matching_step(secret, code, now()) {
+            if provider::user::accept_totp_step(&user.id, step)? {
+                return Ok(true);
+            }
+        }
+        Ok(provider::recovery_code::consume(&user.id, &hash_code(code))?)
+    }
+
+    /// Like `verify`, but wrong codes count towards locking the second factor.
+    pub fn verify_throttled(user: &User, code: &str, client: &ClientInfo) -> Result<bool, LoginError> {
+        let subjects = throttle::second_factor_subjects(user, client);
+        if let Some(until) = throttle::locked_until(&subjects)? {
+            return Err(LoginError::Locked(until - now()));
+        }
+        let valid = verify(user, code)?;
+        if valid {
+            throttle::succeeded(&subjects)?;
+        } else {
+            throttle::failed(&subjects)?;
+        }
+        Ok(valid)
+    }
+
+    /// The second login step. Wrong codes count towards locking the account. Only a
+    /// valid code completes the login and clears the password failures as well.
+    pub fn verify_login(user: &User, code: &str, client: &ClientInfo) -> Result<bool, LoginError> {
+        let valid = verify_throttled(user, code, client)?;
+        if valid {
+            throttle::succeeded(&throttle::subjects(Some(user), &user.username, client))?;
+        }
+        Ok(valid)
+    }
+
+    fn hash_code(code: &str) -> String {
+        sha::sha1::Sha1::default().digest(code.trim().to_uppercase().as_bytes()).to_hex()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::{ClientInfo, LoginError, LoginIdentifier};
+        use crate::auth::permission::ROLE_ADMIN;
+        use crate::database::provider;
+
+        /// The SHA-1 secret from RFC 6238, appendix B.
+        const RFC_SECRET: &'static [u8] = b"12345678901234567890";
+
+        fn code_at(secret: &str, time: i64) -> String {
+            totp_lite::totp_custom::<totp_lite::Sha1>(totp_lite::DEFAULT_STEP, super::DIGITS,
+                                                      &base32::decode(super::ALPHABET, secret).unwrap(),
+                                                      time as u64)
+        }
+
+        #[test]
+        fn rfc_test_vectors() {
+            let secret = base32::encode(super::ALPHABET, RFC_SECRET);
+            assert!(super::verify_code(&secret, "287082", 59));
+            assert!(super::verify_code(&secret, "081804", 1111111109));
+            assert!(super::verify_code(&secret, "050471", 1111111111));
+            assert!(!super::verify_code(&secret, "287082", 1111111109));
+        }
+
+        #[test]
+        fn login_requires_second_factor() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+            provider::user::update_password(&user.id, &crate::auth::login::hash_pw("secret").unwrap()).unwrap();
+            let secret = super::generate_secret();
+            let code = code_at(&secret, crate::auth::now());
+            assert!(super::enable(&user, &secret, "000000x").unwrap().is_none());
+            let codes = super::enable(&user, &secret, &code).unwrap().unwrap();
+            assert_eq!(codes.len(), super::RECOVERY_CODE_COUNT);
+            match crate::auth::login::create_session(LoginIdentifier::from("admin"), "secret", &ClientInfo::default()) {
+                Err(LoginError::SecondFactorRequired(id)) => assert_eq!(id, user.id),
+                _ => assert!(false),
+            }
+            let user = provider::user::by_id(&user.id).unwrap();
+            let next = code_at(&secret, crate::auth::now() + totp_lite::DEFAULT_STEP as i64);
+            assert!(!super::verify_login(&user, &code, &ClientInfo::default()).unwrap());
+            assert!(super::verify_login(&user, &next, &ClientInfo::default()).unwrap());
+            assert!(!super::verify_login(&user, &next, &ClientInfo::default()).unwrap());
+            assert!(super::verify_login(&user, &codes[0].to_lowercase(), &ClientInfo::default()).unwrap());
+            assert!(!super::verify_login(&user, &codes[0], &ClientInfo::default()).unwrap());
+        }
+
+        #[test]
+        fn enabled_secret_is_not_replaced() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+            let secret = super::generate_secret();
+            provider::user::set_totp_secret(&user.id, Some(&secret)).unwrap();
+            let user = provider::user::by_id(&user.id).unwrap();
+            let other = super::generate_secret();
+            match super::enable(&user, &other, &code_at(&other, crate::auth::now())) {
+                Err(LoginError::SecondFactorEnabled) => {},
+                _ => assert!(false),
+            }
+            assert_eq!(provider::user::by_id(&user.id).unwrap().totp_secret, Some(secret));
+        }
+
+        #[test]
+        fn disable_checks_are_throttled() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+            let secret = super::generate_secret();
+            provider::user::set_totp_secret(&user.id, Some(&secret)).unwrap();
+            let user = provider::user::by_id(&user.id).unwrap();
+            for _ in 0..crate::config::get().login_max_failures {
+                assert!(!super::verify_throttled(&user, "000000x", &ClientInfo::default()).unwrap());
+            }
+            match super::verify_throttled(&user, &code_at(&secret, crate::auth::now()), &ClientInfo::default()) {
+                Err(LoginError::Locked(seconds)) => assert!(seconds > 0),
+                _ => assert!(false),
+            }
+        }
+
+        #[test]
+        fn password_does_not_reset_code_failures() {
+            crate::testing::setup_database();
+            let user = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+            provider::user::update_password(&user.id, &crate::auth::login::hash_pw("secret").unwrap()).unwrap();
+            provider::user::set_totp_secret(&user.id, Some(&super::generate_secret())).unwrap();
+            let user = provider::user::by_id(&user.id).unwrap();
+            let max = crate::config::get().login_max_failures;
+            for _ in 1..max {
+                assert!(!super::verify_login(&user, "000000x", &ClientInfo::default()).unwrap());
+            }
+            match crate::auth::login::create_session(LoginIdentifier::from("admin"), "secret", &ClientInfo::default()) {
+                Err(LoginError::SecondFactorRequired(_)) => {},
+                _ => assert!(false),
+            }
+            assert!(!super::verify_login(&user, "000000x", &ClientInfo::default()).unwrap());
+            match super::verify_login(&user, "000000x", &ClientInfo::default()) {
+                Err(LoginError::Locked(seconds)) => assert!(seconds > 0),
+                _ => assert!(false),
+            }
+        }
+    }
+}
+
+/// Personal API tokens for scripts, sent as `Authorization: Bearer <token>`.
+pub mod token {
+
+    use crate::auth::{now, random_string};
+    use crate::auth::login::{LoginError, LoginUser};
+    use crate::database::model::{ApiToken, NewApiToken};
+    use crate::database::provider;
+    use sha::utils::{Digest, DigestExt};
+
+    const TOKEN_PREFIX: &'static str = "rgl_";
+    const TOKEN_LENGTH: usize = 40;
+
+    /// Ordered from least to most powerful.
+    #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+    pub enum TokenScope {
+        /// View galleries and pictures.
+        Read,
+        /// Additionally create galleries and add content.
+        Upload,
+        /// Everything the user may do, including edits and deletes.
+        Full,
+    }
+
+    impl TokenScope {
+        pub fn name(&self) -> &'static str {
+            match self {
+                TokenScope::Read => "read",
+                TokenScope::Upload => "upload",
+                TokenScope::Full => "full",
+            }
+        }
+
+        pub fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "read" => Some(TokenScope::Read),
+                "upload" => Some(TokenScope::Upload),
+                "full" => Some(TokenScope::Full),
+                _ => None,
+            }
+        }
+
+        /// Whether this scope includes everything `needed` allows.
+        pub fn allows(&self, needed: TokenScope) -> bool {
+            *self >= needed
+        }
+    }
+
+    /// Creates a token for `user`. Returns the stored token and the token itself,
+    /// which can't be recovered later.
+    pub fn create(user: &LoginUser, name: &str, scope: TokenScope, lifetime: Option<i64>) -> Result<(ApiToken, String), LoginError> {
+        let token = format!("{}{}", TOKEN_PREFIX, random_string(TOKEN_LENGTH));
+        let now = now();
+        let stored = provider::api_token::insert(&NewApiToken {
+            user_id: user.id,
+            name: name.to_string(),
+            token_hash: hash_token(&token),
+            scope: scope.name().to_string(),
+            created: now,
+            expires: lifetime.map(|l| now + l),
+        })?;
+        Ok((stored, token))
+    }
+
+    /// Revokes one of the user's own tokens. Returns `false` if there is no such token.
+    pub fn revoke(user: &LoginUser, token_id: &i32) -> Result<bool, LoginError> {
+        match provider::api_token::by_id(token_id) {
+            Ok(token) if token.user_id == user.id => {
+                provider::api_token::delete(token_id)?;
+                Ok(true)
+            },
+            Ok(_) | Err(crate::database::Error::Diesel(diesel::NotFound)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn user_for_token(token: &str) -> Option<LoginUser> {
+        let stored = provider::api_token::by_hash(&hash_token(token)).ok()??;
+        let now = now();
+        if stored.expires.map(|e| e <= now).unwrap_or(false) {
+            return None;
+        }
+        let _ = provider::api_token::touch(&stored.id, now);
+        let user = provider::user::by_id(&stored.user_id).ok()?;
+        Some(LoginUser {
+            id: user.id,
+            role: user.role,
+            scope: TokenScope::from_name(&stored.scope)?,
+        })
+    }
+
+    fn hash_token(token: &str) -> String {
+        sha::sha1::Sha1::default().digest(token.as_bytes()).to_hex()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::LoginUser;
+        use crate::auth::permission::ROLE_VIEWER;
+        use crate::auth::token::TokenScope;
+        use crate::database::provider;
+
+        #[test]
+        fn scopes() {
+            assert!(TokenScope::Full.allows(TokenScope::Upload));
+            assert!(TokenScope::Upload.allows(TokenScope::Read));
+            assert!(!TokenScope::Read.allows(TokenScope::Upload));
+            assert!(!TokenScope::Upload.allows(TokenScope::Full));
+        }
+
+        #[test]
+        fn create_use_and_revoke() {
+            crate::testing::setup_database();
+            let user = LoginUser::from(crate::testing::save_user("user", ROLE_VIEWER).unwrap());
+            let (stored, token) = super::create(&user, "import", TokenScope::Upload, None).unwrap();
+            assert_ne!(stored.token_hash, token);
+            let token_user = super::user_for_token(&token).unwrap();
+            assert_eq!(token_user.id, user.id);
+            assert_eq!(token_user.scope, TokenScope::Upload);
+            assert!(provider::api_token::by_id(&stored.id).unwrap().last_used.is_some());
+            let other = LoginUser::from(crate::testing::save_user("other", ROLE_VIEWER).unwrap());
+            assert!(!super::revoke(&other, &stored.id).unwrap());
+            assert!(super::revoke(&user, &stored.id).unwrap());
+            assert!(super::user_for_token(&token).is_none());
+        }
+
+        #[test]
+        fn expired_token_rejected() {
+            crate::testing::setup_database();
+            let user = LoginUser::from(crate::testing::save_user("user", ROLE_VIEWER).unwrap());
+            let (_, token) = super::create(&user, "old", TokenScope::Read, Some(-1)).unwrap();
+            assert!(super::user_for_token(&token).is_none());
+        }
+    }
+}
+
+pub mod registration {
+
+    use crate::auth::{now, random_string};
+    use crate::auth::login::{hash_pw, LoginError};
+    use crate::auth::permission::ROLE_VIEWER;
+    use crate::database::model::{NewUser, PasswordReset};
+    use crate::database::provider;
+    use crate::database::provider::InsertStatus;
+    use crate::mail::MailError;
+    use sha::utils::{Digest, DigestExt};
+
+    /// Fits into `users.verification`.
+    const VERIFICATION_LENGTH: usize = 20;
+    const RESET_TOKEN_LENGTH: usize = 32;
+
+    #[derive(Debug)]
+    pub enum RegistrationError {
+        InvalidUsername,
+        AlreadyExists,
+        InvalidToken,
+        Login(LoginError),
+        Database(crate::database::Error),
+        Mail(MailError),
+    }
+
+    impl From<LoginError> for RegistrationError {
+        fn from(e: LoginError) -> Self {
+            RegistrationError::Login(e)
+        }
+    }
+
+    impl From<crate::database::Error> for RegistrationError {
+        fn from(e: crate::database::Error) -> Self {
+            RegistrationError::Database(e)
+        }
+    }
+
+    impl From<MailError> for RegistrationError {
+        fn from(e: MailError) -> Self {
+            RegistrationError::Mail(e)
+        }
+    }
+
+    /// Creates a user that cannot log in before verifying the email address.
+    /// Returns the verification token.
+    pub fn register(username: &str, email: &str, password: &str) -> Result<String, RegistrationError> {
+        if username.contains('@') {
+            return Err(RegistrationError::InvalidUsername);
+        }
+        let token = random_string(VERIFICATION_LENGTH);
+        let status = provider::user::insert(&NewUser {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: hash_pw(password)?,
+            verification: Some(token.clone()),
+            role: ROLE_VIEWER.to_string(),
+        })?;
+        match status {
+            InsertStatus::Ok => Ok(token),
+            InsertStatus::AlreadyExists => Err(RegistrationError::AlreadyExists),
+        }
+    }
+
+    /// Registers a user and sends the verification mail. If the mail can't be sent,
+    /// the account is deleted again so the username and email address stay free.
+    pub fn register_and_send(username: &str, email: &str, password: &str) -> Result<(), RegistrationError> {
+        let token = register(username, email, password)?;
+        if let Err(e) = send_verification(email, &token) {
+            if let Some(user) = provider::user::by_verification(&token)? {
+                provider::user::delete(&user)?;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn send_verification(email: &str, token: &str) -> Result<(), RegistrationError> {
+        let link = format!("{}/web/verify/{}", crate::config::get().external_url, token);
+        let body = format!("Welcome to Regal!\n\nPlease open the following link to verify your email address:\n{}\n", link);
+        crate::mail::send(email, "Verify your Regal account", &body)?;
+        Ok(())
+    }
+
+    pub fn verify(token: &str) -> Result<(), RegistrationError> {
+        let user = provider::user::by_verification(token)?.ok_or(RegistrationError::InvalidToken)?;
+        provider::user::set_verification(&user.id, None)?;
+        Ok(())
+    }
+
+    /// Creates a single use password reset token for the user with `email`.
+    /// Returns `None` if there is no such user.
+    pub fn request_password_reset(email: &str) -> Result<Option<String>, RegistrationError> {
+        let user = match provider::user::by_email(email)? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let token = random_string(RESET_TOKEN_LENGTH);
+        provider::password_reset::insert(&PasswordReset {
+            token_hash: hash_token(&token),
+            user_id: user.id,
+            expires: now() + crate::config::get().password_reset_lifetime as i64,
+        })?;
+        Ok(Some(token))
+    }
+
+    pub fn send_password_reset(email: &str, token: &str) -> Result<(), RegistrationError> {
+        let link = format!("{}/web/reset/{}", crate::config::get().external_url, token);
+        let body = format!("Someone requested a new password for your Regal account.\n\nOpen the following link to choose a new password:\n{}\n\nIf this was not you, you can ignore this mail.\n", link);
+        crate::mail::send(email, "Reset your Regal password", &body)?;
+        Ok(())
+    }
+
+    /// Sets a new password using a reset token. All reset tokens and sessions of
+    /// the user are invalidated afterwards. The token was sent by mail, so using
+    /// it verifies the email address as well.
+    pub fn reset_password(token: &str, password: &str) -> Result<(), RegistrationError> {
+        let reset = provider::password_reset::by_token_hash(&hash_token(token))?
+            .filter(|r| r.expires > now())
+            .ok_or(RegistrationError::InvalidToken)?;
+        provider::user::update_password(&reset.user_id, &hash_pw(password)?)?;
+        provider::user::set_verification(&reset.user_id, None)?;
+        provider::password_reset::delete_by_user(&reset.user_id)?;
+        provider::session::delete_by_user(&reset.user_id)?;
+        Ok(())
+    }
+
+    fn hash_token(token: &str) -> String {
+        sha::sha1::Sha1::default().digest(token.as_bytes()).to_hex()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::{ClientInfo, LoginError, LoginIdentifier};
+        use crate::auth::registration::RegistrationError;
+        use crate::database::provider;
+
+        fn login(username: &str, password: &str) -> Result<Option<String>, LoginError> {
+            crate::auth::login::create_session(LoginIdentifier::Username(username.to_string()), password, &ClientInfo::default())
+        }
+
+        #[test]
+        fn login_requires_verification() {
+            crate::testing::setup_database();
+            let token = super::register("new", "new@example.com", "secret").unwrap();
+            match login("new", "secret") {
+                Err(LoginError::NotVerified) => {},
+                _ => assert!(false),
+            }
+            super::verify(&token).unwrap();
+            assert!(login("new", "secret").unwrap().is_some());
+            match super::verify(&token) {
+                Err(RegistrationError::InvalidToken) => {},
+                _ => assert!(false),
+            }
+        }
+
+        #[test]
+        fn register_existing_user() {
+            crate::testing::setup_database();
+            super::register("new", "new@example.com", "secret").unwrap();
+            match super::register("new", "other@example.com", "secret") {
+                Err(RegistrationError::AlreadyExists) => {},
+                _ => assert!(false),
+            }
+        }
+
+        #[test]
+        fn username_without_at() {
+            crate::testing::setup_database();
+            match super::register("new@home", "new@example.com", "secret") {
+                Err(RegistrationError::InvalidUsername) => {},
+                _ => assert!(false),
+            }
+        }
+
+        #[test]
+        fn reset_password_once() {
+            crate::testing::setup_database();
+            let token = super::register("new", "new@example.com", "secret").unwrap();
+            super::verify(&token).unwrap();
+            assert!(super::request_password_reset("unknown@example.com").unwrap().is_none());
+            let reset = super::request_password_reset("new@example.com").unwrap().unwrap();
+            super::reset_password(&reset, "changed").unwrap();
+            assert!(login("new", "secret").unwrap().is_none());
+            assert!(login("new", "changed").unwrap().is_some());
+            match super::reset_password(&reset, "again") {
+                Err(RegistrationError::InvalidToken) => {},
+                _ => assert!(false),
+            }
+        }
+
+        #[test]
+        fn reset_password_verifies() {
+            crate::testing::setup_database();
+            super::register("new", "new@example.com", "secret").unwrap();
+            let reset = super::request_password_reset("new@example.com").unwrap().unwrap();
+            super::reset_password(&reset, "changed").unwrap();
+            assert!(login("new", "changed").unwrap().is_some());
+        }
+
+        #[test]
+        fn failed_mail_removes_registration() {
+            crate::testing::setup_database();
+            match super::register_and_send("new", "new@example.com", "secret") {
+                Err(RegistrationError::Mail(_)) => {},
+                _ => assert!(false),
+            }
+            assert!(provider::user::by_username("new").unwrap().is_none());
+        }
+
+        #[test]
+        fn expired_reset_rejected() {
+            crate::testing::setup_database();
+            super::register("new", "new@example.com", "secret").unwrap();
+            let reset = super::request_password_reset("new@example.com").unwrap().unwrap();
+            provider::password_reset::delete_expired(crate::auth::now() + 60 * 60 * 24).unwrap();
+            match super::reset_password(&reset, "changed") {
+                Err(RegistrationError::InvalidToken) => {},
+                _ => assert!(false),
+            }
+        }
+    }
 }
 
 pub mod permission {
 
+    use crate::auth::login::LoginUser;
+    use crate::auth::token::TokenScope;
+    use crate::database::model::Gallery;
+    use crate::database::provider;
+    use std::collections::HashSet;
+
+    pub const ROLE_ADMIN: &'static str = "admin";
+    pub const ROLE_EDITOR: &'static str = "editor";
+    pub const ROLE_VIEWER: &'static str = "viewer";
+
+    pub const VISIBILITY_INHERIT: &'static str = "inherit";
+    pub const VISIBILITY_PUBLIC: &'static str = "public";
+    pub const VISIBILITY_LOGGED_IN: &'static str = "logged_in";
+    pub const VISIBILITY_RESTRICTED: &'static str = "restricted";
+    pub const VISIBILITIES: [&'static str; 4] = [VISIBILITY_INHERIT, VISIBILITY_PUBLIC, VISIBILITY_LOGGED_IN, VISIBILITY_RESTRICTED];
+
+    #[derive(Debug)]
     pub enum PermissionError {
         Database(crate::database::Error),
     }
@@ -102,7 +1130,214 @@ pub mod permission {
         }
     }
 
-    pub fn can_show_pic(_user_id: &i32, _pic_id: &i32) -> Result<bool, PermissionError> {
-        Ok(true)
+    /// What a role may do. Which roles have which capability is stored in the
+    /// `role_permissions` table.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Capability {
+        /// See galleries that aren't public.
+        View,
+        /// Create galleries and add pictures.
+        Upload,
+        /// Change names, tags and other metadata.
+        EditMetadata,
+        Delete,
+        /// Manage users, roles and gallery access.
+        Administer,
+    }
+
+    impl Capability {
+        pub fn name(&self) -> &'static str {
+            match self {
+                Capability::View => "view",
+                Capability::Upload => "upload",
+                Capability::EditMetadata => "edit_metadata",
+                Capability::Delete => "delete",
+                Capability::Administer => "administer",
+            }
+        }
+
+        /// The token scope needed to use this capability with an API token.
+        fn scope(&self) -> TokenScope {
+            match self {
+                Capability::View => TokenScope::Read,
+                Capability::Upload => TokenScope::Upload,
+                _ => TokenScope::Full,
+            }
+        }
+    }
+
+    /// Whether the user's role has `capability` and the request's scope allows using it.
+    pub fn has_capability(user: &LoginUser, capability: Capability) -> Result<bool, PermissionError> {
+        if !user.scope.allows(capability.scope()) {
+            return Ok(false);
+        }
+        Ok(provider::role::has_permission(&user.role, capability.name())?)
+    }
+
+    pub fn can_view(user: &LoginUser) -> Result<bool, PermissionError> {
+        has_capability(user, Capability::View)
+    }
+
+    pub fn can_upload(user: &LoginUser) -> Result<bool, PermissionError> {
+        has_capability(user, Capability::Upload)
+    }
+
+    pub fn can_edit_metadata(user: &LoginUser) -> Result<bool, PermissionError> {
+        has_capability(user, Capability::EditMetadata)
+    }
+
+    pub fn can_delete(user: &LoginUser) -> Result<bool, PermissionError> {
+        has_capability(user, Capability::Delete)
+    }
+
+    pub fn can_administer(user: &LoginUser) -> Result<bool, PermissionError> {
+        has_capability(user, Capability::Administer)
+    }
+
+    pub fn can_show_pic(user: Option<&LoginUser>, pic_id: &i32) -> Result<bool, PermissionError> {
+        let picture = provider::picture::by_id(pic_id)?;
+        let gallery = provider::gallery::by_id(&picture.gallery_id)?;
+        can_show_gallery(user, &gallery)
+    }
+
+    /// Galleries with visibility `inherit` use the visibility of their closest
+    /// ancestor that sets one. Top level galleries inheriting are public.
+    /// Non-public galleries need the `view` capability, and users who may
+    /// administer can see every gallery. Restricted galleries can be granted to
+    /// single users or to whole groups.
+    pub fn can_show_gallery(user: Option<&LoginUser>, gallery: &Gallery) -> Result<bool, PermissionError> {
+        let user = match user {
+            Some(user) if can_administer(user)? => return Ok(true),
+            Some(user) if can_view(user)? => Some(user),
+            _ => None,
+        };
+        let mut current = gallery.clone();
+        let mut visited = HashSet::new();
+        loop {
+            if !visited.insert(current.id) {
+                // Broken hierarchy where a gallery is its own ancestor, nothing grants access.
+                return Ok(false);
+            }
+            match current.visibility.as_str() {
+                VISIBILITY_PUBLIC => return Ok(true),
+                VISIBILITY_LOGGED_IN => return Ok(user.is_some()),
+                VISIBILITY_RESTRICTED => return match user {
+                    Some(user) => Ok(provider::gallery_access::has_access(&current.id, &user.id)?
+                        || provider::gallery_group_access::has_access(&current.id, &user.id)?),
+                    None => Ok(false),
+                },
+                _ => match current.parent {
+                    Some(parent) => current = provider::gallery::by_id(&parent)?,
+                    None => return Ok(true),
+                },
+            }
+        }
+    }
+
+    pub fn can_create_gallery(user: &LoginUser) -> Result<bool, PermissionError> {
+        can_upload(user)
+    }
+
+    pub fn can_edit_gallery(user: &LoginUser, _gallery_id: &i32) -> Result<bool, PermissionError> {
+        can_edit_metadata(user)
+    }
+
+    /// Listing and revoking share links needs the capability creating them does,
+    /// so a read token can neither list nor revoke them.
+    pub fn can_manage_shares(user: &LoginUser) -> Result<bool, PermissionError> {
+        can_edit_metadata(user)
+    }
+
+    pub fn can_delete_gallery(user: &LoginUser, _gallery_id: &i32) -> Result<bool, PermissionError> {
+        can_delete(user)
+    }
+
+    /// Visibility and access lists decide who sees a gallery, so changing them
+    /// needs more than editing metadata.
+    pub fn can_manage_access(user: &LoginUser, _gallery_id: &i32) -> Result<bool, PermissionError> {
+        can_administer(user)
+    }
+
+    /// Tags are shared by all pictures, so changing them counts as editing metadata.
+    pub fn can_edit_tags(user: &LoginUser) -> Result<bool, PermissionError> {
+        can_edit_metadata(user)
+    }
+
+    /// Requires the full scope, so a leaked read or upload token can't create stronger ones.
+    pub fn can_manage_tokens(user: &LoginUser) -> Result<bool, PermissionError> {
+        Ok(user.scope.allows(TokenScope::Full))
+    }
+
+    /// Changing the second factor is guarded like token management, so only sessions
+    /// and full tokens can touch it.
+    pub fn can_secure_account(user: &LoginUser) -> Result<bool, PermissionError> {
+        can_manage_tokens(user)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::auth::login::LoginUser;
+        use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR, ROLE_VIEWER, VISIBILITY_LOGGED_IN};
+        use crate::auth::token::TokenScope;
+
+        fn user(role: &str) -> LoginUser {
+            LoginUser::from(crate::testing::save_user(role, role).unwrap())
+        }
+
+        #[test]
+        fn capabilities_by_role() {
+            crate::testing::setup_database();
+            let admin = user(ROLE_ADMIN);
+            let editor = user(ROLE_EDITOR);
+            let viewer = user(ROLE_VIEWER);
+            assert!(super::can_administer(&admin).unwrap());
+            assert!(!super::can_administer(&editor).unwrap());
+            assert!(super::can_upload(&editor).unwrap());
+            assert!(super::can_edit_metadata(&editor).unwrap());
+            assert!(super::can_delete(&editor).unwrap());
+            assert!(super::can_view(&viewer).unwrap());
+            assert!(!super::can_upload(&viewer).unwrap());
+            assert!(!super::can_delete(&viewer).unwrap());
+        }
+
+        #[test]
+        fn scope_limits_capabilities() {
+            crate::testing::setup_database();
+            let admin = LoginUser {
+                scope: TokenScope::Upload,
+                ..user(ROLE_ADMIN)
+            };
+            assert!(super::can_view(&admin).unwrap());
+            assert!(super::can_upload(&admin).unwrap());
+            assert!(!super::can_edit_metadata(&admin).unwrap());
+            assert!(!super::can_administer(&admin).unwrap());
+        }
+
+        #[test]
+        fn unknown_role_sees_public_only() {
+            crate::testing::setup_database();
+            let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+            crate::database::provider::gallery::set_visibility(&gallery.id, VISIBILITY_LOGGED_IN).unwrap();
+            let gallery = crate::database::provider::gallery::by_id(&gallery.id).unwrap();
+            let viewer = user(ROLE_VIEWER);
+            let nobody = LoginUser {
+                role: "nobody".to_string(),
+                ..viewer.clone()
+            };
+            assert!(super::can_show_gallery(Some(&viewer), &gallery).unwrap());
+            assert!(!super::can_show_gallery(Some(&nobody), &gallery).unwrap());
+        }
+
+        #[test]
+        fn parent_cycle_denied() {
+            crate::testing::setup_database();
+            let first = crate::testing::save_gallery_named("Gal1").unwrap();
+            let second = crate::testing::save_gallery_named("Gal2").unwrap();
+            crate::database::provider::gallery::update(&first.id, None, None, Some(Some(&second.id))).unwrap();
+            crate::database::provider::gallery::update(&second.id, None, None, Some(Some(&first.id))).unwrap();
+            let first = crate::database::provider::gallery::by_id(&first.id).unwrap();
+            assert!(!super::can_show_gallery(None, &first).unwrap());
+            assert!(super::can_show_gallery(Some(&user(ROLE_ADMIN)), &first).unwrap());
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,287 @@
+use crate::auth::login::LoginError;
+use crate::database::model::{GroupMember, NewUser, NewUserGroup, User, UserGroup};
+use crate::database::provider;
+use crate::database::provider::InsertStatus;
+use clap::ArgMatches;
+use colored::Colorize;
+use std::io::{BufRead, Write};
+
+/// Commands run instead of starting the server.
+pub enum Command {
+    User(UserCommand),
+    Group(GroupCommand),
+}
+
+pub enum UserCommand {
+    Add {
+        username: String,
+        email: String,
+        password: Option<String>,
+        role: String,
+    },
+    List,
+    Passwd {
+        username: String,
+        password: Option<String>,
+    },
+    Delete {
+        username: String,
+    },
+    SetRole {
+        username: String,
+        role: String,
+    },
+    Unlock {
+        username: String,
+    },
+}
+
+pub enum GroupCommand {
+    Add {
+        name: String,
+    },
+    List,
+    Delete {
+        name: String,
+    },
+    AddMember {
+        name: String,
+        username: String,
+    },
+    RemoveMember {
+        name: String,
+        username: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UserNotFound(String),
+    UserExists(String),
+    GroupNotFound(String),
+    GroupExists(String),
+    InvalidUsername(String),
+    EmptyPassword,
+    Io(std::io::Error),
+    Database(crate::database::Error),
+    Login(LoginError),
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(e: std::io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+impl From<crate::database::Error> for CliError {
+    fn from(e: crate::database::Error) -> Self {
+        CliError::Database(e)
+    }
+}
+
+impl From<LoginError> for CliError {
+    fn from(e: LoginError) -> Self {
+        CliError::Login(e)
+    }
+}
+
+pub type CliResult<T> = Result<T, CliError>;
+
+impl Command {
+    pub fn from_matches(a: &ArgMatches) -> Option<Self> {
+        match a.subcommand() {
+            ("user", Some(user)) => UserCommand::from_matches(user).map(Command::User),
+            ("group", Some(group)) => GroupCommand::from_matches(group).map(Command::Group),
+            _ => None,
+        }
+    }
+}
+
+impl UserCommand {
+    fn from_matches(a: &ArgMatches) -> Option<Self> {
+        let value = |m: &ArgMatches, name: &str| m.value_of(name).map(ToString::to_string);
+        match a.subcommand() {
+            ("add", Some(m)) => Some(UserCommand::Add {
+                username: value(m, "username")?,
+                email: value(m, "email")?,
+                password: value(m, "password"),
+                role: value(m, "role")?,
+            }),
+            ("list", Some(_)) => Some(UserCommand::List),
+            ("passwd", Some(m)) => Some(UserCommand::Passwd {
+                username: value(m, "username")?,
+                password: value(m, "password"),
+            }),
+            ("delete", Some(m)) => Some(UserCommand::Delete {
+                username: value(m, "username")?,
+            }),
+            ("set-role", Some(m)) => Some(UserCommand::SetRole {
+                username: value(m, "username")?,
+                role: value(m, "role")?,
+            }),
+            ("unlock", Some(m)) => Some(UserCommand::Unlock {
+                username: value(m, "username")?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl GroupCommand {
+    fn from_matches(a: &ArgMatches) -> Option<Self> {
+        let value = |m: &ArgMatches, name: &str| m.value_of(name).map(ToString::to_string);
+        match a.subcommand() {
+            ("add", Some(m)) => Some(GroupCommand::Add {
+                name: value(m, "name")?,
+            }),
+            ("list", Some(_)) => Some(GroupCommand::List),
+            ("delete", Some(m)) => Some(GroupCommand::Delete {
+                name: value(m, "name")?,
+            }),
+            ("add-member", Some(m)) => Some(GroupCommand::AddMember {
+                name: value(m, "name")?,
+                username: value(m, "username")?,
+            }),
+            ("remove-member", Some(m)) => Some(GroupCommand::RemoveMember {
+                name: value(m, "name")?,
+                username: value(m, "username")?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub fn run(command: &Command) -> CliResult<()> {
+    match command {
+        Command::User(user) => run_user(user),
+        Command::Group(group) => run_group(group),
+    }
+}
+
+fn run_user(command: &UserCommand) -> CliResult<()> {
+    use UserCommand::*;
+    match command {
+        Add { username, email, password, role } => {
+            if username.contains('@') {
+                return Err(CliError::InvalidUsername(username.clone()));
+            }
+            let password = read_password(password)?;
+            let status = provider::user::insert(&NewUser {
+                username: username.clone(),
+                email: email.clone(),
+                password: crate::auth::login::hash_pw(&password)?,
+                verification: None,
+                role: role.clone(),
+            })?;
+            match status {
+                InsertStatus::Ok => println!("{} [{}]", "Created user".green(), username.magenta()),
+                InsertStatus::AlreadyExists => return Err(CliError::UserExists(username.clone())),
+            }
+        },
+        List => {
+            for user in provider::user::all()? {
+                println!("{:>5} {} <{}> [{}]", user.id, user.username.magenta(), user.email, user.role.blue());
+            }
+        },
+        Passwd { username, password } => {
+            let user = find_user(username)?;
+            let password = read_password(password)?;
+            provider::user::update_password(&user.id, &crate::auth::login::hash_pw(&password)?)?;
+            provider::session::delete_by_user(&user.id)?;
+            println!("{} [{}]", "Changed password of user".green(), username.magenta());
+        },
+        Delete { username } => {
+            let user = find_user(username)?;
+            provider::user::delete(&user)?;
+            crate::audit::record_local(crate::audit::USER_DELETE, crate::audit::Target::User(user.id), Some(username.clone()));
+            println!("{} [{}]", "Deleted user".green(), username.magenta());
+        },
+        SetRole { username, role } => {
+            let user = find_user(username)?;
+            provider::user::set_role(&user.id, role)?;
+            crate::audit::record_local(crate::audit::ROLE_CHANGE, crate::audit::Target::User(user.id), Some(role.clone()));
+            println!("{} [{}] {}", "Changed role of user".green(), username.magenta(), role.blue());
+        },
+        Unlock { username } => {
+            let user = find_user(username)?;
+            crate::auth::throttle::unlock(&user)?;
+            println!("{} [{}]", "Unlocked user".green(), username.magenta());
+        },
+    }
+    Ok(())
+}
+
+fn run_group(command: &GroupCommand) -> CliResult<()> {
+    use GroupCommand::*;
+    match command {
+        Add { name } => {
+            match provider::user_group::insert(&NewUserGroup { name: name.clone() })? {
+                InsertStatus::Ok => println!("{} [{}]", "Created group".green(), name.magenta()),
+                InsertStatus::AlreadyExists => return Err(CliError::GroupExists(name.clone())),
+            }
+            let group = find_group(name)?;
+            crate::audit::record_local(crate::audit::GROUP_CREATE, crate::audit::Target::Group(group.id), Some(name.clone()));
+        },
+        List => {
+            for group in provider::user_group::all()? {
+                let members = provider::group_member::by_group(&group.id)?
+                    .into_iter()
+                    .map(|u| u.username)
+                    .collect::<Vec<_>>();
+                println!("{:>5} {} [{}]", group.id, group.name.magenta(), members.join(", ").blue());
+            }
+        },
+        Delete { name } => {
+            let group = find_group(name)?;
+            provider::user_group::delete(&group.id)?;
+            crate::audit::record_local(crate::audit::GROUP_DELETE, crate::audit::Target::Group(group.id), Some(name.clone()));
+            println!("{} [{}]", "Deleted group".green(), name.magenta());
+        },
+        AddMember { name, username } => {
+            let group = find_group(name)?;
+            let user = find_user(username)?;
+            provider::group_member::add(&GroupMember {
+                group_id: group.id,
+                user_id: user.id,
+            })?;
+            crate::audit::record_local(crate::audit::GROUP_MEMBER_ADD, crate::audit::Target::Group(group.id), Some(crate::audit::Target::User(user.id).key()));
+            println!("{} [{}] {}", "Added user to group".green(), username.magenta(), name.blue());
+        },
+        RemoveMember { name, username } => {
+            let group = find_group(name)?;
+            let user = find_user(username)?;
+            provider::group_member::remove(&group.id, &user.id)?;
+            crate::audit::record_local(crate::audit::GROUP_MEMBER_REMOVE, crate::audit::Target::Group(group.id), Some(crate::audit::Target::User(user.id).key()));
+            println!("{} [{}] {}", "Removed user from group".green(), username.magenta(), name.blue());
+        },
+    }
+    Ok(())
+}
+
+fn find_group(name: &str) -> CliResult<UserGroup> {
+    provider::user_group::by_name(name)?.ok_or(CliError::GroupNotFound(name.to_string()))
+}
+
+fn find_user(username: &str) -> CliResult<User> {
+    provider::user::by_username(username)?.ok_or(CliError::UserNotFound(username.to_string()))
+}
+
+/// Uses the password given on the command line or reads one line from stdin,
+/// so passwords can be piped in from scripts.
+fn read_password(password: &Option<String>) -> CliResult<String> {
+    let password = match password {
+        Some(password) => password.clone(),
+        None => {
+            eprint!("Password: ");
+            std::io::stderr().flush()?;
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(|c| c == '\n' || c == '\r').to_string()
+        },
+    };
+    if password.is_empty() {
+        Err(CliError::EmptyPassword)
+    } else {
+        Ok(password)
+    }
+}
\ No newline at end of file
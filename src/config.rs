@@ -1,4 +1,5 @@
 use std::path::Path;
+use crate::thumb::Fit;
 lazy_static! {
     static ref CONFIG: Config = {
         let mut config_file = "/etc/regal/scanner.json".to_string();
@@ -22,11 +23,148 @@ pub struct ScanDir {
     pub recursive: bool,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rendition {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub fit: Fit,
+    /// Generate this rendition for every picture on startup instead of on demand.
+    #[serde(default)]
+    pub pregenerate: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender address of all mails.
+    pub from: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub external_url: String,
     pub database_file: String,
     pub scan_dirs: Vec<ScanDir>,
+    #[serde(default = "default_watch_delay")]
+    pub watch_delay: u64,
+    #[serde(default = "default_scan_threads")]
+    pub scan_threads: usize,
+    #[serde(default = "default_renditions")]
+    pub renditions: Vec<Rendition>,
+    #[serde(default = "default_max_rendition_size")]
+    pub max_rendition_size: u32,
+    /// Bytes of resized pictures of sizes that aren't configured as rendition kept in memory.
+    #[serde(default = "default_resize_cache_size")]
+    pub resize_cache_size: usize,
+    /// Resized pictures rendered at the same time. Further requests are refused until one is done.
+    #[serde(default = "default_resize_max_renders")]
+    pub resize_max_renders: usize,
+    /// `Cache-Control` of pictures anonymous visitors can see. All others are sent as
+    /// `private, no-cache`.
+    #[serde(default = "default_cache_control")]
+    pub cache_control: String,
+    /// Seconds a session stays valid after it was last used.
+    #[serde(default = "default_session_lifetime")]
+    pub session_lifetime: u64,
+    /// Seconds between two runs of the expired session cleanup.
+    #[serde(default = "default_session_reap_interval")]
+    pub session_reap_interval: u64,
+    /// Allow visitors to create accounts. Requires `smtp` for verification mails.
+    #[serde(default)]
+    pub registration: bool,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    /// Seconds a password reset link stays valid.
+    #[serde(default = "default_password_reset_lifetime")]
+    pub password_reset_lifetime: u64,
+    /// Failed logins per account or IP address before it gets locked.
+    #[serde(default = "default_login_max_failures")]
+    pub login_max_failures: u32,
+    /// Seconds of the first lockout. Every further failure doubles it.
+    #[serde(default = "default_login_lockout")]
+    pub login_lockout: u64,
+    #[serde(default = "default_login_max_lockout")]
+    pub login_max_lockout: u64,
+    /// Seconds after the last failure until the failure count is reset.
+    #[serde(default = "default_login_failure_window")]
+    pub login_failure_window: u64,
+    /// Days audit log entries are kept. `0` keeps them forever.
+    #[serde(default = "default_audit_retention")]
+    pub audit_retention: u64,
+}
+
+fn default_watch_delay() -> u64 {
+    2
+}
+
+fn default_scan_threads() -> usize {
+    num_cpus::get()
+}
+
+fn default_renditions() -> Vec<Rendition> {
+    vec![
+        Rendition { name: "small".to_string(), width: 100, height: 100, fit: Fit::Contain, pregenerate: true },
+        Rendition { name: "medium".to_string(), width: 800, height: 800, fit: Fit::Contain, pregenerate: false },
+        Rendition { name: "large".to_string(), width: 1920, height: 1080, fit: Fit::Contain, pregenerate: false },
+    ]
+}
+
+fn default_max_rendition_size() -> u32 {
+    4096
+}
+
+fn default_resize_cache_size() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_resize_max_renders() -> usize {
+    num_cpus::get()
+}
+
+fn default_cache_control() -> String {
+    "public, max-age=86400".to_string()
+}
+
+fn default_session_lifetime() -> u64 {
+    60 * 60 * 24 * 14
+}
+
+fn default_session_reap_interval() -> u64 {
+    60 * 60
+}
+
+fn default_smtp_port() -> u16 {
+    25
+}
+
+fn default_password_reset_lifetime() -> u64 {
+    60 * 60
+}
+
+fn default_login_max_failures() -> u32 {
+    5
+}
+
+fn default_login_lockout() -> u64 {
+    60
+}
+
+fn default_login_max_lockout() -> u64 {
+    60 * 60
+}
+
+fn default_login_failure_window() -> u64 {
+    60 * 60
+}
+
+fn default_audit_retention() -> u64 {
+    365
 }
 
 impl Config {
@@ -44,6 +182,23 @@ impl Config {
                 external_url,
                 database_file,
                 scan_dirs,
+                watch_delay: default_watch_delay(),
+                scan_threads: default_scan_threads(),
+                renditions: default_renditions(),
+                max_rendition_size: default_max_rendition_size(),
+                resize_cache_size: default_resize_cache_size(),
+                resize_max_renders: default_resize_max_renders(),
+                cache_control: default_cache_control(),
+                session_lifetime: default_session_lifetime(),
+                session_reap_interval: default_session_reap_interval(),
+                registration: false,
+                smtp: None,
+                password_reset_lifetime: default_password_reset_lifetime(),
+                login_max_failures: default_login_max_failures(),
+                login_lockout: default_login_lockout(),
+                login_max_lockout: default_login_max_lockout(),
+                login_failure_window: default_login_failure_window(),
+                audit_retention: default_audit_retention(),
             })
         }
     }
@@ -51,4 +206,13 @@ impl Config {
 
 pub fn get() -> &'static Config {
     return &CONFIG;
+}
+
+/// Default configuration watching only `scan_dirs`, for tests that must not depend on
+/// the config file of the machine running them.
+#[cfg(test)]
+pub fn with_scan_dirs(scan_dirs: Vec<ScanDir>) -> Config {
+    let mut config = Config::from_file(String::new()).unwrap();
+    config.scan_dirs = scan_dirs;
+    config
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
-use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
-use diesel::SqliteConnection;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+use diesel::{Connection, SqliteConnection};
 use dotenv::dotenv;
 use crate::config::Config;
 use crate::config;
@@ -18,7 +18,9 @@ lazy_static! {
         dotenv().ok();
         let database_url = database_url();
         let manager = ConnectionManager::<SqliteConnection>::new(&database_url);
-        let pool = Pool::builder().build(manager).expect(&format!("Error opening database"));
+        let pool = Pool::builder()
+            .connection_customizer(Box::new(BusyTimeout))
+            .build(manager).expect(&format!("Error opening database"));
         {
             embedded_migrations::run(&pool.get().expect("Cannot get connection for migrations")).expect("Cannot run migrations!");
         }
@@ -26,6 +28,19 @@ lazy_static! {
     };
 }
 
+/// Lets connections wait for locks held by other threads (e.g. the scanner
+/// writing a batch) instead of failing right away with `database is locked`.
+#[derive(Debug)]
+struct BusyTimeout;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for BusyTimeout {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> std::result::Result<(), diesel::r2d2::Error> {
+        conn.execute("PRAGMA busy_timeout = 5000;")
+            .map(|_| ())
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Unknown(Option<String>),
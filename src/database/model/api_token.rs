@@ -0,0 +1,27 @@
+use crate::database::model::User;
+use crate::database::schema::api_tokens;
+
+/// A personal access token. Only the SHA-1 hash of the token is stored.
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug)]
+#[belongs_to(User)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scope: String,
+    pub created: i64,
+    pub expires: Option<i64>,
+    pub last_used: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="api_tokens"]
+pub struct NewApiToken {
+    pub user_id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scope: String,
+    pub created: i64,
+    pub expires: Option<i64>,
+}
\ No newline at end of file
@@ -0,0 +1,26 @@
+use crate::database::schema::audit_log;
+
+/// One recorded action. Entries without actor were done by the server itself,
+/// e.g. when scanning removes pictures that no longer exist on disk.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Debug)]
+#[table_name="audit_log"]
+pub struct AuditEntry {
+    pub id: i32,
+    pub created: i64,
+    pub actor_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub details: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name="audit_log"]
+pub struct NewAuditEntry {
+    pub created: i64,
+    pub actor_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub details: Option<String>,
+    pub ip: Option<String>,
+}
\ No newline at end of file
@@ -1,4 +1,5 @@
-use crate::database::schema::gallerys;
+use crate::database::model::{User, UserGroup};
+use crate::database::schema::{gallery_access, gallery_group_access, gallerys};
 
 #[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Serialize)]
 pub struct Gallery {
@@ -6,6 +7,7 @@ pub struct Gallery {
     pub name: String,
     pub directory: Option<String>,
     pub parent: Option<i32>,
+    pub visibility: String,
 }
 
 #[derive(Insertable)]
@@ -14,4 +16,24 @@ pub struct NewGallery {
     pub name: String,
     pub directory: Option<String>,
     pub parent: Option<i32>,
+}
+
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(Gallery)]
+#[belongs_to(User)]
+#[table_name="gallery_access"]
+#[primary_key(gallery_id, user_id)]
+pub struct GalleryAccess {
+    pub gallery_id: i32,
+    pub user_id: i32,
+}
+
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(Gallery)]
+#[belongs_to(UserGroup, foreign_key="group_id")]
+#[table_name="gallery_group_access"]
+#[primary_key(gallery_id, group_id)]
+pub struct GalleryGroupAccess {
+    pub gallery_id: i32,
+    pub group_id: i32,
 }
\ No newline at end of file
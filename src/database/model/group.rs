@@ -0,0 +1,25 @@
+use crate::database::model::User;
+use crate::database::schema::{group_members, user_groups};
+
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Serialize)]
+#[table_name="user_groups"]
+pub struct UserGroup {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable)]
+#[table_name="user_groups"]
+pub struct NewUserGroup {
+    pub name: String,
+}
+
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(UserGroup, foreign_key="group_id")]
+#[belongs_to(User)]
+#[table_name="group_members"]
+#[primary_key(group_id, user_id)]
+pub struct GroupMember {
+    pub group_id: i32,
+    pub user_id: i32,
+}
\ No newline at end of file
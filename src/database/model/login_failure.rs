@@ -0,0 +1,12 @@
+use crate::database::schema::login_failures;
+
+/// Failed logins for an account or IP address. Times are unix timestamps in seconds.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[primary_key(kind, subject)]
+pub struct LoginFailure {
+    pub kind: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure: i64,
+    pub locked_until: i64,
+}
\ No newline at end of file
@@ -0,0 +1,21 @@
+use crate::database::model::Picture;
+use crate::database::schema::picture_metadata;
+
+#[derive(Clone, Associations, Identifiable, Queryable, Insertable, PartialEq, Debug)]
+#[belongs_to(Picture)]
+#[primary_key(picture_id)]
+#[table_name="picture_metadata"]
+pub struct PictureMetadata {
+    pub picture_id: i32,
+    pub taken_at: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens: Option<String>,
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<i32>,
+    pub focal_length: Option<f64>,
+    pub orientation: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
\ No newline at end of file
@@ -1,11 +1,31 @@
+mod api_token;
+mod audit;
 mod gallery;
+mod group;
+mod login_failure;
+mod metadata;
+mod password_reset;
 mod picture;
+mod recovery_code;
+mod role;
+mod session;
+mod share_link;
 mod tag;
 mod thumb;
 mod user;
 
+pub use api_token::*;
+pub use audit::*;
 pub use gallery::*;
+pub use group::*;
+pub use login_failure::*;
+pub use metadata::*;
+pub use password_reset::*;
 pub use picture::*;
+pub use recovery_code::*;
+pub use role::*;
+pub use session::*;
+pub use share_link::*;
 pub use tag::*;
 pub use thumb::*;
 pub use user::*;
\ No newline at end of file
@@ -0,0 +1,12 @@
+use crate::database::model::User;
+use crate::database::schema::password_resets;
+
+/// A pending password reset. Only the SHA-1 hash of the token sent to the user is stored.
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(User)]
+#[primary_key(token_hash)]
+pub struct PasswordReset {
+    pub token_hash: String,
+    pub user_id: i32,
+    pub expires: i64,
+}
\ No newline at end of file
@@ -16,7 +16,7 @@ pub struct Picture {
     pub external_id: String,
 }
 
-#[derive(Insertable)]
+#[derive(Clone, Insertable)]
 #[table_name="pictures"]
 pub struct NewPicture {
     pub name: String,
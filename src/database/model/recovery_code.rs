@@ -0,0 +1,11 @@
+use crate::database::model::User;
+use crate::database::schema::recovery_codes;
+
+/// A single use code to log in without the second factor. Only the SHA-1 hash is stored.
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(User)]
+#[primary_key(user_id, code_hash)]
+pub struct RecoveryCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+use crate::database::schema::role_permissions;
+
+/// Grants a capability like `upload` or `administer` to every user with `role`.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[primary_key(role, permission)]
+pub struct RolePermission {
+    pub role: String,
+    pub permission: String,
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+use crate::database::model::User;
+use crate::database::schema::sessions;
+
+/// A login session. All times are unix timestamps in seconds.
+#[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
+#[belongs_to(User)]
+pub struct Session {
+    pub id: String,
+    pub user_id: i32,
+    pub created: i64,
+    pub last_seen: i64,
+    pub expires: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
\ No newline at end of file
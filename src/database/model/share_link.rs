@@ -0,0 +1,33 @@
+use crate::database::schema::share_links;
+
+/// Grants access to a gallery or picture without an account. Exactly one of
+/// `gallery_id` and `picture_id` is set. Only the SHA-1 hash of the token is stored.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Debug)]
+pub struct ShareLink {
+    pub id: i32,
+    pub token_hash: String,
+    pub gallery_id: Option<i32>,
+    pub picture_id: Option<i32>,
+    /// Whether sub galleries of `gallery_id` are shared too.
+    pub recursive: bool,
+    pub created_by: i32,
+    pub created: i64,
+    pub expires: Option<i64>,
+    /// Argon2 hash of the optional password.
+    pub password: Option<String>,
+    pub allow_download: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="share_links"]
+pub struct NewShareLink {
+    pub token_hash: String,
+    pub gallery_id: Option<i32>,
+    pub picture_id: Option<i32>,
+    pub recursive: bool,
+    pub created_by: i32,
+    pub created: i64,
+    pub expires: Option<i64>,
+    pub password: Option<String>,
+    pub allow_download: bool,
+}
\ No newline at end of file
@@ -1,11 +1,63 @@
 use crate::database::model::Picture;
 use crate::database::schema::{picture_tags, tags};
 
+/// The category of a tag, stored as `tags.tag_type`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TagType {
+    FreeForm,
+    Person,
+    Place,
+    Event,
+    Object,
+}
+
+impl TagType {
+    pub const ALL: [TagType; 5] = [TagType::FreeForm, TagType::Person, TagType::Place, TagType::Event, TagType::Object];
+
+    pub fn id(&self) -> i32 {
+        match self {
+            TagType::FreeForm => 1,
+            TagType::Person => 2,
+            TagType::Place => 3,
+            TagType::Event => 4,
+            TagType::Object => 5,
+        }
+    }
+
+    /// Unknown ids are treated as free-form tags.
+    pub fn from_id(id: i32) -> Self {
+        TagType::ALL.iter().cloned().find(|t| t.id() == id).unwrap_or(TagType::FreeForm)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TagType::FreeForm => "free",
+            TagType::Person => "person",
+            TagType::Place => "place",
+            TagType::Event => "event",
+            TagType::Object => "object",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        TagType::ALL.iter().cloned().find(|t| t.name() == name)
+    }
+}
+
+/// Tags form a tree through `parent`, e.g. `Europe/France/Paris`. Names are
+/// only unique among tags with the same parent.
 #[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug)]
 pub struct Tag {
     pub id: i32,
     pub tag_type: i32,
     pub name: String,
+    pub parent: Option<i32>,
+}
+
+impl Tag {
+    pub fn kind(&self) -> TagType {
+        TagType::from_id(self.tag_type)
+    }
 }
 
 #[derive(Insertable)]
@@ -13,12 +65,13 @@ pub struct Tag {
 pub struct NewTag {
     pub tag_type: i32,
     pub name: String,
+    pub parent: Option<i32>,
 }
 
 #[derive(Clone, Associations, Insertable, Queryable, PartialEq, Debug)]
 #[belongs_to(Picture)]
 #[belongs_to(Tag)]
 pub struct PictureTag {
-    tag_id: i32,
-    picture_id: i32,
+    pub tag_id: i32,
+    pub picture_id: i32,
 }
\ No newline at end of file
@@ -3,8 +3,12 @@ use crate::database::schema::thumbs;
 
 #[derive(Clone, Associations, Identifiable, Queryable, PartialEq, Debug, Insertable)]
 #[belongs_to(Picture)]
-#[primary_key(picture_id)]
+#[primary_key(picture_id, rendition)]
 pub struct Thumb {
     pub picture_id: i32,
+    pub rendition: String,
+    pub width: i32,
+    pub height: i32,
+    pub fit: String,
     pub picture_hash: String,
 }
@@ -6,7 +6,10 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub password: String,
-    pub verification: Option<String>
+    pub verification: Option<String>,
+    pub role: String,
+    pub totp_secret: Option<String>,
+    pub totp_last_step: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -15,5 +18,6 @@ pub struct NewUser {
     pub username: String,
     pub email: String,
     pub password: String,
-    pub verification: Option<String>
+    pub verification: Option<String>,
+    pub role: String,
 }
\ No newline at end of file
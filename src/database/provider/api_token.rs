@@ -0,0 +1,50 @@
+use crate::database::{connection, Result};
+use crate::database::model::{ApiToken, NewApiToken};
+use crate::database::schema::api_tokens::dsl::*;
+use crate::database::schema::api_tokens::table;
+
+use diesel::prelude::*;
+
+pub fn by_id(token_id: &i32) -> Result<ApiToken> {
+    let conn = connection()?;
+    Ok(api_tokens.find(token_id).first::<ApiToken>(&*conn)?)
+}
+
+pub fn by_hash(hash: &str) -> Result<Option<ApiToken>> {
+    let conn = connection()?;
+    let results = api_tokens.filter(token_hash.eq(hash)).limit(1).load::<ApiToken>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn by_user(u_id: &i32) -> Result<Vec<ApiToken>> {
+    let conn = connection()?;
+    Ok(api_tokens.filter(user_id.eq(u_id)).order(created.asc()).load::<ApiToken>(&*conn)?)
+}
+
+pub fn insert(token: &NewApiToken) -> Result<ApiToken> {
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(token)
+        .execute(&*conn)?;
+    Ok(api_tokens.filter(token_hash.eq(&token.token_hash)).first::<ApiToken>(&*conn)?)
+}
+
+pub fn touch(token_id: &i32, used: i64) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(api_tokens.find(token_id))
+        .set(last_used.eq(used))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete(token_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(api_tokens.find(token_id)).execute(&*conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(api_tokens).execute(&*conn).unwrap();
+}
\ No newline at end of file
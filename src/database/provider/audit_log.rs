@@ -0,0 +1,124 @@
+use crate::database::{connection, Result};
+use crate::database::model::{AuditEntry, NewAuditEntry};
+use crate::database::schema::audit_log::dsl::*;
+use crate::database::schema::audit_log::table;
+
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+/// Restricts queried entries. Unset fields match everything, times are
+/// inclusive unix timestamps.
+#[derive(Default)]
+pub struct AuditFilter<'a> {
+    pub actor_id: Option<i32>,
+    pub action: Option<&'a str>,
+    pub target: Option<&'a str>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+fn filtered<'a>(filter: &AuditFilter<'a>) -> crate::database::schema::audit_log::BoxedQuery<'a, Sqlite> {
+    let mut query = table.into_boxed();
+    if let Some(a_id) = filter.actor_id {
+        query = query.filter(actor_id.eq(a_id));
+    }
+    if let Some(a) = filter.action {
+        query = query.filter(action.eq(a));
+    }
+    if let Some(t) = filter.target {
+        query = query.filter(target.eq(t));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(created.ge(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(created.le(until));
+    }
+    query
+}
+
+pub fn insert(entry: &NewAuditEntry) -> Result<()> {
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(entry)
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Newest entries first.
+pub fn query(filter: &AuditFilter, offset: i64, limit: i64) -> Result<Vec<AuditEntry>> {
+    let conn = connection()?;
+    Ok(filtered(filter)
+        .order((created.desc(), id.desc()))
+        .offset(offset)
+        .limit(limit)
+        .load::<AuditEntry>(&*conn)?)
+}
+
+pub fn count(filter: &AuditFilter) -> Result<i64> {
+    let conn = connection()?;
+    Ok(filtered(filter).count().get_result(&*conn)?)
+}
+
+/// Deletes entries created before `time`. Returns the number of deleted entries.
+pub fn delete_before(time: i64) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(audit_log.filter(created.lt(time))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(audit_log).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::model::NewAuditEntry;
+    use crate::database::provider::audit_log::AuditFilter;
+    use crate::testing::setup_database;
+
+    fn entry(created: i64, action: &str) -> NewAuditEntry {
+        NewAuditEntry {
+            created,
+            actor_id: Some(1),
+            action: action.to_string(),
+            target: Some("gallery:1".to_string()),
+            details: None,
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn query_filtered() {
+        setup_database();
+        super::insert(&entry(10, "login")).unwrap();
+        super::insert(&entry(20, "gallery_delete")).unwrap();
+        super::insert(&entry(30, "login")).unwrap();
+        let logins = AuditFilter {
+            action: Some("login"),
+            ..AuditFilter::default()
+        };
+        assert_eq!(super::count(&logins).unwrap(), 2);
+        let loaded = super::query(&logins, 0, 10).unwrap();
+        assert_eq!(loaded.iter().map(|e| e.created).collect::<Vec<_>>(), vec![30, 10]);
+        let recent = AuditFilter {
+            since: Some(15),
+            ..AuditFilter::default()
+        };
+        assert_eq!(super::count(&recent).unwrap(), 2);
+        let page = super::query(&AuditFilter::default(), 1, 1).unwrap();
+        assert_eq!(page[0].created, 20);
+    }
+
+    #[test]
+    fn delete_before() {
+        setup_database();
+        super::insert(&entry(10, "login")).unwrap();
+        super::insert(&entry(20, "login")).unwrap();
+        super::insert(&entry(30, "login")).unwrap();
+        assert_eq!(super::delete_before(20).unwrap(), 1);
+        let left = super::query(&AuditFilter::default(), 0, 10).unwrap();
+        assert_eq!(left.iter().map(|e| e.created).collect::<Vec<_>>(), vec![30, 20]);
+    }
+}
\ No newline at end of file
@@ -67,6 +67,51 @@ pub fn insert(gal: &NewGallery) -> Result<InsertStatus> {
     }
 }
 
+/// Updates the fields of the gallery that are given and leaves the others untouched.
+/// A `parent_id` of `Some(None)` moves the gallery to the top level.
+pub fn update(gallery_id: &i32, new_name: Option<&str>, dir: Option<&str>, parent_id: Option<Option<&i32>>) -> Result<()> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        if let Some(new_name) = new_name {
+            diesel::update(gallerys.find(gallery_id))
+                .set(name.eq(new_name))
+                .execute(&*conn)?;
+        }
+        if let Some(dir) = dir {
+            diesel::update(gallerys.find(gallery_id))
+                .set(directory.eq(dir))
+                .execute(&*conn)?;
+        }
+        if let Some(parent_id) = parent_id {
+            diesel::update(gallerys.find(gallery_id))
+                .set(parent.eq(parent_id))
+                .execute(&*conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// Ids of `gallery_id` and all galleries below it.
+pub fn descendant_ids(gallery_id: &i32) -> Result<Vec<i32>> {
+    let conn = connection()?;
+    let mut ids = vec![*gallery_id];
+    let mut level = vec![*gallery_id];
+    while !level.is_empty() {
+        level = gallerys.filter(parent.eq_any(&level)).select(id).load::<i32>(&*conn)?;
+        level.retain(|i| !ids.contains(i));
+        ids.extend(&level);
+    }
+    Ok(ids)
+}
+
+pub fn set_visibility(gallery_id: &i32, vis: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(gallerys.find(gallery_id))
+        .set(visibility.eq(vis))
+        .execute(&*conn)?;
+    Ok(())
+}
+
 pub fn delete(gal: &Gallery) -> Result<()> {
     let conn = connection()?;
     diesel::delete(gallerys.find(&gal.id)).execute(&*conn)?;
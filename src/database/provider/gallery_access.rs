@@ -0,0 +1,64 @@
+use crate::database::{connection, Result};
+use crate::database::model::GalleryAccess;
+use crate::database::schema::gallery_access::dsl::*;
+use crate::database::schema::gallery_access::table;
+
+use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
+
+pub fn by_gallery(g_id: &i32) -> Result<Vec<GalleryAccess>> {
+    let conn = connection()?;
+    Ok(gallery_access.filter(gallery_id.eq(g_id)).load::<GalleryAccess>(&*conn)?)
+}
+
+pub fn has_access(g_id: &i32, u_id: &i32) -> Result<bool> {
+    let conn = connection()?;
+    let results = gallery_access.find((g_id, u_id)).limit(1).load::<GalleryAccess>(&*conn)?;
+    Ok(!results.is_empty())
+}
+
+pub fn grant(access: &GalleryAccess) -> Result<InsertStatus> {
+    if has_access(&access.gallery_id, &access.user_id)? {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(access)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+pub fn revoke(g_id: &i32, u_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(gallery_access.find((g_id, u_id))).execute(&*conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(gallery_access).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::ROLE_VIEWER;
+    use crate::database::model::GalleryAccess;
+    use crate::testing::setup_database;
+
+    #[test]
+    fn grant_and_revoke() {
+        setup_database();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        assert!(!super::has_access(&gallery.id, &user.id).unwrap());
+        super::grant(&GalleryAccess {
+            gallery_id: gallery.id,
+            user_id: user.id,
+        }).unwrap();
+        assert!(super::has_access(&gallery.id, &user.id).unwrap());
+        assert_eq!(super::by_gallery(&gallery.id).unwrap().len(), 1);
+        super::revoke(&gallery.id, &user.id).unwrap();
+        assert!(!super::has_access(&gallery.id, &user.id).unwrap());
+    }
+}
\ No newline at end of file
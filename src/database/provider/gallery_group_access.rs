@@ -0,0 +1,95 @@
+use crate::database::{connection, Result};
+use crate::database::model::{GalleryGroupAccess, UserGroup};
+use crate::database::schema::gallery_group_access::dsl::*;
+use crate::database::schema::gallery_group_access::table;
+use crate::database::schema::{group_members, user_groups};
+
+use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
+
+pub fn by_gallery(g_id: &i32) -> Result<Vec<UserGroup>> {
+    let conn = connection()?;
+    Ok(gallery_group_access.inner_join(user_groups::table)
+        .filter(gallery_id.eq(g_id))
+        .order(user_groups::name.asc())
+        .select(user_groups::all_columns)
+        .load::<UserGroup>(&*conn)?)
+}
+
+pub fn is_granted(g_id: &i32, gr_id: &i32) -> Result<bool> {
+    let conn = connection()?;
+    let results = gallery_group_access.find((g_id, gr_id)).limit(1).load::<GalleryGroupAccess>(&*conn)?;
+    Ok(!results.is_empty())
+}
+
+/// Whether the user belongs to any group that was granted access to the gallery.
+pub fn has_access(g_id: &i32, u_id: &i32) -> Result<bool> {
+    let conn = connection()?;
+    let members = group_members::table
+        .filter(group_members::user_id.eq(u_id))
+        .select(group_members::group_id);
+    let results = gallery_group_access
+        .filter(gallery_id.eq(g_id))
+        .filter(group_id.eq_any(members))
+        .limit(1)
+        .load::<GalleryGroupAccess>(&*conn)?;
+    Ok(!results.is_empty())
+}
+
+pub fn grant(access: &GalleryGroupAccess) -> Result<InsertStatus> {
+    if is_granted(&access.gallery_id, &access.group_id)? {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(access)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+pub fn revoke(g_id: &i32, gr_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(gallery_group_access.find((g_id, gr_id))).execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete_by_group(gr_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(gallery_group_access.filter(group_id.eq(gr_id))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(gallery_group_access).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::ROLE_VIEWER;
+    use crate::database::model::{GalleryGroupAccess, GroupMember};
+    use crate::testing::setup_database;
+
+    #[test]
+    fn members_have_access() {
+        setup_database();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let group = crate::testing::save_group("family").unwrap();
+        let member = crate::testing::save_user("member", ROLE_VIEWER).unwrap();
+        let outsider = crate::testing::save_user("outsider", ROLE_VIEWER).unwrap();
+        crate::database::provider::group_member::add(&GroupMember {
+            group_id: group.id,
+            user_id: member.id,
+        }).unwrap();
+        assert!(!super::has_access(&gallery.id, &member.id).unwrap());
+        super::grant(&GalleryGroupAccess {
+            gallery_id: gallery.id,
+            group_id: group.id,
+        }).unwrap();
+        assert!(super::has_access(&gallery.id, &member.id).unwrap());
+        assert!(!super::has_access(&gallery.id, &outsider.id).unwrap());
+        assert_eq!(super::by_gallery(&gallery.id).unwrap(), vec![group.clone()]);
+        super::revoke(&gallery.id, &group.id).unwrap();
+        assert!(!super::has_access(&gallery.id, &member.id).unwrap());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use crate::database::{connection, Result};
+use crate::database::model::{GroupMember, User, UserGroup};
+use crate::database::schema::group_members::dsl::*;
+use crate::database::schema::group_members::table;
+use crate::database::schema::{user_groups, users};
+
+use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
+
+pub fn by_group(g_id: &i32) -> Result<Vec<User>> {
+    let conn = connection()?;
+    Ok(group_members.inner_join(users::table)
+        .filter(group_id.eq(g_id))
+        .order(users::username.asc())
+        .select(users::all_columns)
+        .load::<User>(&*conn)?)
+}
+
+pub fn by_user(u_id: &i32) -> Result<Vec<UserGroup>> {
+    let conn = connection()?;
+    Ok(group_members.inner_join(user_groups::table)
+        .filter(user_id.eq(u_id))
+        .order(user_groups::name.asc())
+        .select(user_groups::all_columns)
+        .load::<UserGroup>(&*conn)?)
+}
+
+pub fn is_member(g_id: &i32, u_id: &i32) -> Result<bool> {
+    let conn = connection()?;
+    let results = group_members.find((g_id, u_id)).limit(1).load::<GroupMember>(&*conn)?;
+    Ok(!results.is_empty())
+}
+
+pub fn add(member: &GroupMember) -> Result<InsertStatus> {
+    if is_member(&member.group_id, &member.user_id)? {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(member)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+pub fn remove(g_id: &i32, u_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(group_members.find((g_id, u_id))).execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete_by_group(g_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(group_members.filter(group_id.eq(g_id))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(group_members).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::ROLE_VIEWER;
+    use crate::database::model::GroupMember;
+    use crate::testing::setup_database;
+
+    #[test]
+    fn add_and_remove() {
+        setup_database();
+        let group = crate::testing::save_group("family").unwrap();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        assert!(!super::is_member(&group.id, &user.id).unwrap());
+        super::add(&GroupMember {
+            group_id: group.id,
+            user_id: user.id,
+        }).unwrap();
+        assert!(super::is_member(&group.id, &user.id).unwrap());
+        assert_eq!(super::by_group(&group.id).unwrap(), vec![user.clone()]);
+        assert_eq!(super::by_user(&user.id).unwrap(), vec![group.clone()]);
+        super::remove(&group.id, &user.id).unwrap();
+        assert!(!super::is_member(&group.id, &user.id).unwrap());
+    }
+}
\ No newline at end of file
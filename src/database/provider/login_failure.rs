@@ -0,0 +1,38 @@
+use crate::database::{connection, Result};
+use crate::database::model::LoginFailure;
+use crate::database::schema::login_failures::dsl::*;
+use crate::database::schema::login_failures::table;
+
+use diesel::prelude::*;
+
+pub fn by_subject(failure_kind: &str, failure_subject: &str) -> Result<Option<LoginFailure>> {
+    let conn = connection()?;
+    let results = login_failures.find((failure_kind, failure_subject)).limit(1).load::<LoginFailure>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn save(failure: &LoginFailure) -> Result<()> {
+    let conn = connection()?;
+    diesel::replace_into(table)
+        .values(failure)
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete(failure_kind: &str, failure_subject: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(login_failures.find((failure_kind, failure_subject))).execute(&*conn)?;
+    Ok(())
+}
+
+/// Deletes entries that are neither locked nor recent enough to count anymore.
+pub fn delete_stale(now: i64, window: i64) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(login_failures.filter(locked_until.le(now)).filter(last_failure.le(now - window))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(login_failures).execute(&*conn).unwrap();
+}
\ No newline at end of file
@@ -0,0 +1,94 @@
+use crate::database::{connection, Result};
+use crate::database::model::PictureMetadata;
+use crate::database::schema::picture_metadata::dsl::*;
+use crate::database::schema::picture_metadata::table;
+
+use diesel::prelude::*;
+
+pub fn by_picture(p_id: &i32) -> Result<Option<PictureMetadata>> {
+    let conn = connection()?;
+    let results = picture_metadata.filter(picture_id.eq(p_id)).limit(1).load::<PictureMetadata>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+/// Inserts the metadata or replaces the existing metadata of the same picture.
+pub fn save(meta: &PictureMetadata) -> Result<()> {
+    let conn = connection()?;
+    diesel::replace_into(table)
+        .values(meta)
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn save_batch(metas: &[PictureMetadata]) -> Result<()> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        for meta in metas {
+            diesel::replace_into(table)
+                .values(meta)
+                .execute(&*conn)?;
+        }
+        Ok(())
+    })
+}
+
+pub fn delete_by_picture(p_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(picture_metadata.find(p_id)).execute(&*conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(picture_metadata).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{setup_database, save_gallery_named, save_picture};
+    use crate::database::model::{NewPicture, PictureMetadata};
+
+    fn metadata(picture_id: i32) -> PictureMetadata {
+        PictureMetadata {
+            picture_id,
+            taken_at: Some("2020:02:14 18:30:00".to_string()),
+            camera_make: Some("Canon".to_string()),
+            camera_model: Some("EOS 80D".to_string()),
+            lens: None,
+            exposure_time: Some("1/125".to_string()),
+            f_number: Some(4.0),
+            iso: Some(200),
+            focal_length: Some(35.0),
+            orientation: Some(1),
+            latitude: Some(48.8566),
+            longitude: Some(2.3522),
+        }
+    }
+
+    #[test]
+    fn save_by_picture() {
+        setup_database();
+        let gallery = save_gallery_named("Gal1").unwrap().id;
+        let picture = save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery,
+            format: "jpg".to_string(),
+            path: "/img1.jpg".to_string(),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: "img1.jpg".to_string(),
+        }).unwrap();
+        assert_eq!(super::by_picture(&picture.id).unwrap(), None);
+        let mut meta = metadata(picture.id);
+        super::save(&meta).unwrap();
+        assert_eq!(super::by_picture(&picture.id).unwrap(), Some(meta.clone()));
+        meta.iso = Some(400);
+        super::save(&meta).unwrap();
+        assert_eq!(super::by_picture(&picture.id).unwrap(), Some(meta));
+        super::delete_by_picture(&picture.id).unwrap();
+        assert_eq!(super::by_picture(&picture.id).unwrap(), None);
+    }
+}
\ No newline at end of file
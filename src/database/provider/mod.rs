@@ -1,8 +1,22 @@
+pub mod api_token;
+pub mod audit_log;
 pub mod gallery;
+pub mod gallery_access;
+pub mod gallery_group_access;
+pub mod group_member;
+pub mod login_failure;
+pub mod metadata;
+pub mod password_reset;
 pub mod picture;
+pub mod picture_tag;
+pub mod recovery_code;
+pub mod role;
+pub mod session;
+pub mod share_link;
 pub mod tag;
 pub mod thumb;
 pub mod user;
+pub mod user_group;
 
 pub enum InsertStatus {
     Ok,
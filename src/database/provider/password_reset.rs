@@ -0,0 +1,36 @@
+use crate::database::{connection, Result};
+use crate::database::model::PasswordReset;
+use crate::database::schema::password_resets::dsl::*;
+use crate::database::schema::password_resets::table;
+
+use diesel::prelude::*;
+
+pub fn by_token_hash(hash: &str) -> Result<Option<PasswordReset>> {
+    let conn = connection()?;
+    let results = password_resets.find(hash).limit(1).load::<PasswordReset>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn insert(reset: &PasswordReset) -> Result<()> {
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(reset)
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete_by_user(u_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(password_resets.filter(user_id.eq(u_id))).execute(&*conn)?)
+}
+
+pub fn delete_expired(now: i64) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(password_resets.filter(expires.le(now))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(password_resets).execute(&*conn).unwrap();
+}
\ No newline at end of file
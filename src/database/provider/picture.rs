@@ -1,10 +1,11 @@
 use crate::database::{connection, Result};
-use crate::database::model::{Picture, NewPicture};
+use crate::database::model::{Gallery, Picture, NewPicture};
 use crate::database::schema::pictures::dsl::*;
 use crate::database::schema::pictures::table;
 
 use diesel::prelude::*;
 use crate::database::provider::InsertStatus;
+use std::collections::HashSet;
 
 pub fn by_id(picture_id: &i32) -> Result<Picture> {
     let conn = connection()?;
@@ -22,7 +23,16 @@ pub fn by_gallery(g_id: &i32) -> Result<Vec<Picture>> {
     Ok(pictures.filter(gallery_id.eq(g_id)).load::<Picture>(&*conn)?)
 }
 
-pub fn find_thumb(g_id: &i32) -> Result<Option<Picture>> {
+/// First picture of the gallery or else of its sub galleries. Only sub galleries
+/// accepted by `include` are searched, e.g. the ones the viewer may see.
+pub fn find_thumb<F>(g_id: &i32, mut include: F) -> Result<Option<Picture>> where F: FnMut(&Gallery) -> bool {
+    let mut visited = HashSet::new();
+    visited.insert(*g_id);
+    find_thumb_in(g_id, &mut include, &mut visited)
+}
+
+/// Galleries are only searched once, so a broken hierarchy with a cycle ends the search.
+fn find_thumb_in<F>(g_id: &i32, include: &mut F, visited: &mut HashSet<i32>) -> Result<Option<Picture>> where F: FnMut(&Gallery) -> bool {
     use super::gallery;
     let imgs = by_gallery(&g_id)?;
     if let Some(img) = imgs.first() {
@@ -30,7 +40,10 @@ pub fn find_thumb(g_id: &i32) -> Result<Option<Picture>> {
     }
     let gals = gallery::by_parent(g_id)?;
     for gal in gals {
-        if let Some(img) = find_thumb(&gal.id)? {
+        if !visited.insert(gal.id) || !include(&gal) {
+            continue;
+        }
+        if let Some(img) = find_thumb_in(&gal.id, include, visited)? {
             return Ok(Some(img));
         }
     }
@@ -40,7 +53,7 @@ pub fn find_thumb(g_id: &i32) -> Result<Option<Picture>> {
 pub fn update(img_id: &i32, img: &NewPicture) -> Result<()> {
     let conn = connection()?;
     diesel::update(pictures.find(img_id))
-        .set((name.eq(&img.name), width.eq(&img.width), height.eq(&img.height), sha1.eq(&img.sha1)))
+        .set((name.eq(&img.name), width.eq(&img.width), height.eq(&img.height), format.eq(&img.format), sha1.eq(&img.sha1), filesize.eq(&img.filesize)))
         .execute(&*conn)?;
     Ok(())
 }
@@ -59,6 +72,23 @@ pub fn insert(img: &NewPicture) -> Result<InsertStatus> {
     }
 }
 
+/// Inserts all pictures in a single transaction. Pictures whose path or external id
+/// already exists are skipped, e.g. when the file watcher added them during a scan.
+/// Returns for every picture whether it was inserted.
+pub fn insert_batch(imgs: &[NewPicture]) -> Result<Vec<bool>> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        let mut inserted = Vec::with_capacity(imgs.len());
+        for img in imgs {
+            let count = diesel::insert_or_ignore_into(table)
+                .values(img)
+                .execute(&*conn)?;
+            inserted.push(count > 0);
+        }
+        Ok(inserted)
+    })
+}
+
 pub fn delete(img: &Picture) -> Result<()> {
     let conn = connection()?;
     diesel::delete(pictures.find(&img.id)).execute(&*conn)?;
@@ -74,7 +104,7 @@ pub fn clear_all() {
 
 #[cfg(test)]
 mod tests {
-    use crate::testing::{setup_database, save_picture, save_gallery_named};
+    use crate::testing::{setup_database, save_picture, save_gallery, save_gallery_named};
     use crate::database::model::{Gallery, Picture, NewPicture};
 
     #[test]
@@ -91,6 +121,7 @@ mod tests {
                 path: "/img1.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext1.png".to_string(),
             },
             NewPicture {
                 name: "Img2".to_string(),
@@ -101,6 +132,7 @@ mod tests {
                 path: "/img2.jpg".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext2.png".to_string(),
             }
         ].iter().map(|i| save_picture(i).unwrap()).collect();
         for img in pictures {
@@ -128,6 +160,7 @@ mod tests {
                 path: "/p1.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext3.png".to_string(),
             },
             NewPicture {
                 name: "Pic2".to_string(),
@@ -138,6 +171,7 @@ mod tests {
                 path: "/p2.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext4.png".to_string(),
             },
             NewPicture {
                 name: "Pic3".to_string(),
@@ -148,6 +182,7 @@ mod tests {
                 path: "/p3.jpg".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext5.png".to_string(),
             }
         ].iter().map(|i| save_picture(i).unwrap()).collect();
         let loaded = super::by_gallery(&right_gallery).unwrap();
@@ -156,4 +191,55 @@ mod tests {
             assert!(loaded.contains(img));
         }
     }
+
+    #[test]
+    fn insert_batch_skips_existing() {
+        setup_database();
+        let gallery = save_gallery_named("Gal1").unwrap().id;
+        let new_picture = |name: &str| NewPicture {
+            name: name.to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.clone(),
+            format: "png".to_string(),
+            path: format!("/{}.png", name),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: format!("{}.png", name),
+        };
+        let existing = save_picture(&new_picture("pic1")).unwrap();
+        assert_eq!(super::insert_batch(&[new_picture("pic1"), new_picture("pic2")]).unwrap(), vec![false, true]);
+        assert_eq!(super::by_path("/pic1.png").unwrap().unwrap(), existing);
+        assert!(super::by_path("/pic2.png").unwrap().is_some());
+        assert_eq!(super::by_gallery(&gallery).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn find_thumb() {
+        setup_database();
+        let sub_gallery = |gallery_name: &str, parent: &Gallery| save_gallery(&crate::database::model::NewGallery {
+            name: gallery_name.to_string(),
+            directory: None,
+            parent: Some(parent.id),
+        }).unwrap();
+        let root = save_gallery_named("Root").unwrap();
+        let child = sub_gallery("Child", &root);
+        let picture = save_picture(&NewPicture {
+            name: "pic1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: child.id,
+            format: "png".to_string(),
+            path: "/pic1.png".to_string(),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: "pic1.png".to_string(),
+        }).unwrap();
+        assert_eq!(super::find_thumb(&root.id, |_| true).unwrap(), Some(picture));
+        assert_eq!(super::find_thumb(&root.id, |g| g.id != child.id).unwrap(), None);
+        let cycle = sub_gallery("Cycle", &root);
+        let below = sub_gallery("Below", &cycle);
+        crate::database::provider::gallery::update(&cycle.id, None, None, Some(Some(&below.id))).unwrap();
+        assert_eq!(super::find_thumb(&cycle.id, |_| true).unwrap(), None);
+    }
 }
\ No newline at end of file
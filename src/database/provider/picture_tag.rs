@@ -0,0 +1,135 @@
+use crate::database::{connection, Result};
+use crate::database::model::{Picture, PictureTag, Tag};
+use crate::database::schema::picture_tags::dsl::*;
+use crate::database::schema::picture_tags::table;
+use crate::database::schema::{pictures, tags};
+
+use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
+
+pub fn pictures_by_tag(t_id: &i32) -> Result<Vec<Picture>> {
+    let conn = connection()?;
+    Ok(picture_tags.inner_join(pictures::table)
+        .filter(tag_id.eq(t_id))
+        .order(pictures::name.asc())
+        .select(pictures::all_columns)
+        .load::<Picture>(&*conn)?)
+}
+
+/// Pictures carrying any of the tags, each listed once.
+pub fn pictures_by_tags(t_ids: &[i32]) -> Result<Vec<Picture>> {
+    let conn = connection()?;
+    Ok(picture_tags.inner_join(pictures::table)
+        .filter(tag_id.eq_any(t_ids))
+        .order(pictures::name.asc())
+        .select(pictures::all_columns)
+        .distinct()
+        .load::<Picture>(&*conn)?)
+}
+
+pub fn tags_by_picture(p_id: &i32) -> Result<Vec<Tag>> {
+    let conn = connection()?;
+    Ok(picture_tags.inner_join(tags::table)
+        .filter(picture_id.eq(p_id))
+        .order(tags::name.asc())
+        .select(tags::all_columns)
+        .load::<Tag>(&*conn)?)
+}
+
+pub fn is_attached(t_id: &i32, p_id: &i32) -> Result<bool> {
+    let conn = connection()?;
+    let results = picture_tags.find((t_id, p_id)).limit(1).load::<PictureTag>(&*conn)?;
+    Ok(!results.is_empty())
+}
+
+pub fn attach(picture_tag: &PictureTag) -> Result<InsertStatus> {
+    if is_attached(&picture_tag.tag_id, &picture_tag.picture_id)? {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(picture_tag)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+/// Attaches the tag to all pictures in a single transaction. Returns the
+/// number of pictures that didn't have the tag yet.
+pub fn attach_all(t_id: &i32, p_ids: &[i32]) -> Result<usize> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        let mut attached = 0;
+        for p_id in p_ids {
+            attached += diesel::insert_or_ignore_into(table)
+                .values(&PictureTag {
+                    tag_id: *t_id,
+                    picture_id: *p_id,
+                })
+                .execute(&*conn)?;
+        }
+        Ok(attached)
+    })
+}
+
+pub fn detach(t_id: &i32, p_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(picture_tags.find((t_id, p_id))).execute(&*conn)?;
+    Ok(())
+}
+
+/// Returns the number of pictures that had the tag.
+pub fn detach_all(t_id: &i32, p_ids: &[i32]) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(picture_tags.filter(tag_id.eq(t_id)).filter(picture_id.eq_any(p_ids))).execute(&*conn)?)
+}
+
+pub fn delete_by_picture(p_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(picture_tags.filter(picture_id.eq(p_id))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(picture_tags).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::model::{NewPicture, Picture, PictureTag};
+    use crate::testing::setup_database;
+
+    fn save_picture(gallery_id: i32, name: &str) -> Picture {
+        crate::testing::save_picture(&NewPicture {
+            name: name.to_string(),
+            width: 0,
+            height: 0,
+            gallery_id,
+            format: "png".to_string(),
+            path: format!("/{}.png", name),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: format!("{}.png", name),
+        }).unwrap()
+    }
+
+    #[test]
+    fn attach_and_detach() {
+        setup_database();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let first = save_picture(gallery.id, "first");
+        let second = save_picture(gallery.id, "second");
+        let tag = crate::testing::save_tag_named("Tag1").unwrap();
+        super::attach(&PictureTag {
+            tag_id: tag.id,
+            picture_id: first.id,
+        }).unwrap();
+        assert!(super::is_attached(&tag.id, &first.id).unwrap());
+        assert_eq!(super::attach_all(&tag.id, &[first.id, second.id]).unwrap(), 1);
+        assert_eq!(super::pictures_by_tag(&tag.id).unwrap(), vec![first.clone(), second.clone()]);
+        assert_eq!(super::tags_by_picture(&second.id).unwrap(), vec![tag.clone()]);
+        super::detach(&tag.id, &first.id).unwrap();
+        assert_eq!(super::detach_all(&tag.id, &[first.id, second.id]).unwrap(), 1);
+        assert!(super::pictures_by_tag(&tag.id).unwrap().is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,42 @@
+use crate::database::{connection, Result};
+use crate::database::model::RecoveryCode;
+use crate::database::schema::recovery_codes::dsl::*;
+use crate::database::schema::recovery_codes::table;
+
+use diesel::prelude::*;
+
+pub fn by_user(u_id: &i32) -> Result<Vec<RecoveryCode>> {
+    let conn = connection()?;
+    Ok(recovery_codes.filter(user_id.eq(u_id)).load::<RecoveryCode>(&*conn)?)
+}
+
+/// Replaces all recovery codes of the user with `codes`.
+pub fn replace_for_user(u_id: &i32, codes: &[RecoveryCode]) -> Result<()> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        diesel::delete(recovery_codes.filter(user_id.eq(u_id))).execute(&*conn)?;
+        for code in codes {
+            diesel::insert_into(table)
+                .values(code)
+                .execute(&*conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// Deletes a code. Returns whether it existed, so every code can be used only once.
+pub fn consume(u_id: &i32, hash: &str) -> Result<bool> {
+    let conn = connection()?;
+    Ok(diesel::delete(recovery_codes.find((u_id, hash))).execute(&*conn)? > 0)
+}
+
+pub fn delete_by_user(u_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(recovery_codes.filter(user_id.eq(u_id))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(recovery_codes).execute(&*conn).unwrap();
+}
\ No newline at end of file
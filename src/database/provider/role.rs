@@ -0,0 +1,42 @@
+use crate::database::{connection, Result};
+use crate::database::schema::role_permissions::dsl::*;
+use crate::database::schema::roles;
+
+use diesel::prelude::*;
+
+pub fn all() -> Result<Vec<String>> {
+    let conn = connection()?;
+    Ok(roles::table.select(roles::name).order(roles::name.asc()).load::<String>(&*conn)?)
+}
+
+pub fn exists(role_name: &str) -> Result<bool> {
+    let conn = connection()?;
+    Ok(roles::table.find(role_name).select(roles::name).first::<String>(&*conn).optional()?.is_some())
+}
+
+pub fn permissions(role_name: &str) -> Result<Vec<String>> {
+    let conn = connection()?;
+    Ok(role_permissions.filter(role.eq(role_name)).select(permission).load::<String>(&*conn)?)
+}
+
+pub fn has_permission(role_name: &str, permission_name: &str) -> Result<bool> {
+    let conn = connection()?;
+    Ok(role_permissions.find((role_name, permission_name)).select(permission).first::<String>(&*conn).optional()?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR, ROLE_VIEWER};
+
+    #[test]
+    fn default_roles() {
+        crate::testing::setup_database();
+        assert_eq!(super::all().unwrap(), vec![ROLE_ADMIN, ROLE_EDITOR, ROLE_VIEWER]);
+        assert!(super::exists(ROLE_EDITOR).unwrap());
+        assert!(!super::exists("user").unwrap());
+        assert!(super::has_permission(ROLE_ADMIN, "administer").unwrap());
+        assert!(super::has_permission(ROLE_EDITOR, "edit_metadata").unwrap());
+        assert!(!super::has_permission(ROLE_EDITOR, "administer").unwrap());
+        assert_eq!(super::permissions(ROLE_VIEWER).unwrap(), vec!["view"]);
+    }
+}
\ No newline at end of file
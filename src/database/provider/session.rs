@@ -0,0 +1,109 @@
+use crate::database::{connection, Result};
+use crate::database::model::Session;
+use crate::database::schema::sessions::dsl::*;
+use crate::database::schema::sessions::table;
+
+use diesel::prelude::*;
+
+pub fn by_id(session_id: &str) -> Result<Option<Session>> {
+    let conn = connection()?;
+    let results = sessions.find(session_id).limit(1).load::<Session>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn by_user(u_id: &i32) -> Result<Vec<Session>> {
+    let conn = connection()?;
+    Ok(sessions.filter(user_id.eq(u_id)).load::<Session>(&*conn)?)
+}
+
+pub fn insert(session: &Session) -> Result<()> {
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(session)
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn touch(session_id: &str, seen: i64, expiry: i64) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(sessions.find(session_id))
+        .set((last_seen.eq(seen), expires.eq(expiry)))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete(session_id: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(sessions.find(session_id)).execute(&*conn)?;
+    Ok(())
+}
+
+pub fn delete_by_user(u_id: &i32) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(sessions.filter(user_id.eq(u_id))).execute(&*conn)?)
+}
+
+pub fn delete_expired(now: i64) -> Result<usize> {
+    let conn = connection()?;
+    Ok(diesel::delete(sessions.filter(expires.le(now))).execute(&*conn)?)
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(sessions).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::ROLE_VIEWER;
+    use crate::database::model::Session;
+    use crate::testing::setup_database;
+
+    fn session(id: &str, user_id: i32, expires: i64) -> Session {
+        Session {
+            id: id.to_string(),
+            user_id,
+            created: 0,
+            last_seen: 0,
+            expires,
+            user_agent: None,
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn insert_and_touch() {
+        setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        super::insert(&session("abc", user.id, 100)).unwrap();
+        super::touch("abc", 50, 150).unwrap();
+        let loaded = super::by_id("abc").unwrap().unwrap();
+        assert_eq!(loaded.last_seen, 50);
+        assert_eq!(loaded.expires, 150);
+    }
+
+    #[test]
+    fn delete_expired() {
+        setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        super::insert(&session("old", user.id, 100)).unwrap();
+        super::insert(&session("new", user.id, 300)).unwrap();
+        assert_eq!(super::delete_expired(200).unwrap(), 1);
+        assert!(super::by_id("old").unwrap().is_none());
+        assert!(super::by_id("new").unwrap().is_some());
+    }
+
+    #[test]
+    fn delete_by_user() {
+        setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let other = crate::testing::save_user("other", ROLE_VIEWER).unwrap();
+        super::insert(&session("a", user.id, 100)).unwrap();
+        super::insert(&session("b", user.id, 100)).unwrap();
+        super::insert(&session("c", other.id, 100)).unwrap();
+        assert_eq!(super::delete_by_user(&user.id).unwrap(), 2);
+        assert!(super::by_user(&user.id).unwrap().is_empty());
+        assert_eq!(super::by_user(&other.id).unwrap().len(), 1);
+    }
+}
\ No newline at end of file
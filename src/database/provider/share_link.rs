@@ -0,0 +1,42 @@
+use crate::database::{connection, Result};
+use crate::database::model::{NewShareLink, ShareLink};
+use crate::database::schema::share_links::dsl::*;
+use crate::database::schema::share_links::table;
+
+use diesel::prelude::*;
+
+pub fn by_id(link_id: &i32) -> Result<ShareLink> {
+    let conn = connection()?;
+    Ok(share_links.find(link_id).first::<ShareLink>(&*conn)?)
+}
+
+pub fn by_hash(hash: &str) -> Result<Option<ShareLink>> {
+    let conn = connection()?;
+    let results = share_links.filter(token_hash.eq(hash)).limit(1).load::<ShareLink>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn by_creator(user_id: &i32) -> Result<Vec<ShareLink>> {
+    let conn = connection()?;
+    Ok(share_links.filter(created_by.eq(user_id)).order(created.asc()).load::<ShareLink>(&*conn)?)
+}
+
+pub fn insert(link: &NewShareLink) -> Result<ShareLink> {
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(link)
+        .execute(&*conn)?;
+    Ok(share_links.filter(token_hash.eq(&link.token_hash)).first::<ShareLink>(&*conn)?)
+}
+
+pub fn delete(link_id: &i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::delete(share_links.find(link_id)).execute(&*conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(share_links).execute(&*conn).unwrap();
+}
\ No newline at end of file
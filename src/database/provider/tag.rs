@@ -2,21 +2,105 @@ use crate::database::{connection, Result};
 use crate::database::model::{Tag, NewTag};
 use crate::database::schema::tags::dsl::*;
 use crate::database::schema::tags::table;
+use crate::database::schema::picture_tags;
 
 use diesel::prelude::*;
+use std::collections::HashSet;
 
 pub fn all() -> Result<Vec<Tag>> {
     let conn = connection()?;
-    let results = tags.load::<Tag>(&*conn)?;
+    let results = tags.order(name.asc()).load::<Tag>(&*conn)?;
     Ok(results)
 }
 
+pub fn by_id(tag_id: &i32) -> Result<Tag> {
+    let conn = connection()?;
+    Ok(tags.find(tag_id).first::<Tag>(&*conn)?)
+}
+
+/// Finds any tag called `tag_name`, regardless of its parent.
 pub fn by_name(tag_name: &str) -> Result<Option<Tag>> {
     let conn = connection()?;
     let results = tags.filter(name.eq(tag_name)).limit(1).load::<Tag>(&*conn)?;
     Ok(results.first().map(|a| a.clone()))
 }
 
+/// Finds the tag called `tag_name` below `parent_id`, or among top level tags.
+pub fn by_name_in(tag_name: &str, parent_id: Option<&i32>) -> Result<Option<Tag>> {
+    let conn = connection()?;
+    let query = tags.filter(name.eq(tag_name)).into_boxed();
+    let query = match parent_id {
+        Some(parent_id) => query.filter(parent.eq(parent_id)),
+        None => query.filter(parent.is_null()),
+    };
+    Ok(query.first::<Tag>(&*conn).optional()?)
+}
+
+/// Resolves a path like `Europe/France/Paris`, starting at the top level.
+pub fn by_path(path: &str) -> Result<Option<Tag>> {
+    let mut current: Option<Tag> = None;
+    for segment in path.split('/').map(str::trim).filter(|s| !s.is_empty()) {
+        current = match by_name_in(segment, current.as_ref().map(|t| &t.id))? {
+            Some(tag) => Some(tag),
+            None => return Ok(None),
+        };
+    }
+    Ok(current)
+}
+
+pub fn by_type(type_id: &i32) -> Result<Vec<Tag>> {
+    let conn = connection()?;
+    Ok(tags.filter(tag_type.eq(type_id)).order(name.asc()).load::<Tag>(&*conn)?)
+}
+
+pub fn top_level() -> Result<Vec<Tag>> {
+    let conn = connection()?;
+    Ok(tags.filter(parent.is_null()).order(name.asc()).load::<Tag>(&*conn)?)
+}
+
+pub fn children(tag_id: &i32) -> Result<Vec<Tag>> {
+    let conn = connection()?;
+    Ok(tags.filter(parent.eq(tag_id)).order(name.asc()).load::<Tag>(&*conn)?)
+}
+
+/// The tag and all tags below it, level by level.
+pub fn descendant_ids(tag_id: &i32) -> Result<Vec<i32>> {
+    let conn = connection()?;
+    let mut ids = vec![*tag_id];
+    let mut level = vec![*tag_id];
+    while !level.is_empty() {
+        level = tags.filter(parent.eq_any(&level)).select(id).load::<i32>(&*conn)?;
+        level.retain(|i| !ids.contains(i));
+        ids.extend(&level);
+    }
+    Ok(ids)
+}
+
+/// The tags above `tag`, starting at the top level. Stops at the first tag
+/// seen twice if the hierarchy contains a cycle.
+pub fn ancestors(tag: &Tag) -> Result<Vec<Tag>> {
+    let mut ancestors = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert(tag.id);
+    let mut current = tag.parent;
+    while let Some(parent_id) = current {
+        if !visited.insert(parent_id) {
+            break;
+        }
+        let parent_tag = by_id(&parent_id)?;
+        current = parent_tag.parent;
+        ancestors.insert(0, parent_tag);
+    }
+    Ok(ancestors)
+}
+
+/// The names of all ancestors and the tag itself, joined by `/`.
+pub fn path(tag: &Tag) -> Result<String> {
+    let mut names = ancestors(tag)?.into_iter().map(|t| t.name).collect::<Vec<_>>();
+    names.push(tag.name.clone());
+    Ok(names.join("/"))
+}
+
 pub fn insert(tag: &NewTag) -> Result<()> {
     let conn = connection()?;
     diesel::insert_into(table)
@@ -25,6 +109,71 @@ pub fn insert(tag: &NewTag) -> Result<()> {
     Ok(())
 }
 
+pub fn set_type(tag_id: &i32, type_id: i32) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(tags.find(tag_id))
+        .set(tag_type.eq(type_id))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Moves the tag with all its descendants. Callers have to make sure the new
+/// parent isn't one of the descendants.
+pub fn set_parent(tag_id: &i32, parent_id: Option<i32>) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(tags.find(tag_id))
+        .set(parent.eq(parent_id))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn rename(tag_id: &i32, new_name: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(tags.find(tag_id))
+        .set(name.eq(new_name))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Moves all pictures and child tags of `source` to `target` and deletes `source`.
+/// Callers have to make sure no child of `source` has the same name as a child
+/// of `target`.
+pub fn merge(source: &i32, target: &i32) -> Result<()> {
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        let picture_ids = picture_tags::table
+            .filter(picture_tags::tag_id.eq(source))
+            .select(picture_tags::picture_id)
+            .load::<i32>(&*conn)?;
+        for picture_id in picture_ids {
+            diesel::insert_or_ignore_into(picture_tags::table)
+                .values((picture_tags::tag_id.eq(target), picture_tags::picture_id.eq(picture_id)))
+                .execute(&*conn)?;
+        }
+        diesel::delete(picture_tags::table.filter(picture_tags::tag_id.eq(source))).execute(&*conn)?;
+        diesel::update(tags.filter(parent.eq(source)))
+            .set(parent.eq(target))
+            .execute(&*conn)?;
+        diesel::delete(tags.find(source)).execute(&*conn)?;
+        Ok(())
+    })
+}
+
+/// Deletes the tag and detaches it from all pictures. Its children move up to
+/// the tag's parent, so callers have to check their names are still free there.
+pub fn delete(tag_id: &i32) -> Result<()> {
+    let tag = by_id(tag_id)?;
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        diesel::delete(picture_tags::table.filter(picture_tags::tag_id.eq(tag_id))).execute(&*conn)?;
+        diesel::update(tags.filter(parent.eq(tag_id)))
+            .set(parent.eq(tag.parent))
+            .execute(&*conn)?;
+        diesel::delete(tags.find(tag_id)).execute(&*conn)?;
+        Ok(())
+    })
+}
+
 #[cfg(test)]
 pub fn clear_all() {
     let conn = connection().unwrap();
@@ -34,7 +183,7 @@ pub fn clear_all() {
 #[cfg(test)]
 mod tests {
     use crate::testing::{setup_database, save_tag_named};
-    use crate::database::model::Tag;
+    use crate::database::model::{NewTag, PictureTag, Tag, TagType};
 
     #[test]
     fn all() {
@@ -60,4 +209,77 @@ mod tests {
         let loaded = super::by_name("Tag2").unwrap().unwrap();
         assert_eq!(loaded, tags[1])
     }
+
+    #[test]
+    fn rename() {
+        setup_database();
+        let tag = save_tag_named("Tag1").unwrap();
+        super::rename(&tag.id, "Renamed").unwrap();
+        assert_eq!(super::by_id(&tag.id).unwrap().name, "Renamed");
+        assert!(super::by_name("Tag1").unwrap().is_none());
+    }
+
+    #[test]
+    fn merge() {
+        setup_database();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = crate::testing::save_picture(&crate::database::model::NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.id,
+            format: "png".to_string(),
+            path: "/1.png".to_string(),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        let source = save_tag_named("Paris").unwrap();
+        let target = save_tag_named("paris").unwrap();
+        crate::database::provider::picture_tag::attach(&PictureTag {
+            tag_id: source.id,
+            picture_id: picture.id,
+        }).unwrap();
+        super::merge(&source.id, &target.id).unwrap();
+        assert!(super::by_name("Paris").unwrap().is_none());
+        assert_eq!(crate::database::provider::picture_tag::tags_by_picture(&picture.id).unwrap(), vec![target]);
+    }
+
+    #[test]
+    fn hierarchy() {
+        setup_database();
+        let europe = save_tag_named("Europe").unwrap();
+        let save_child = |tag_name: &str, parent: &Tag| crate::testing::save_tag(&NewTag {
+            tag_type: TagType::Place.id(),
+            name: tag_name.to_string(),
+            parent: Some(parent.id),
+        }).unwrap();
+        let france = save_child("France", &europe);
+        let paris = save_child("Paris", &france);
+        let other = save_tag_named("Paris").unwrap();
+        assert_eq!(super::by_path("Europe/France/Paris").unwrap(), Some(paris.clone()));
+        assert_eq!(super::by_path("Paris").unwrap(), Some(other.clone()));
+        assert!(super::by_path("Europe/Paris").unwrap().is_none());
+        assert_eq!(super::path(&paris).unwrap(), "Europe/France/Paris");
+        assert_eq!(super::descendant_ids(&europe.id).unwrap(), vec![europe.id, france.id, paris.id]);
+        assert_eq!(super::top_level().unwrap(), vec![europe.clone(), other]);
+        assert_eq!(paris.kind(), TagType::Place);
+        super::delete(&france.id).unwrap();
+        assert_eq!(super::children(&europe.id).unwrap().iter().map(|t| t.id).collect::<Vec<_>>(), vec![paris.id]);
+    }
+
+    #[test]
+    fn ancestors_stop_at_cycle() {
+        setup_database();
+        let first = save_tag_named("First").unwrap();
+        let second = crate::testing::save_tag(&NewTag {
+            tag_type: TagType::Place.id(),
+            name: "Second".to_string(),
+            parent: Some(first.id),
+        }).unwrap();
+        super::set_parent(&first.id, Some(second.id)).unwrap();
+        let second = super::by_id(&second.id).unwrap();
+        assert_eq!(super::ancestors(&second).unwrap().iter().map(|t| t.id).collect::<Vec<_>>(), vec![first.id]);
+        assert_eq!(super::path(&second).unwrap(), "First/Second");
+    }
 }
\ No newline at end of file
@@ -12,23 +12,31 @@ pub fn all() -> Result<Vec<Thumb>> {
     Ok(results)
 }
 
-pub fn by_picture(p_id: &i32) -> Result<Option<Thumb>> {
+pub fn by_picture(p_id: &i32) -> Result<Vec<Thumb>> {
     let conn = connection()?;
-    let results = thumbs.filter(picture_id.eq(p_id)).limit(1).load::<Thumb>(&*conn)?;
+    Ok(thumbs.filter(picture_id.eq(p_id)).load::<Thumb>(&*conn)?)
+}
+
+pub fn by_picture_and_rendition(p_id: &i32, rendition_name: &str) -> Result<Option<Thumb>> {
+    let conn = connection()?;
+    let results = thumbs.filter(picture_id.eq(p_id))
+        .filter(rendition.eq(rendition_name))
+        .limit(1)
+        .load::<Thumb>(&*conn)?;
     Ok(results.first().map(|a| a.clone()))
 }
 
-pub fn update(t_id: &i32, thmb: &Thumb) -> Result<()> {
+pub fn update(thmb: &Thumb) -> Result<()> {
     let conn = connection()?;
-    diesel::update(thumbs.find(t_id))
-        .set(picture_hash.eq(&thmb.picture_hash))
+    diesel::update(thumbs.find((&thmb.picture_id, &thmb.rendition)))
+        .set((width.eq(&thmb.width), height.eq(&thmb.height), fit.eq(&thmb.fit), picture_hash.eq(&thmb.picture_hash)))
         .execute(&*conn)?;
     Ok(())
 }
 
 pub fn insert(thmb: &Thumb) -> Result<InsertStatus> {
     let conn = connection()?;
-    if by_picture(&thmb.picture_id)?.is_some() {
+    if by_picture_and_rendition(&thmb.picture_id, &thmb.rendition)?.is_some() {
         return Ok(InsertStatus::AlreadyExists)
     }
     diesel::insert_into(table)
@@ -37,9 +45,68 @@ pub fn insert(thmb: &Thumb) -> Result<InsertStatus> {
     Ok(InsertStatus::Ok)
 }
 
+/// Inserts the thumb or updates the existing one for the same picture and rendition.
+pub fn save(thmb: &Thumb) -> Result<()> {
+    match insert(thmb)? {
+        InsertStatus::Ok => Ok(()),
+        InsertStatus::AlreadyExists => update(thmb),
+    }
+}
+
 pub fn delete(thmb: &Thumb) -> Result<()> {
     let conn = connection()?;
-    diesel::delete(thumbs.find(&thmb.picture_id)).execute(&*conn)?;
+    diesel::delete(thumbs.find((&thmb.picture_id, &thmb.rendition))).execute(&*conn)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(thumbs).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{setup_database, save_gallery_named, save_picture};
+    use crate::database::model::{NewPicture, Thumb};
+
+    fn thumb(picture_id: i32, rendition: &str) -> Thumb {
+        Thumb {
+            picture_id,
+            rendition: rendition.to_string(),
+            width: 100,
+            height: 100,
+            fit: "contain".to_string(),
+            picture_hash: "abc".to_string(),
+        }
+    }
+
+    #[test]
+    fn save_by_picture_and_rendition() {
+        setup_database();
+        let gallery = save_gallery_named("Gal1").unwrap().id;
+        let picture = save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery,
+            format: "png".to_string(),
+            path: "/img1.png".to_string(),
+            sha1: "abc".to_string(),
+            filesize: 0,
+            external_id: "img1.png".to_string(),
+        }).unwrap();
+        let small = thumb(picture.id, "small");
+        let mut large = thumb(picture.id, "large");
+        super::save(&small).unwrap();
+        super::save(&large).unwrap();
+        assert_eq!(super::by_picture(&picture.id).unwrap().len(), 2);
+        large.picture_hash = "def".to_string();
+        super::save(&large).unwrap();
+        assert_eq!(super::by_picture_and_rendition(&picture.id, "large").unwrap(), Some(large));
+        assert_eq!(super::by_picture_and_rendition(&picture.id, "small").unwrap(), Some(small.clone()));
+        super::delete(&small).unwrap();
+        assert_eq!(super::by_picture_and_rendition(&picture.id, "small").unwrap(), None);
+    }
+}
\ No newline at end of file
@@ -1,14 +1,21 @@
 use crate::database::{connection, Result};
-use crate::database::model::User;
+use crate::database::model::{User, NewUser};
 use crate::database::schema::users::dsl::*;
+use crate::database::schema::users::table;
 
 use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
 
 pub fn by_id(user_id: &i32) -> Result<User> {
     let conn = connection()?;
     Ok(users.find(user_id).first::<User>(&*conn)?)
 }
 
+pub fn all() -> Result<Vec<User>> {
+    let conn = connection()?;
+    Ok(users.order(username.asc()).load::<User>(&*conn)?)
+}
+
 pub fn by_username(uname: &str) -> Result<Option<User>> {
     let conn = connection()?;
     let results = users.filter(username.eq(uname)).limit(1).load::<User>(&*conn)?;
@@ -19,4 +26,147 @@ pub fn by_email(mail: &str) -> Result<Option<User>> {
     let conn = connection()?;
     let results = users.filter(email.eq(mail)).limit(1).load::<User>(&*conn)?;
     Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn by_verification(token: &str) -> Result<Option<User>> {
+    let conn = connection()?;
+    let results = users.filter(verification.eq(token)).limit(1).load::<User>(&*conn)?;
+    Ok(results.first().map(|a| a.clone()))
+}
+
+pub fn insert(user: &NewUser) -> Result<InsertStatus> {
+    let conn = connection()?;
+    if by_username(&user.username)?.is_some() || by_email(&user.email)?.is_some() {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    diesel::insert_into(table)
+        .values(user)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+pub fn update_password(user_id: &i32, hash: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(users.find(user_id))
+        .set(password.eq(hash))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+pub fn set_verification(user_id: &i32, token: Option<&str>) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(users.find(user_id))
+        .set(verification.eq(token))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Also forgets the last accepted time step, which belonged to the old secret.
+pub fn set_totp_secret(user_id: &i32, secret: Option<&str>) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(users.find(user_id))
+        .set((totp_secret.eq(secret), totp_last_step.eq(None::<i64>)))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Remembers `step` as the last used TOTP time step. Returns false if the same or
+/// a later step was accepted before, so every code can only be used once.
+pub fn accept_totp_step(user_id: &i32, step: i64) -> Result<bool> {
+    let conn = connection()?;
+    let updated = diesel::update(users.find(user_id)
+            .filter(totp_last_step.is_null().or(totp_last_step.lt(step))))
+        .set(totp_last_step.eq(step))
+        .execute(&*conn)?;
+    Ok(updated > 0)
+}
+
+pub fn set_role(user_id: &i32, new_role: &str) -> Result<()> {
+    let conn = connection()?;
+    diesel::update(users.find(user_id))
+        .set(role.eq(new_role))
+        .execute(&*conn)?;
+    Ok(())
+}
+
+/// Deletes the user together with everything that belongs to them in one transaction.
+/// Foreign keys are not enforced, so the rows are removed explicitly.
+pub fn delete(user: &User) -> Result<()> {
+    use crate::database::schema::{api_tokens, gallery_access, group_members, password_resets, recovery_codes, sessions, share_links};
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        diesel::delete(sessions::table.filter(sessions::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(api_tokens::table.filter(api_tokens::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(gallery_access::table.filter(gallery_access::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(group_members::table.filter(group_members::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(password_resets::table.filter(password_resets::user_id.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(share_links::table.filter(share_links::created_by.eq(&user.id))).execute(&*conn)?;
+        diesel::delete(users.find(&user.id)).execute(&*conn)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(users).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_VIEWER};
+    use crate::testing::setup_database;
+
+    #[test]
+    fn all() {
+        setup_database();
+        let bob = crate::testing::save_user("bob", ROLE_VIEWER).unwrap();
+        let alice = crate::testing::save_user("alice", ROLE_ADMIN).unwrap();
+        assert_eq!(super::all().unwrap(), vec![alice, bob]);
+    }
+
+    #[test]
+    fn set_role() {
+        setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        super::set_role(&user.id, ROLE_ADMIN).unwrap();
+        assert_eq!(super::by_id(&user.id).unwrap().role, ROLE_ADMIN);
+    }
+
+    #[test]
+    fn delete() {
+        use crate::database::model::{NewShareLink, PasswordReset, RecoveryCode};
+        use crate::database::provider;
+        setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        provider::recovery_code::replace_for_user(&user.id, &[RecoveryCode {
+            user_id: user.id,
+            code_hash: "code".to_string(),
+        }]).unwrap();
+        provider::password_reset::insert(&PasswordReset {
+            token_hash: "reset".to_string(),
+            user_id: user.id,
+            expires: crate::auth::now() + 60,
+        }).unwrap();
+        let link = provider::share_link::insert(&NewShareLink {
+            token_hash: "link".to_string(),
+            gallery_id: Some(gallery.id),
+            picture_id: None,
+            recursive: false,
+            created_by: user.id,
+            created: crate::auth::now(),
+            expires: None,
+            password: None,
+            allow_download: false,
+        }).unwrap();
+        let session = crate::auth::login::session_for(user.clone());
+        super::delete(&user).unwrap();
+        assert!(super::by_username("user").unwrap().is_none());
+        assert!(provider::recovery_code::by_user(&user.id).unwrap().is_empty());
+        assert!(provider::password_reset::by_token_hash("reset").unwrap().is_none());
+        assert!(provider::share_link::by_hash(&link.token_hash).unwrap().is_none());
+        assert!(provider::session::by_id(&session).unwrap().is_none());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+use crate::database::{connection, Result};
+use crate::database::model::{NewUserGroup, UserGroup};
+use crate::database::schema::user_groups::dsl::*;
+use crate::database::schema::user_groups::table;
+
+use diesel::prelude::*;
+use crate::database::provider::InsertStatus;
+
+pub fn all() -> Result<Vec<UserGroup>> {
+    let conn = connection()?;
+    Ok(user_groups.order(name.asc()).load::<UserGroup>(&*conn)?)
+}
+
+pub fn by_id(group_id: &i32) -> Result<UserGroup> {
+    let conn = connection()?;
+    Ok(user_groups.find(group_id).first::<UserGroup>(&*conn)?)
+}
+
+pub fn by_name(group_name: &str) -> Result<Option<UserGroup>> {
+    let conn = connection()?;
+    Ok(user_groups.filter(name.eq(group_name)).first::<UserGroup>(&*conn).optional()?)
+}
+
+pub fn insert(group: &NewUserGroup) -> Result<InsertStatus> {
+    if by_name(&group.name)?.is_some() {
+        return Ok(InsertStatus::AlreadyExists);
+    }
+    let conn = connection()?;
+    diesel::insert_into(table)
+        .values(group)
+        .execute(&*conn)?;
+    Ok(InsertStatus::Ok)
+}
+
+/// Deletes the group along with its memberships and gallery grants in one transaction.
+pub fn delete(group_id: &i32) -> Result<()> {
+    use crate::database::schema::{gallery_group_access, group_members};
+    let conn = connection()?;
+    conn.transaction::<_, crate::database::Error, _>(|| {
+        diesel::delete(group_members::table.filter(group_members::group_id.eq(group_id))).execute(&*conn)?;
+        diesel::delete(gallery_group_access::table.filter(gallery_group_access::group_id.eq(group_id))).execute(&*conn)?;
+        diesel::delete(user_groups.find(group_id)).execute(&*conn)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+pub fn clear_all() {
+    let conn = connection().unwrap();
+    diesel::delete(user_groups).execute(&*conn).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::model::NewUserGroup;
+    use crate::database::provider::InsertStatus;
+    use crate::testing::setup_database;
+
+    #[test]
+    fn insert_and_delete() {
+        setup_database();
+        super::insert(&NewUserGroup { name: "family".to_string() }).unwrap();
+        match super::insert(&NewUserGroup { name: "family".to_string() }).unwrap() {
+            InsertStatus::AlreadyExists => {},
+            InsertStatus::Ok => panic!("Duplicate group name inserted"),
+        }
+        let group = super::by_name("family").unwrap().unwrap();
+        assert_eq!(super::all().unwrap(), vec![group.clone()]);
+        super::delete(&group.id).unwrap();
+        assert!(super::by_name("family").unwrap().is_none());
+    }
+}
\ No newline at end of file
@@ -1,9 +1,91 @@
+table! {
+    api_tokens (id) {
+        id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        scope -> Text,
+        created -> BigInt,
+        expires -> Nullable<BigInt>,
+        last_used -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Integer,
+        created -> BigInt,
+        actor_id -> Nullable<Integer>,
+        action -> Text,
+        target -> Nullable<Text>,
+        details -> Nullable<Text>,
+        ip -> Nullable<Text>,
+    }
+}
+
+table! {
+    gallery_access (gallery_id, user_id) {
+        gallery_id -> Integer,
+        user_id -> Integer,
+    }
+}
+
+table! {
+    gallery_group_access (gallery_id, group_id) {
+        gallery_id -> Integer,
+        group_id -> Integer,
+    }
+}
+
 table! {
     gallerys (id) {
         id -> Integer,
         name -> Text,
         directory -> Nullable<Text>,
         parent -> Nullable<Integer>,
+        visibility -> Text,
+    }
+}
+
+table! {
+    group_members (group_id, user_id) {
+        group_id -> Integer,
+        user_id -> Integer,
+    }
+}
+
+table! {
+    login_failures (kind, subject) {
+        kind -> Text,
+        subject -> Text,
+        failures -> Integer,
+        last_failure -> BigInt,
+        locked_until -> BigInt,
+    }
+}
+
+table! {
+    password_resets (token_hash) {
+        token_hash -> Text,
+        user_id -> Integer,
+        expires -> BigInt,
+    }
+}
+
+table! {
+    picture_metadata (picture_id) {
+        picture_id -> Integer,
+        taken_at -> Nullable<Text>,
+        camera_make -> Nullable<Text>,
+        camera_model -> Nullable<Text>,
+        lens -> Nullable<Text>,
+        exposure_time -> Nullable<Text>,
+        f_number -> Nullable<Double>,
+        iso -> Nullable<Integer>,
+        focal_length -> Nullable<Double>,
+        orientation -> Nullable<Integer>,
+        latitude -> Nullable<Double>,
+        longitude -> Nullable<Double>,
     }
 }
 
@@ -29,21 +111,80 @@ table! {
     }
 }
 
+table! {
+    recovery_codes (user_id, code_hash) {
+        user_id -> Integer,
+        code_hash -> Text,
+    }
+}
+
+table! {
+    role_permissions (role, permission) {
+        role -> Text,
+        permission -> Text,
+    }
+}
+
+table! {
+    roles (name) {
+        name -> Text,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Text,
+        user_id -> Integer,
+        created -> BigInt,
+        last_seen -> BigInt,
+        expires -> BigInt,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+    }
+}
+
+table! {
+    share_links (id) {
+        id -> Integer,
+        token_hash -> Text,
+        gallery_id -> Nullable<Integer>,
+        picture_id -> Nullable<Integer>,
+        recursive -> Bool,
+        created_by -> Integer,
+        created -> BigInt,
+        expires -> Nullable<BigInt>,
+        password -> Nullable<Text>,
+        allow_download -> Bool,
+    }
+}
+
 table! {
     tags (id) {
         id -> Integer,
         tag_type -> Integer,
         name -> Text,
+        parent -> Nullable<Integer>,
     }
 }
 
 table! {
-    thumbs (picture_id) {
+    thumbs (picture_id, rendition) {
         picture_id -> Integer,
+        rendition -> Text,
+        width -> Integer,
+        height -> Integer,
+        fit -> Text,
         picture_hash -> Text,
     }
 }
 
+table! {
+    user_groups (id) {
+        id -> Integer,
+        name -> Text,
+    }
+}
+
 table! {
     users (id) {
         id -> Integer,
@@ -51,17 +192,52 @@ table! {
         email -> Text,
         password -> Text,
         verification -> Nullable<Text>,
+        role -> Text,
+        totp_secret -> Nullable<Text>,
+        totp_last_step -> Nullable<BigInt>,
     }
 }
 
+joinable!(api_tokens -> users (user_id));
+joinable!(gallery_access -> gallerys (gallery_id));
+joinable!(gallery_access -> users (user_id));
+joinable!(gallery_group_access -> gallerys (gallery_id));
+joinable!(gallery_group_access -> user_groups (group_id));
+joinable!(group_members -> user_groups (group_id));
+joinable!(group_members -> users (user_id));
+joinable!(password_resets -> users (user_id));
+joinable!(picture_metadata -> pictures (picture_id));
+joinable!(picture_tags -> pictures (picture_id));
+joinable!(picture_tags -> tags (tag_id));
 joinable!(pictures -> gallerys (gallery_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(role_permissions -> roles (role));
+joinable!(sessions -> users (user_id));
+joinable!(share_links -> gallerys (gallery_id));
+joinable!(share_links -> pictures (picture_id));
+joinable!(share_links -> users (created_by));
 joinable!(thumbs -> pictures (picture_id));
+joinable!(users -> roles (role));
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_log,
+    gallery_access,
+    gallery_group_access,
     gallerys,
+    group_members,
+    login_failures,
+    password_resets,
+    picture_metadata,
     picture_tags,
     pictures,
+    recovery_codes,
+    role_permissions,
+    roles,
+    sessions,
+    share_links,
     tags,
     thumbs,
+    user_groups,
     users,
 );
@@ -1,13 +1,17 @@
 use crate::database::model::Picture;
+use std::fs::File;
 
-pub fn load_img(image: &Picture) -> std::io::Result<Vec<u8>> {
+pub fn open_img(image: &Picture) -> std::io::Result<File> {
     let path = &image.path;
-    std::fs::read(path)
+    File::open(path)
 }
 
-pub fn load_thumb(pic_id: &i32) -> std::io::Result<Vec<u8>> {
-    let path = &format!("{}/{}.png", get_thumbs_dir(), pic_id);
-    std::fs::read(path)
+pub fn open_rendition(pic_id: &i32, rendition: &str) -> std::io::Result<File> {
+    File::open(rendition_path(pic_id, rendition))
+}
+
+pub fn rendition_path(pic_id: &i32, rendition: &str) -> String {
+    format!("{}/{}_{}.png", get_thumbs_dir(), pic_id, rendition)
 }
 
 pub fn get_thumbs_dir() -> String {
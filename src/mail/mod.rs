@@ -0,0 +1,47 @@
+use crate::config::SmtpConfig;
+use lettre::{ClientSecurity, SmtpClient, Transport};
+use lettre::smtp::authentication::Credentials;
+use lettre_email::EmailBuilder;
+
+#[derive(Debug)]
+pub enum MailError {
+    NotConfigured,
+    Email(lettre_email::error::Error),
+    Smtp(lettre::smtp::error::Error),
+}
+
+impl From<lettre_email::error::Error> for MailError {
+    fn from(e: lettre_email::error::Error) -> Self {
+        MailError::Email(e)
+    }
+}
+
+impl From<lettre::smtp::error::Error> for MailError {
+    fn from(e: lettre::smtp::error::Error) -> Self {
+        MailError::Smtp(e)
+    }
+}
+
+pub type MailResult<T> = Result<T, MailError>;
+
+/// Sends a plain text mail through the configured SMTP server.
+pub fn send(to: &str, subject: &str, body: &str) -> MailResult<()> {
+    let conf: &SmtpConfig = crate::config::get().smtp.as_ref().ok_or(MailError::NotConfigured)?;
+    let email = EmailBuilder::new()
+        .to(to)
+        .from(conf.from.as_str())
+        .subject(subject)
+        .text(body)
+        .build()?;
+    let mut client = SmtpClient::new((conf.host.as_str(), conf.port), ClientSecurity::None)?;
+    if let (Some(username), Some(password)) = (&conf.username, &conf.password) {
+        client = client.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    client.transport().send(email.into())?;
+    Ok(())
+}
+
+/// Whether mails can be sent at all.
+pub fn enabled() -> bool {
+    crate::config::get().smtp.is_some()
+}
\ No newline at end of file
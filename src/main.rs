@@ -1,6 +1,8 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 
+extern crate argon2;
 extern crate askama;
+extern crate base32;
 #[macro_use]
 extern crate clap;
 extern crate colored;
@@ -11,9 +13,16 @@ extern crate diesel;
 extern crate diesel_migrations;
 extern crate dirs;
 extern crate dotenv;
+extern crate exif;
+extern crate httpdate;
 extern crate image;
 #[macro_use]
 extern crate lazy_static;
+extern crate lettre;
+extern crate lettre_email;
+extern crate notify;
+extern crate num_cpus;
+extern crate qrcode;
 extern crate r2d2;
 extern crate r2d2_sqlite;
 #[macro_use]
@@ -26,6 +35,7 @@ extern crate serde_derive;
 extern crate serde_json;
 extern crate sha;
 extern crate signal_hook;
+extern crate totp_lite;
 extern crate uuid;
 
 use colored::Colorize;
@@ -34,13 +44,19 @@ use std::path::Path;
 use std::process::exit;
 use clap::{App, ArgMatches};
 
+pub mod audit;
 pub mod auth;
+pub mod cli;
 pub mod config;
 pub mod database;
 pub mod disk;
+pub mod mail;
+pub mod metadata;
 mod net;
 pub mod scan;
+pub mod share;
 pub mod thumb;
+pub mod watch;
 #[cfg(test)]
 pub mod testing;
 
@@ -58,6 +74,8 @@ pub struct MyArgs {
     pub cache: Option<String>,
     pub skip_scan: bool,
     pub skip_thumbs: bool,
+    pub skip_watch: bool,
+    pub command: Option<cli::Command>,
 }
 
 impl<'a> From<ArgMatches<'a>> for MyArgs {
@@ -67,6 +85,8 @@ impl<'a> From<ArgMatches<'a>> for MyArgs {
             cache: a.value_of("cache").map(ToString::to_string),
             skip_scan: a.is_present("skip scan"),
             skip_thumbs: a.is_present("skip thumbs"),
+            skip_watch: a.is_present("skip watch"),
+            command: cli::Command::from_matches(&a),
         }
     }
 }
@@ -85,6 +105,13 @@ fn main() {
 
     get_cache_dir();
     println!("Regal v{}", VERSION);
+    if let Some(command) = &ARGS.command {
+        if let Err(e) = cli::run(command) {
+            eprintln!("{} {:?}", "! Error:".red(), e);
+            exit(1);
+        }
+        exit(0);
+    }
     let conf: &Config = config::get();
     if !ARGS.skip_scan {
         for dir in conf.scan_dirs.iter() {
@@ -99,6 +126,9 @@ fn main() {
 
     if !ARGS.skip_thumbs {
         println!("\n{}\n===========\n", "Generating thumbnails".blue());
+        if let Err(e) = thumb::remove_orphans() {
+            eprintln!("{} {:?}", "! Error removing unused thumbnails:".yellow(), e);
+        }
         for gallery in database::provider::gallery::all().unwrap() {
             println!("{} [{}]", "Generating thumbnails in gallery".blue(), gallery.name.magenta());
             for pic in database::provider::picture::by_gallery(&gallery.id).unwrap() {
@@ -107,6 +137,12 @@ fn main() {
         }
     }
 
+    if !ARGS.skip_watch {
+        watch::start(conf);
+    }
+
+    auth::login::start_reaper(conf);
+
     net::launch();
 }
 
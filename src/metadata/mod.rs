@@ -0,0 +1,180 @@
+use crate::database::model::PictureMetadata;
+use exif::{Exif, In, Tag, Value};
+use image::DynamicImage;
+use std::fs::File;
+use std::io::BufReader;
+
+/// EXIF data read from a picture file before the picture has been stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExifData {
+    pub taken_at: Option<String>,
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens: Option<String>,
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<i32>,
+    pub focal_length: Option<f64>,
+    pub orientation: Option<i32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl ExifData {
+    pub fn into_metadata(self, picture_id: i32) -> PictureMetadata {
+        let ExifData {
+            taken_at,
+            camera_make,
+            camera_model,
+            lens,
+            exposure_time,
+            f_number,
+            iso,
+            focal_length,
+            orientation,
+            latitude,
+            longitude,
+        } = self;
+        PictureMetadata {
+            picture_id,
+            taken_at,
+            camera_make,
+            camera_model,
+            lens,
+            exposure_time,
+            f_number,
+            iso,
+            focal_length,
+            orientation,
+            latitude,
+            longitude,
+        }
+    }
+}
+
+/// Reads the EXIF data of `file`. Returns `None` for files without EXIF data.
+pub fn extract(file: &str) -> Option<ExifData> {
+    let mut reader = BufReader::new(File::open(file).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    Some(ExifData {
+        taken_at: ascii(&exif, Tag::DateTimeOriginal).or_else(|| ascii(&exif, Tag::DateTime)),
+        camera_make: ascii(&exif, Tag::Make),
+        camera_model: ascii(&exif, Tag::Model),
+        lens: ascii(&exif, Tag::LensModel),
+        exposure_time: exif.get_field(Tag::ExposureTime, In::PRIMARY).map(|f| f.display_value().to_string()),
+        f_number: rational(&exif, Tag::FNumber),
+        iso: uint(&exif, Tag::PhotographicSensitivity),
+        focal_length: rational(&exif, Tag::FocalLength),
+        orientation: uint(&exif, Tag::Orientation),
+        latitude: coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        longitude: coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    })
+}
+
+/// Rotates and flips `img` so it is displayed upright according to the EXIF
+/// orientation tag. Pictures without orientation are returned as they are.
+pub fn apply_orientation(img: DynamicImage, orientation: Option<i32>) -> DynamicImage {
+    match orientation.unwrap_or(1) {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Whether the orientation turns the picture by 90 degrees, swapping width and height.
+pub fn swaps_dimensions(orientation: Option<i32>) -> bool {
+    match orientation {
+        Some(5..=8) => true,
+        _ => false,
+    }
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+fn rational(exif: &Exif, tag: Tag) -> Option<f64> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) => values.first().map(|v| v.to_f64()),
+        _ => None,
+    }
+}
+
+fn uint(exif: &Exif, tag: Tag) -> Option<i32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0).map(|v| v as i32)
+}
+
+/// Converts a GPS degrees/minutes/seconds triple into signed decimal degrees.
+fn coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let values = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) if values.len() >= 3 => values,
+        _ => return None,
+    };
+    let degrees = values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0;
+    if ascii(exif, ref_tag).map(|r| r.eq(negative_ref)).unwrap_or(false) {
+        Some(-degrees)
+    } else {
+        Some(degrees)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+
+    /// 3x2 picture where every pixel has a different value.
+    fn source() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, y| Luma([(y * 3 + x) as u8])))
+    }
+
+    /// Checks that the pixel at `(x, y)` of the source ends up at `target(x, y)`.
+    fn assert_oriented(orientation: Option<i32>, target: fn(u32, u32) -> (u32, u32)) {
+        let oriented = super::apply_orientation(source(), orientation);
+        let swapped = super::swaps_dimensions(orientation);
+        let (width, height) = if swapped { (2, 3) } else { (3, 2) };
+        assert_eq!(oriented.dimensions(), (width, height));
+        let oriented = oriented.to_luma();
+        for y in 0..2 {
+            for x in 0..3 {
+                let (tx, ty) = target(x, y);
+                assert_eq!(oriented.get_pixel(tx, ty), &Luma([(y * 3 + x) as u8]), "orientation {:?}", orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn orientations() {
+        assert_oriented(None, |x, y| (x, y));
+        assert_oriented(Some(1), |x, y| (x, y));
+        assert_oriented(Some(2), |x, y| (2 - x, y));
+        assert_oriented(Some(3), |x, y| (2 - x, 1 - y));
+        assert_oriented(Some(4), |x, y| (x, 1 - y));
+        assert_oriented(Some(5), |x, y| (y, x));
+        assert_oriented(Some(6), |x, y| (1 - y, x));
+        assert_oriented(Some(7), |x, y| (1 - y, 2 - x));
+        assert_oriented(Some(8), |x, y| (y, 2 - x));
+    }
+
+    #[test]
+    fn swaps_dimensions() {
+        for orientation in 1..=4 {
+            assert!(!super::swaps_dimensions(Some(orientation)));
+        }
+        for orientation in 5..=8 {
+            assert!(super::swaps_dimensions(Some(orientation)));
+        }
+        assert!(!super::swaps_dimensions(None));
+        assert!(!super::swaps_dimensions(Some(9)));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,151 @@
+use rocket::Rocket;
+use rocket::http::Status;
+use rocket::request::Form;
+use rocket_contrib::json::Json;
+use crate::auth::login::LoginUser;
+use crate::auth::permission;
+use crate::database::model::AuditEntry;
+use crate::database::provider::audit_log::AuditFilter;
+use crate::net::{api_error, ApiError};
+use crate::net::auth::authorize;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/audit", routes![list])
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct AuditEntryData {
+    entry_id: i32,
+    created: i64,
+    actor_id: Option<i32>,
+    action: String,
+    target: Option<String>,
+    details: Option<String>,
+    ip: Option<String>,
+}
+
+impl From<AuditEntry> for AuditEntryData {
+    fn from(entry: AuditEntry) -> Self {
+        AuditEntryData {
+            entry_id: entry.id,
+            created: entry.created,
+            actor_id: entry.actor_id,
+            action: entry.action,
+            target: entry.target,
+            details: entry.details,
+            ip: entry.ip,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct AuditPageData {
+    /// Number of entries matching the filter on all pages.
+    total: i64,
+    page: u32,
+    page_size: u32,
+    entries: Vec<AuditEntryData>,
+}
+
+#[derive(FromForm)]
+struct AuditQuery {
+    actor_id: Option<i32>,
+    action: Option<String>,
+    target: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    /// Pages start at 0.
+    page: Option<u32>,
+    page_size: Option<u32>,
+}
+
+/// Newest entries first, filtered by the given query parameters.
+#[get("/?<query..>")]
+fn list(user: Option<LoginUser>, query: Form<AuditQuery>) -> Result<Json<AuditPageData>, ApiError> {
+    authorize(user, permission::can_administer)?;
+    let page = query.page.unwrap_or(0);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1).min(MAX_PAGE_SIZE);
+    let filter = AuditFilter {
+        actor_id: query.actor_id,
+        action: query.action.as_ref().map(String::as_str),
+        target: query.target.as_ref().map(String::as_str),
+        since: query.since,
+        until: query.until,
+    };
+    let total = crate::database::provider::audit_log::count(&filter)
+        .map_err(|e| api_error(Status::InternalServerError, format!("Error loading audit log: {:?}", e)))?;
+    let offset = page as i64 * page_size as i64;
+    match crate::database::provider::audit_log::query(&filter, offset, page_size as i64) {
+        Ok(entries) => Ok(Json(AuditPageData {
+            total,
+            page,
+            page_size,
+            entries: entries.into_iter().map(Into::into).collect(),
+        })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading audit log: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::ClientInfo;
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR};
+    use crate::net::audit::AuditPageData;
+    use rocket::http::Status;
+    use rocket::local::Client;
+
+    fn setup() -> Client {
+        crate::testing::setup_database();
+        super::super::test_client()
+    }
+
+    #[test]
+    fn gallery_delete_logged() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let response = client.delete(format!("/gallery/{}", &gallery.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = client.get("/audit/?action=gallery_delete")
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let parsed: AuditPageData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.total, 1);
+        assert_eq!(parsed.entries[0].actor_id, Some(admin.id));
+        assert_eq!(parsed.entries[0].target, Some(format!("gallery:{}", &gallery.id)));
+        assert_eq!(parsed.entries[0].details, Some("Gal1".to_string()));
+    }
+
+    #[test]
+    fn paginated() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        for _ in 0..3 {
+            crate::auth::login::start_session(&admin, &ClientInfo::default()).unwrap();
+        }
+        let mut response = client.get("/audit/?action=login&page=1&page_size=2")
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        let parsed: AuditPageData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.total, 4);
+        assert_eq!(parsed.entries.len(), 2);
+    }
+
+    #[test]
+    fn requires_administer() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let response = client.get("/audit/")
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+}
\ No newline at end of file
@@ -1,11 +1,23 @@
 use rocket::request::{FromRequest, Outcome};
-use crate::auth::login::LoginUser;
+use rocket::http::Status;
+use crate::auth::login::{ClientInfo, LoginUser};
+use crate::auth::permission::{self, PermissionError};
+use crate::database::model::Gallery;
+use crate::net::{api_error, ApiError};
 use askama::rocket::Request;
 
 impl<'a, 'r> FromRequest<'a, 'r> for LoginUser {
     type Error = ();
 
+    /// Accepts API tokens in an `Authorization: Bearer` header or a session cookie.
+    /// Requests with an invalid token fail instead of falling back to the cookie.
     fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        if let Some(authorization) = request.headers().get_one("Authorization") {
+            return match bearer_token(authorization).and_then(crate::auth::token::user_for_token) {
+                Some(user) => Outcome::Success(user),
+                None => Outcome::Failure((Status::Unauthorized, ())),
+            };
+        }
         if let Some(session_id) = request.cookies().get_private("session") {
             if let Some(user) = crate::auth::login::user_for_session(session_id.value()) {
                 Outcome::Success(user)
@@ -16,4 +28,48 @@ impl<'a, 'r> FromRequest<'a, 'r> for LoginUser {
             Outcome::Forward(())
         }
     }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientInfo {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            user_agent: request.headers().get_one("User-Agent").map(ToString::to_string),
+            ip: request.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
+
+pub(crate) fn bearer_token(authorization: &str) -> Option<&str> {
+    const PREFIX: &'static str = "Bearer ";
+    if authorization.starts_with(PREFIX) {
+        Some(authorization[PREFIX.len()..].trim())
+    } else {
+        None
+    }
+}
+
+/// Ensures a user is logged in and passes the permission `check`.
+/// Responds with `401 Unauthorized` for anonymous requests and `403 Forbidden`
+/// for users lacking the permission.
+pub fn authorize<F>(user: Option<LoginUser>, check: F) -> Result<LoginUser, ApiError>
+    where F: FnOnce(&LoginUser) -> Result<bool, PermissionError> {
+    let user = user.ok_or(api_error(Status::Unauthorized, "Login required"))?;
+    match check(&user) {
+        Ok(true) => Ok(user),
+        Ok(false) => Err(api_error(Status::Forbidden, "Permission denied")),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error checking permission: {:?}", e))),
+    }
+}
+
+
+/// Whether `user` may see `gallery`. Errors while checking deny access.
+pub fn can_see_gallery(user: &Option<LoginUser>, gallery: &Gallery) -> bool {
+    permission::can_show_gallery(user.as_ref(), gallery).unwrap_or(false)
+}
+
+/// Whether `user` may see the picture `picture_id`. Errors while checking deny access.
+pub fn can_see_picture(user: &Option<LoginUser>, picture_id: &i32) -> bool {
+    permission::can_show_pic(user.as_ref(), picture_id).unwrap_or(false)
 }
\ No newline at end of file
@@ -0,0 +1,135 @@
+use std::io::Read;
+use std::ops::Deref;
+use rocket::{Data, Request};
+use rocket::data::{self, FromDataSimple};
+use rocket::http::{Cookie, SameSite, Status};
+use rocket::request::{self, FormItems, FromForm, FromRequest};
+use rocket::Outcome;
+
+/// Forms are protected with the double submit pattern: a random token is kept
+/// in the `csrf` cookie and has to be sent back with every state-changing
+/// request, either as `csrf_token` form field or as `X-CSRF-Token` header.
+/// Other sites can neither read nor set the cookie, so they can't forge it.
+pub const COOKIE: &'static str = "csrf";
+pub const FIELD: &'static str = "csrf_token";
+pub const HEADER: &'static str = "X-CSRF-Token";
+
+const TOKEN_LENGTH: usize = 32;
+const FORM_LIMIT: u64 = 32 * 1024;
+
+/// The token to embed into forms. Creates one if the client has none yet, so
+/// routes rendering forms should list this guard before `Cookies`.
+pub struct CsrfToken(pub String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for CsrfToken {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if let Some(token) = cookie_token(request) {
+            return Outcome::Success(CsrfToken(token));
+        }
+        let token = crate::auth::random_string(TOKEN_LENGTH);
+        request.cookies().add(Cookie::build(COOKIE, token.clone())
+            .path("/")
+            .same_site(SameSite::Strict)
+            .http_only(true)
+            .finish());
+        Outcome::Success(CsrfToken(token))
+    }
+}
+
+/// Guards state-changing routes without form data. Responds with
+/// `403 Forbidden` unless the request carries a valid `X-CSRF-Token` header.
+pub struct Csrf;
+
+impl<'a, 'r> FromRequest<'a, 'r> for Csrf {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if verified(request, None) {
+            Outcome::Success(Csrf)
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+/// Works like `Form<T>`, but responds with `403 Forbidden` unless the form
+/// contains a valid `csrf_token` field. The field isn't passed on to `T`.
+pub struct CsrfForm<T>(T);
+
+impl<T> CsrfForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CsrfForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> FromDataSimple for CsrfForm<T> where T: for<'f> FromForm<'f> {
+    type Error = ();
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        if !request.content_type().map_or(false, |ct| ct.is_form()) {
+            return Outcome::Forward(data);
+        }
+        let limit = request.limits().get("forms").unwrap_or(FORM_LIMIT);
+        let mut body = String::new();
+        if data.open().take(limit).read_to_string(&mut body).is_err() {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+        let submitted = FormItems::from(body.as_str())
+            .find(|item| item.key.as_str() == FIELD)
+            .and_then(|item| item.value.url_decode().ok());
+        if !verified(request, submitted.as_ref().map(String::as_str)) {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+        let prefix = format!("{}=", FIELD);
+        let fields = body.split('&')
+            .filter(|field| !field.starts_with(&prefix))
+            .collect::<Vec<_>>()
+            .join("&");
+        match T::from_form(&mut FormItems::from(fields.as_str()), true) {
+            Ok(form) => Outcome::Success(CsrfForm(form)),
+            Err(_) => Outcome::Failure((Status::UnprocessableEntity, ())),
+        }
+    }
+}
+
+/// Reads the token from the raw `Cookie` header. Going through `Cookies`
+/// doesn't work here, as handlers taking `Cookies` already borrow the jar when
+/// the form is parsed.
+fn cookie_token(request: &Request) -> Option<String> {
+    request.headers().get("Cookie")
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| Cookie::parse_encoded(cookie.trim()).ok())
+        .find(|cookie| cookie.name() == COOKIE)
+        .map(|cookie| cookie.value().to_string())
+}
+
+/// Requests authenticated by a valid API token don't need a CSRF token, since
+/// browsers never send a bearer token on their own. Other `Authorization` headers,
+/// like Basic credentials a browser may have cached, don't count.
+fn verified(request: &Request, submitted: Option<&str>) -> bool {
+    let token_user = request.headers().get_one("Authorization")
+        .and_then(crate::net::auth::bearer_token)
+        .and_then(crate::auth::token::user_for_token);
+    if token_user.is_some() {
+        return true;
+    }
+    let submitted = request.headers().get_one(HEADER).or(submitted);
+    match (cookie_token(request), submitted) {
+        (Some(expected), Some(submitted)) => constant_time_eq(expected.as_bytes(), submitted.as_bytes()),
+        _ => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
\ No newline at end of file
@@ -1,11 +1,17 @@
 use rocket::Rocket;
-use crate::database::model::{Gallery, NewGallery};
+use crate::audit::{self, Target};
+use crate::auth::login::{ClientInfo, LoginUser};
+use crate::auth::permission;
+use crate::database::model::{Gallery, GalleryAccess, GalleryGroupAccess, NewGallery};
+use crate::net::{api_error, ApiError};
+use crate::net::auth::{authorize, can_see_gallery};
+use crate::net::csrf::{Csrf, CsrfForm};
 use rocket_contrib::json::Json;
-use rocket::response::status::{NotFound, BadRequest};
-use rocket::request::Form;
+use rocket::http::Status;
+use rocket::response::status::NotFound;
 
 pub fn mount(rocket: Rocket) -> Rocket {
-    rocket.mount("/gallery", routes![all, by_id, top_level, by_parent, list_all, create, delete])
+    rocket.mount("/gallery", routes![all, by_id, top_level, by_parent, list_all, create, update, delete, set_visibility, grant_access, revoke_access, grant_group_access, revoke_group_access])
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -17,10 +23,11 @@ struct GalleryData {
     thumb: String,
 }
 
-impl From<Gallery> for GalleryData {
-    fn from(gal: Gallery) -> Self {
+impl GalleryData {
+    /// The thumbnail is only taken from sub galleries `user` may see.
+    fn new(gal: Gallery, user: &Option<LoginUser>) -> Self {
         let mut thumb = "none".to_string();
-        if let Ok(Some(img)) = crate::database::provider::picture::find_thumb(&gal.id) {
+        if let Ok(Some(img)) = crate::database::provider::picture::find_thumb(&gal.id, |g| can_see_gallery(user, g)) {
             thumb = format!("/picture/thumb/{}", &img.id);
         }
         GalleryData {
@@ -55,6 +62,28 @@ impl Into<NewGallery> for NewGalleryForm {
     }
 }
 
+/// Fields that are not sent keep their current value.
+#[derive(FromForm)]
+struct UpdateGalleryForm {
+    name: Option<String>,
+    directory: Option<String>,
+    parent: Option<i32>,
+    /// Moves the gallery to the top level. Can't be combined with `parent`.
+    top_level: Option<bool>,
+}
+
+#[derive(FromForm)]
+struct VisibilityForm {
+    visibility: String,
+}
+
+fn visible(user: &Option<LoginUser>, galleries: Vec<Gallery>) -> Vec<GalleryData> {
+    galleries.into_iter()
+        .filter(|g| can_see_gallery(user, g))
+        .map(|g| GalleryData::new(g, user))
+        .collect()
+}
+
 #[get("/list_all")]
 fn list_all() {
     let galleries = crate::database::provider::gallery::all().unwrap();
@@ -63,66 +92,194 @@ fn list_all() {
 }
 
 #[get("/all")]
-fn all() -> Result<Json<Vec<GalleryData>>, NotFound<&'static str>> {
+fn all(user: Option<LoginUser>) -> Result<Json<Vec<GalleryData>>, NotFound<&'static str>> {
     if let Ok(galleries) = crate::database::provider::gallery::all() {
-        Ok(Json(galleries.into_iter().map(|g| g.into()).collect()))
+        Ok(Json(visible(&user, galleries)))
     } else {
         Err(NotFound("Error loading gallery list"))
     }
 }
 
 #[get("/<gallery_id>")]
-fn by_id(gallery_id: i32) -> Result<Json<GalleryData>, NotFound<String>> {
-    if let Ok(gallery) = crate::database::provider::gallery::by_id(&gallery_id) {
-        Ok(Json(gallery.into()))
-    } else {
-        Err(NotFound(format!("Gallery {} not found.", gallery_id)))
+fn by_id(user: Option<LoginUser>, gallery_id: i32) -> Result<Json<GalleryData>, NotFound<String>> {
+    match crate::database::provider::gallery::by_id(&gallery_id) {
+        Ok(ref gallery) if can_see_gallery(&user, gallery) => Ok(Json(GalleryData::new(gallery.clone(), &user))),
+        _ => Err(NotFound(format!("Gallery {} not found.", gallery_id))),
     }
 }
 
 #[get("/top")]
-fn top_level() -> Result<Json<Vec<GalleryData>>, NotFound<String>> {
+fn top_level(user: Option<LoginUser>) -> Result<Json<Vec<GalleryData>>, NotFound<String>> {
     if let Ok(galleries) = crate::database::provider::gallery::top_level() {
-        Ok(Json(galleries.into_iter().map(|g| g.into()).collect()))
+        Ok(Json(visible(&user, galleries)))
     } else {
         Err(NotFound(format!("Error loading galleries")))
     }
 }
 
 #[get("/by_parent/<parent_id>")]
-fn by_parent(parent_id: i32) -> Result<Json<Vec<GalleryData>>, NotFound<String>> {
+fn by_parent(user: Option<LoginUser>, parent_id: i32) -> Result<Json<Vec<GalleryData>>, NotFound<String>> {
     if let Ok(galleries) = crate::database::provider::gallery::by_parent(&parent_id) {
-        Ok(Json(galleries.into_iter().map(|g| g.into()).collect()))
+        Ok(Json(visible(&user, galleries)))
     } else {
         Err(NotFound(format!("Error loading galleries")))
     }
 }
 
+/// The parent has to exist and the user has to be allowed to see and edit it.
+fn check_parent(user: &LoginUser, parent_id: &i32) -> Result<(), ApiError> {
+    let parent = match crate::database::provider::gallery::by_id(parent_id) {
+        Ok(parent) => parent,
+        Err(_) => return Err(api_error(Status::BadRequest, format!("Parent gallery [{}] does not exist", parent_id))),
+    };
+    let allowed = match permission::can_show_gallery(Some(user), &parent) {
+        Ok(true) => permission::can_edit_gallery(user, parent_id),
+        other => other,
+    };
+    match allowed {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(api_error(Status::Forbidden, "Permission denied")),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error checking permission: {:?}", e))),
+    }
+}
+
 #[post("/new", data = "<new_gallery>")]
-fn create(new_gallery: Form<NewGalleryForm>) -> Result<(), BadRequest<String>> {
-    match crate::database::provider::gallery::insert(&new_gallery.into_inner().into()) {
+fn create(user: Option<LoginUser>, new_gallery: CsrfForm<NewGalleryForm>) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_create_gallery)?;
+    let new_gallery = new_gallery.into_inner();
+    if let Some(parent_id) = &new_gallery.parent {
+        check_parent(&user, parent_id)?;
+    }
+    match crate::database::provider::gallery::insert(&new_gallery.into()) {
         Ok(_) => Ok(()),
-        Err(e) => Err(BadRequest(Some(format!("Error creating gallery: {:?}", e))))
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error creating gallery: {:?}", e)))
+    }
+}
+
+#[put("/<gallery_id>", data = "<gallery>")]
+fn update(user: Option<LoginUser>, gallery_id: i32, gallery: CsrfForm<UpdateGalleryForm>) -> Result<(), ApiError> {
+    use crate::database::provider;
+    let user = authorize(user, |u| permission::can_edit_gallery(u, &gallery_id))?;
+    if provider::gallery::by_id(&gallery_id).is_err() {
+        return Err(api_error(Status::NotFound, format!("Gallery [{}] does not exists or could not be loaded", &gallery_id)));
+    }
+    let gallery = gallery.into_inner();
+    let parent = match (gallery.parent.as_ref(), gallery.top_level.unwrap_or(false)) {
+        (Some(_), true) => return Err(api_error(Status::BadRequest, "Either parent or top_level can be sent")),
+        (Some(parent_id), false) => Some(Some(parent_id)),
+        (None, true) => Some(None),
+        (None, false) => None,
+    };
+    if let Some(Some(parent_id)) = parent {
+        check_parent(&user, parent_id)?;
+        match provider::gallery::descendant_ids(&gallery_id) {
+            Ok(ids) if ids.contains(parent_id) => return Err(api_error(Status::BadRequest, "A gallery cannot be moved below itself")),
+            Ok(_) => {},
+            Err(e) => return Err(api_error(Status::InternalServerError, format!("Error loading galleries: {:?}", e))),
+        }
+    }
+    match provider::gallery::update(&gallery_id, gallery.name.as_ref().map(String::as_str), gallery.directory.as_ref().map(String::as_str), parent) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error updating gallery: {:?}", e)))
     }
 }
 
 #[delete("/<gallery_id>")]
-fn delete(gallery_id: i32) -> Result<(), BadRequest<String>> {
+fn delete(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, gallery_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_delete_gallery(u, &gallery_id))?;
     if let Ok(gallery) = crate::database::provider::gallery::by_id(&gallery_id) {
         match crate::database::provider::gallery::delete(&gallery) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(BadRequest(Some(format!("Error deleting gallery: {:?}", e))))
+            Ok(_) => {
+                audit::record(Some(user.id), audit::GALLERY_DELETE, Target::Gallery(gallery_id), Some(gallery.name), &client);
+                Ok(())
+            },
+            Err(e) => Err(api_error(Status::BadRequest, format!("Error deleting gallery: {:?}", e)))
         }
     } else {
-        Err(BadRequest(Some(format!("Gallery [{}] does not exists or could not be loaded", &gallery_id))))
+        Err(api_error(Status::BadRequest, format!("Gallery [{}] does not exists or could not be loaded", &gallery_id)))
+    }
+}
+
+#[put("/<gallery_id>/visibility", data = "<form>")]
+fn set_visibility(user: Option<LoginUser>, client: ClientInfo, gallery_id: i32, form: CsrfForm<VisibilityForm>) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_manage_access(u, &gallery_id))?;
+    if !permission::VISIBILITIES.contains(&form.visibility.as_str()) {
+        return Err(api_error(Status::BadRequest, format!("Unknown visibility '{}'", &form.visibility)));
+    }
+    match crate::database::provider::gallery::set_visibility(&gallery_id, &form.visibility) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::VISIBILITY_CHANGE, Target::Gallery(gallery_id), Some(form.visibility.clone()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error updating gallery: {:?}", e)))
+    }
+}
+
+#[put("/<gallery_id>/access/<user_id>")]
+fn grant_access(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, gallery_id: i32, user_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_manage_access(u, &gallery_id))?;
+    let access = GalleryAccess {
+        gallery_id,
+        user_id,
+    };
+    match crate::database::provider::gallery_access::grant(&access) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::ACCESS_GRANT, Target::Gallery(gallery_id), Some(Target::User(user_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error granting access: {:?}", e)))
+    }
+}
+
+#[delete("/<gallery_id>/access/<user_id>")]
+fn revoke_access(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, gallery_id: i32, user_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_manage_access(u, &gallery_id))?;
+    match crate::database::provider::gallery_access::revoke(&gallery_id, &user_id) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::ACCESS_REVOKE, Target::Gallery(gallery_id), Some(Target::User(user_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error revoking access: {:?}", e)))
+    }
+}
+
+#[put("/<gallery_id>/group_access/<group_id>")]
+fn grant_group_access(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, gallery_id: i32, group_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_manage_access(u, &gallery_id))?;
+    if crate::database::provider::user_group::by_id(&group_id).is_err() {
+        return Err(api_error(Status::NotFound, format!("Group [{}] does not exists or could not be loaded", &group_id)));
+    }
+    let access = GalleryGroupAccess {
+        gallery_id,
+        group_id,
+    };
+    match crate::database::provider::gallery_group_access::grant(&access) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::ACCESS_GRANT, Target::Gallery(gallery_id), Some(Target::Group(group_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error granting access: {:?}", e)))
+    }
+}
+
+#[delete("/<gallery_id>/group_access/<group_id>")]
+fn revoke_group_access(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, gallery_id: i32, group_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, |u| permission::can_manage_access(u, &gallery_id))?;
+    match crate::database::provider::gallery_group_access::revoke(&gallery_id, &group_id) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::ACCESS_REVOKE, Target::Gallery(gallery_id), Some(Target::Group(group_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::BadRequest, format!("Error revoking access: {:?}", e)))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use rocket::http::Status;
+    use rocket::http::{ContentType, Status};
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR, ROLE_VIEWER, VISIBILITY_LOGGED_IN, VISIBILITY_RESTRICTED};
     use crate::net::gallery::GalleryData;
-    use crate::database::model::NewGallery;
+    use crate::database::model::{GalleryAccess, GroupMember, NewGallery};
     use rocket::local::Client;
 
     fn setup() -> Client {
@@ -151,7 +308,7 @@ mod tests {
         let parsed: Vec<GalleryData> = serde_json::from_str(&resp_string).unwrap();
         assert_eq!(parsed.len(), galleries.len());
         for gallery in galleries {
-            assert!(parsed.contains(&gallery.into()));
+            assert!(parsed.contains(&GalleryData::new(gallery, &None)));
         }
     }
 
@@ -176,7 +333,267 @@ mod tests {
             let mut response = client.get(format!("/gallery/{}", id)).dispatch();
             assert_eq!(response.status(), Status::Ok);
             let parsed: GalleryData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
-            assert_eq!(parsed, gallery.into());
+            assert_eq!(parsed, GalleryData::new(gallery, &None));
         }
     }
+
+    #[test]
+    fn thumb_skips_hidden_sub_galleries() {
+        let client = setup();
+        let root = crate::testing::save_gallery_named("Root").unwrap();
+        let hidden = crate::testing::save_gallery(&NewGallery {
+            name: "Hidden".to_string(),
+            directory: None,
+            parent: Some(root.id),
+        }).unwrap();
+        crate::database::provider::gallery::set_visibility(&hidden.id, VISIBILITY_RESTRICTED).unwrap();
+        let picture = crate::testing::save_picture(&crate::database::model::NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: hidden.id,
+            format: "png".to_string(),
+            path: "/img1.png".to_string(),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: "img1.png".to_string(),
+        }).unwrap();
+        let mut response = client.get(format!("/gallery/{}", &root.id)).dispatch();
+        let parsed: GalleryData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.thumb, "none");
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let mut response = client.get(format!("/gallery/{}", &root.id))
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        let parsed: GalleryData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.thumb, format!("/picture/thumb/{}", &picture.id));
+    }
+
+    #[test]
+    fn create_requires_login() {
+        let client = setup();
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Gal1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(crate::database::provider::gallery::by_name("Gal1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn create_requires_admin() {
+        let client = setup();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Gal1")
+            .private_cookie(crate::testing::session_cookie(user))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(crate::database::provider::gallery::by_name("Gal1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn create_requires_csrf_token() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .body("name=Gal1")
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(crate::database::provider::gallery::by_name("Gal1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn create_and_delete_as_admin() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Gal1")
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let gallery = crate::database::provider::gallery::by_name("Gal1").unwrap().remove(0);
+        let response = client.delete(format!("/gallery/{}", &gallery.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client.delete(format!("/gallery/{}", &gallery.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(crate::database::provider::gallery::by_name("Gal1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn editor_cannot_manage_access() {
+        let client = setup();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let response = client.put(format!("/gallery/{}", &gallery.id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Renamed")
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.put(format!("/gallery/{}/visibility", &gallery.id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(format!("visibility={}", VISIBILITY_RESTRICTED))
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn restricted_gallery_hidden() {
+        let client = setup();
+        let public = crate::testing::save_gallery_named("Public").unwrap();
+        let private = crate::testing::save_gallery_named("Private").unwrap();
+        crate::database::provider::gallery::set_visibility(&private.id, VISIBILITY_RESTRICTED).unwrap();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let mut response = client.get("/gallery/top").dispatch();
+        let parsed: Vec<GalleryData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed, vec![GalleryData::new(public.clone(), &None)]);
+        let response = client.get(format!("/gallery/{}", &private.id))
+            .private_cookie(crate::testing::session_cookie(user.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        crate::database::provider::gallery_access::grant(&GalleryAccess {
+            gallery_id: private.id,
+            user_id: user.id,
+        }).unwrap();
+        let mut response = client.get("/gallery/all")
+            .private_cookie(crate::testing::session_cookie(user))
+            .dispatch();
+        let parsed: Vec<GalleryData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.contains(&GalleryData::new(private, &None)));
+    }
+
+    #[test]
+    fn group_access() {
+        let client = setup();
+        let private = crate::testing::save_gallery_named("Private").unwrap();
+        crate::database::provider::gallery::set_visibility(&private.id, VISIBILITY_RESTRICTED).unwrap();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let group = crate::testing::save_group("family").unwrap();
+        crate::database::provider::group_member::add(&GroupMember {
+            group_id: group.id,
+            user_id: user.id,
+        }).unwrap();
+        let response = client.get(format!("/gallery/{}", &private.id))
+            .private_cookie(crate::testing::session_cookie(user.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.put(format!("/gallery/{}/group_access/{}", &private.id, &group.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get(format!("/gallery/{}", &private.id))
+            .private_cookie(crate::testing::session_cookie(user))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn visibility_inherited() {
+        let client = setup();
+        let parent = crate::testing::save_gallery_named("Parent").unwrap();
+        let child = crate::testing::save_gallery(&NewGallery {
+            name: "Child".to_string(),
+            directory: None,
+            parent: Some(parent.id),
+        }).unwrap();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let response = client.put(format!("/gallery/{}/visibility", &parent.id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(format!("visibility={}", VISIBILITY_LOGGED_IN))
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get(format!("/gallery/{}", &child.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let response = client.get(format!("/gallery/{}", &child.id))
+            .private_cookie(crate::testing::session_cookie(user))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn update_only_sent_fields() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let parent = crate::testing::save_gallery_named("Parent").unwrap();
+        let gallery = crate::testing::save_gallery(&NewGallery {
+            name: "Gal1".to_string(),
+            directory: Some("/gal1".to_string()),
+            parent: Some(parent.id),
+        }).unwrap();
+        let child = crate::testing::save_gallery(&NewGallery {
+            name: "Child".to_string(),
+            directory: None,
+            parent: Some(gallery.id),
+        }).unwrap();
+        let update = |body: String| client.put(format!("/gallery/{}", &gallery.id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(body)
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch()
+            .status();
+        assert_eq!(update("name=Renamed".to_string()), Status::Ok);
+        let updated = crate::database::provider::gallery::by_id(&gallery.id).unwrap();
+        assert_eq!(updated.name, "Renamed");
+        assert_eq!(updated.directory, Some("/gal1".to_string()));
+        assert_eq!(updated.parent, Some(parent.id));
+        assert_eq!(update(format!("parent={}", gallery.id)), Status::BadRequest);
+        assert_eq!(update(format!("parent={}", child.id)), Status::BadRequest);
+        assert_eq!(update(format!("parent={}", child.id + 100)), Status::BadRequest);
+        assert_eq!(update(format!("parent={}&top_level=true", parent.id)), Status::BadRequest);
+        assert_eq!(crate::database::provider::gallery::by_id(&gallery.id).unwrap().parent, Some(parent.id));
+        assert_eq!(update("top_level=true".to_string()), Status::Ok);
+        assert_eq!(crate::database::provider::gallery::by_id(&gallery.id).unwrap().parent, None);
+    }
+
+    #[test]
+    fn create_checks_parent() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let hidden = crate::testing::save_gallery_named("Hidden").unwrap();
+        crate::database::provider::gallery::set_visibility(&hidden.id, VISIBILITY_RESTRICTED).unwrap();
+        let create = |body: String| client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(body)
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch()
+            .status();
+        assert_eq!(create(format!("name=Gal1&parent={}", hidden.id + 100)), Status::BadRequest);
+        assert_eq!(create(format!("name=Gal1&parent={}", hidden.id)), Status::Forbidden);
+        assert!(crate::database::provider::gallery::by_name("Gal1").unwrap().is_empty());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,201 @@
+use rocket::Rocket;
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+use crate::audit::{self, Target};
+use crate::auth::login::{ClientInfo, LoginUser};
+use crate::auth::permission;
+use crate::database::model::{GroupMember, NewUserGroup, User, UserGroup};
+use crate::database::provider::InsertStatus;
+use crate::net::{api_error, ApiError};
+use crate::net::auth::authorize;
+use crate::net::csrf::{Csrf, CsrfForm};
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/group", routes![list, by_id, create, delete, add_member, remove_member])
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GroupData {
+    group_id: i32,
+    name: String,
+}
+
+impl From<UserGroup> for GroupData {
+    fn from(group: UserGroup) -> Self {
+        GroupData {
+            group_id: group.id,
+            name: group.name,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MemberData {
+    user_id: i32,
+    username: String,
+}
+
+impl From<User> for MemberData {
+    fn from(user: User) -> Self {
+        MemberData {
+            user_id: user.id,
+            username: user.username,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GroupDetailsData {
+    group: GroupData,
+    members: Vec<MemberData>,
+}
+
+#[derive(FromForm)]
+struct NewGroupForm {
+    name: String,
+}
+
+fn load_group(group_id: &i32) -> Result<UserGroup, ApiError> {
+    crate::database::provider::user_group::by_id(group_id)
+        .map_err(|_| api_error(Status::NotFound, format!("Group [{}] does not exists or could not be loaded", group_id)))
+}
+
+#[get("/")]
+fn list(user: Option<LoginUser>) -> Result<Json<Vec<GroupData>>, ApiError> {
+    authorize(user, permission::can_administer)?;
+    match crate::database::provider::user_group::all() {
+        Ok(groups) => Ok(Json(groups.into_iter().map(Into::into).collect())),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading groups: {:?}", e))),
+    }
+}
+
+#[get("/<group_id>")]
+fn by_id(user: Option<LoginUser>, group_id: i32) -> Result<Json<GroupDetailsData>, ApiError> {
+    authorize(user, permission::can_administer)?;
+    let group = load_group(&group_id)?;
+    match crate::database::provider::group_member::by_group(&group_id) {
+        Ok(members) => Ok(Json(GroupDetailsData {
+            group: group.into(),
+            members: members.into_iter().map(Into::into).collect(),
+        })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading members: {:?}", e))),
+    }
+}
+
+#[post("/", data = "<form>")]
+fn create(user: Option<LoginUser>, form: CsrfForm<NewGroupForm>, client: ClientInfo) -> Result<Json<GroupData>, ApiError> {
+    let user = authorize(user, permission::can_administer)?;
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(api_error(Status::BadRequest, "Group name must not be empty"));
+    }
+    match crate::database::provider::user_group::insert(&NewUserGroup { name: name.to_string() }) {
+        Ok(InsertStatus::Ok) => {},
+        Ok(InsertStatus::AlreadyExists) => return Err(api_error(Status::Conflict, format!("Group '{}' already exists", name))),
+        Err(e) => return Err(api_error(Status::InternalServerError, format!("Error creating group: {:?}", e))),
+    }
+    match crate::database::provider::user_group::by_name(name) {
+        Ok(Some(group)) => {
+            audit::record(Some(user.id), audit::GROUP_CREATE, Target::Group(group.id), Some(group.name.clone()), &client);
+            Ok(Json(group.into()))
+        },
+        Ok(None) => Err(api_error(Status::InternalServerError, "Created group could not be loaded")),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading group: {:?}", e))),
+    }
+}
+
+#[delete("/<group_id>")]
+fn delete(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, group_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_administer)?;
+    let group = load_group(&group_id)?;
+    match crate::database::provider::user_group::delete(&group_id) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::GROUP_DELETE, Target::Group(group_id), Some(group.name), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error deleting group: {:?}", e))),
+    }
+}
+
+#[put("/<group_id>/member/<user_id>")]
+fn add_member(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, group_id: i32, user_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_administer)?;
+    load_group(&group_id)?;
+    if crate::database::provider::user::by_id(&user_id).is_err() {
+        return Err(api_error(Status::NotFound, format!("User [{}] does not exists or could not be loaded", &user_id)));
+    }
+    let member = GroupMember {
+        group_id,
+        user_id,
+    };
+    match crate::database::provider::group_member::add(&member) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::GROUP_MEMBER_ADD, Target::Group(group_id), Some(Target::User(user_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error adding member: {:?}", e))),
+    }
+}
+
+#[delete("/<group_id>/member/<user_id>")]
+fn remove_member(user: Option<LoginUser>, _csrf: Csrf, client: ClientInfo, group_id: i32, user_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_administer)?;
+    match crate::database::provider::group_member::remove(&group_id, &user_id) {
+        Ok(_) => {
+            audit::record(Some(user.id), audit::GROUP_MEMBER_REMOVE, Target::Group(group_id), Some(Target::User(user_id).key()), &client);
+            Ok(())
+        },
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error removing member: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR};
+    use crate::net::group::{GroupData, GroupDetailsData};
+    use rocket::http::{ContentType, Status};
+    use rocket::local::Client;
+
+    fn setup() -> Client {
+        crate::testing::setup_database();
+        super::super::test_client()
+    }
+
+    #[test]
+    fn create_and_add_member() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let mut response = client.post("/group/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=family")
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let group: GroupData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(group.name, "family");
+        let response = client.put(format!("/group/{}/member/{}", &group.group_id, &admin.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = client.get(format!("/group/{}", &group.group_id))
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        let details: GroupDetailsData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(details.members.len(), 1);
+        assert_eq!(details.members[0].username, "admin");
+    }
+
+    #[test]
+    fn requires_administer() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let response = client.get("/group/")
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+}
\ No newline at end of file
@@ -1,11 +1,21 @@
 use askama::Template;
 use rocket::Rocket;
+use rocket::http::Status;
+use rocket::response::status::Custom;
+use rocket_contrib::json::Json;
 #[cfg(test)]
 use rocket::local::Client;
 
+mod audit;
 mod auth;
+mod csrf;
+mod response;
 mod gallery;
+mod group;
 mod picture;
+mod share;
+mod tag;
+mod token;
 mod web;
 
 pub fn launch() {
@@ -16,12 +26,30 @@ pub fn launch() {
 fn build_rocket() -> Rocket {
     let rocket = rocket::ignite();
     let rocket = rocket.mount("/", routes![index, favicon_ico]);
+    let rocket = audit::mount(rocket);
     let rocket = gallery::mount(rocket);
+    let rocket = group::mount(rocket);
     let rocket = picture::mount(rocket);
+    let rocket = share::mount(rocket);
+    let rocket = tag::mount(rocket);
+    let rocket = token::mount(rocket);
     let rocket = web::mount(rocket);
     rocket
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorData {
+    pub error: String,
+}
+
+pub type ApiError = Custom<Json<ErrorData>>;
+
+pub fn api_error<S: Into<String>>(status: Status, message: S) -> ApiError {
+    Custom(status, Json(ErrorData {
+        error: message.into(),
+    }))
+}
+
 #[derive(Template)]
 #[template(path = "index.html")]
 struct Index {
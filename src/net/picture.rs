@@ -1,21 +1,45 @@
 use rocket::Rocket;
 use rocket_contrib::json::Json;
-use rocket::response::status::NotFound;
-use crate::database::model::Picture;
+use rocket::response::status::{Custom, NotFound};
+use rocket::http::{ContentType, Status};
+use crate::auth::login::LoginUser;
+use crate::config::Rendition;
+use crate::net::auth::{can_see_gallery, can_see_picture};
+use crate::net::response::{Cacheability, CacheInfo, CacheValidators, ImageResponse};
+use crate::database::model::{Picture, PictureMetadata};
+use crate::thumb::{Fit, ThumbError};
+use std::io::Cursor;
 
 pub fn mount(rocket: Rocket) -> Rocket {
-    rocket.mount("/picture", routes![data, raw, thumb, in_gallery])
+    rocket.mount("/picture", routes![data, raw, thumb, rendition, resize, in_gallery])
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct PictureData {
+pub struct PictureData {
     picture_id: i32,
     picture_name: String,
     raw: String,
     thumb: String,
     display: String,
+    metadata: Option<MetadataData>,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetadataData {
+    taken_at: Option<String>,
+    camera_make: Option<String>,
+    camera_model: Option<String>,
+    lens: Option<String>,
+    exposure_time: Option<String>,
+    f_number: Option<f64>,
+    iso: Option<i32>,
+    focal_length: Option<f64>,
+    orientation: Option<i32>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+/// Metadata is left empty, it is only loaded for single pictures by `/picture/data`.
 impl From<Picture> for PictureData {
     fn from(img: Picture) -> Self {
         let name = img.name.clone();
@@ -26,24 +50,69 @@ impl From<Picture> for PictureData {
             raw: format!("/picture/raw/{}", id),
             thumb: format!("/picture/thumb/{}", id),
             display: format!("/web/picture/{}", id),
+            metadata: None,
+        }
+    }
+}
+
+impl From<PictureMetadata> for MetadataData {
+    fn from(meta: PictureMetadata) -> Self {
+        MetadataData {
+            taken_at: meta.taken_at,
+            camera_make: meta.camera_make,
+            camera_model: meta.camera_model,
+            lens: meta.lens,
+            exposure_time: meta.exposure_time,
+            f_number: meta.f_number,
+            iso: meta.iso,
+            focal_length: meta.focal_length,
+            orientation: meta.orientation,
+            latitude: meta.latitude,
+            longitude: meta.longitude,
         }
     }
 }
 
 #[get("/data/<img_id>")]
-fn data(img_id: i32) -> Result<Json<PictureData>, NotFound<String>> {
-    if let Ok(picture) = crate::database::provider::picture::by_id(&img_id) {
-        Ok(Json(picture.into()))
-    } else {
-        Err(NotFound(format!("Picture with id {} was not found.", img_id)))
+fn data(user: Option<LoginUser>, img_id: i32) -> Result<Json<PictureData>, Custom<String>> {
+    match crate::database::provider::picture::by_id(&img_id) {
+        Ok(picture) if can_see_picture(&user, &img_id) => {
+            let metadata = crate::database::provider::metadata::by_picture(&img_id)
+                .map_err(|e| Custom(Status::InternalServerError, format!("Error loading metadata: {:?}", e)))?;
+            let mut data: PictureData = picture.into();
+            data.metadata = metadata.map(Into::into);
+            Ok(Json(data))
+        },
+        _ => Err(Custom(Status::NotFound, format!("Picture with id {} was not found.", img_id))),
     }
 }
 
 #[get("/raw/<img_id>")]
-fn raw(img_id: i32) -> Result<Vec<u8>, NotFound<String>> {
+fn raw(user: Option<LoginUser>, img_id: i32, validators: CacheValidators) -> Result<ImageResponse, NotFound<String>> {
+    if !can_see_picture(&user, &img_id) {
+        return Err(NotFound(format!("Picture with id {} was not found.", img_id)));
+    }
+    original(img_id, &validators, cacheability(&img_id))
+}
+
+/// Pictures anonymous visitors can see may be kept by shared caches, all others
+/// only by the browser of the user allowed to see them.
+fn cacheability(img_id: &i32) -> Cacheability {
+    match crate::auth::permission::can_show_pic(None, img_id) {
+        Ok(true) => Cacheability::Public,
+        _ => Cacheability::Private,
+    }
+}
+
+/// Serves the original file without checking permissions.
+pub fn original(img_id: i32, validators: &CacheValidators, cacheability: Cacheability) -> Result<ImageResponse, NotFound<String>> {
     if let Ok(picture) = crate::database::provider::picture::by_id(&img_id) {
-        if let Ok(data) = crate::disk::load_img(&picture) {
-            Ok(data)
+        let info = CacheInfo::for_file(picture.sha1.clone(), &picture.path, cacheability);
+        if validators.is_fresh(&info) {
+            return Ok(ImageResponse::not_modified(info));
+        }
+        if let Ok(file) = crate::disk::open_img(&picture) {
+            Ok(ImageResponse::new(info, crate::net::response::content_type(&picture.format), file))
         } else {
             Err(NotFound(format!("File '{}' was not found.", &picture.path)))
         }
@@ -53,10 +122,63 @@ fn raw(img_id: i32) -> Result<Vec<u8>, NotFound<String>> {
 }
 
 #[get("/thumb/<img_id>")]
-fn thumb(img_id: i32) -> Result<Vec<u8>, NotFound<String>> {
+fn thumb(user: Option<LoginUser>, img_id: i32, validators: CacheValidators) -> Result<ImageResponse, NotFound<String>> {
+    render(&user, img_id, &crate::thumb::thumb_rendition(), &validators)
+}
+
+#[get("/rendition/<img_id>/<name>")]
+fn rendition(user: Option<LoginUser>, img_id: i32, name: String, validators: CacheValidators) -> Result<ImageResponse, NotFound<String>> {
+    match crate::thumb::rendition(&name) {
+        Some(rendition) => render(&user, img_id, rendition, &validators),
+        None => Err(NotFound(format!("Rendition '{}' does not exist.", &name))),
+    }
+}
+
+#[get("/resize/<img_id>?<w>&<h>&<fit>")]
+fn resize(user: Option<LoginUser>, img_id: i32, w: Option<u32>, h: Option<u32>, fit: Option<String>, validators: CacheValidators) -> Result<ImageResponse, Custom<String>> {
+    let fit = match fit {
+        Some(fit) => Fit::from_name(&fit).ok_or(Custom(Status::BadRequest, format!("Unknown fit '{}'.", &fit)))?,
+        None => Fit::Contain,
+    };
+    let max = crate::config::get().max_rendition_size;
+    let (width, height) = match (w, h, fit) {
+        (None, None, _) => return Err(Custom(Status::BadRequest, "Width or height is required.".to_string())),
+        (Some(w), Some(h), _) => (w, h),
+        (Some(w), None, Fit::Contain) => (w, max),
+        (None, Some(h), Fit::Contain) => (max, h),
+        (Some(w), None, _) => (w, w),
+        (None, Some(h), _) => (h, h),
+    };
+    if width == 0 || height == 0 || width > max || height > max {
+        return Err(Custom(Status::BadRequest, format!("Width and height must be between 1 and {}.", max)));
+    }
+    if !can_see_picture(&user, &img_id) {
+        return Err(Custom(Status::NotFound, format!("Picture with id {} was not found.", img_id)));
+    }
+    let configured = crate::config::get().renditions.iter()
+        .find(|r| r.width == width && r.height == height && r.fit == fit);
+    match configured {
+        Some(rendition) => render_unchecked(img_id, rendition, &validators, cacheability(&img_id)).map_err(|NotFound(e)| Custom(Status::NotFound, e)),
+        None => serve_resized(img_id, &crate::thumb::custom_rendition(width, height, fit), &validators, cacheability(&img_id)),
+    }
+}
+
+fn render(user: &Option<LoginUser>, img_id: i32, rendition: &Rendition, validators: &CacheValidators) -> Result<ImageResponse, NotFound<String>> {
+    if !can_see_picture(user, &img_id) {
+        return Err(NotFound(format!("Picture with id {} was not found.", img_id)));
+    }
+    render_unchecked(img_id, rendition, validators, cacheability(&img_id))
+}
+
+/// Serves a rendition without checking permissions.
+pub fn render_unchecked(img_id: i32, rendition: &Rendition, validators: &CacheValidators, cacheability: Cacheability) -> Result<ImageResponse, NotFound<String>> {
     if let Ok(picture) = crate::database::provider::picture::by_id(&img_id) {
-        if let Ok(data) = crate::disk::load_thumb(&img_id) {
-            Ok(data)
+        let info = rendition_info(&picture, rendition, cacheability);
+        if validators.is_fresh(&info) {
+            return Ok(ImageResponse::not_modified(info));
+        }
+        if let Ok(file) = crate::thumb::load_or_generate(&picture, rendition) {
+            Ok(ImageResponse::new(info, ContentType::PNG, file))
         } else {
             Err(NotFound(format!("File '{}' was not found.", &picture.path)))
         }
@@ -65,9 +187,34 @@ fn thumb(img_id: i32) -> Result<Vec<u8>, NotFound<String>> {
     }
 }
 
+/// Sizes that are not configured as rendition are only cached in memory, so arbitrary
+/// sizes cannot fill the disk and the database. Responds with `503 Service Unavailable`
+/// while too many of them are rendered at once.
+fn serve_resized(img_id: i32, rendition: &Rendition, validators: &CacheValidators, cacheability: Cacheability) -> Result<ImageResponse, Custom<String>> {
+    let picture = crate::database::provider::picture::by_id(&img_id)
+        .map_err(|_| Custom(Status::NotFound, format!("Picture with id {} was not found.", img_id)))?;
+    let info = rendition_info(&picture, rendition, cacheability);
+    if validators.is_fresh(&info) {
+        return Ok(ImageResponse::not_modified(info));
+    }
+    match crate::thumb::load_or_render_resized(&picture, rendition) {
+        Ok(data) => Ok(ImageResponse::new(info, ContentType::PNG, Cursor::new(data))),
+        Err(ThumbError::Busy) => Err(Custom(Status::ServiceUnavailable, "Too many pictures are resized right now. Please try again later.".to_string())),
+        Err(_) => Err(Custom(Status::NotFound, format!("File '{}' was not found.", &picture.path))),
+    }
+}
+
+fn rendition_info(picture: &Picture, rendition: &Rendition, cacheability: Cacheability) -> CacheInfo {
+    let etag = format!("{}-{}x{}-{}", &picture.sha1, rendition.width, rendition.height, rendition.fit.name());
+    CacheInfo::for_file(etag, &picture.path, cacheability)
+}
+
 #[get("/in_gallery/<gallery_id>")]
-fn in_gallery(gallery_id: i32) -> Result<Json<Vec<PictureData>>, NotFound<String>> {
+fn in_gallery(user: Option<LoginUser>, gallery_id: i32) -> Result<Json<Vec<PictureData>>, NotFound<String>> {
     if let Ok(gallery) = crate::database::provider::gallery::by_id(&gallery_id) {
+        if !can_see_gallery(&user, &gallery) {
+            return Err(NotFound(format!("Gallery {} not found.", gallery_id)));
+        }
         if let Ok(pictures) = crate::database::provider::picture::by_gallery(&gallery.id) {
             let pictures = pictures.into_iter().map(Into::into).collect();
             Ok(Json(pictures))
@@ -81,10 +228,11 @@ fn in_gallery(gallery_id: i32) -> Result<Json<Vec<PictureData>>, NotFound<String
 
 #[cfg(test)]
 mod tests {
-    use crate::database::model::{Picture, NewPicture, NewGallery};
-    use crate::net::picture::PictureData;
+    use crate::auth::permission::{ROLE_ADMIN, VISIBILITY_RESTRICTED};
+    use crate::database::model::{Picture, NewPicture, NewGallery, PictureMetadata};
+    use crate::net::picture::{PictureData, MetadataData};
     use rocket::local::Client;
-    use rocket::http::Status;
+    use rocket::http::{ContentType, Header, Status};
 
     fn setup() -> Client {
         crate::testing::setup_database();
@@ -103,6 +251,7 @@ mod tests {
             path: "/home/test/IMG_0001.png".to_string(),
             sha1: "".to_string(),
             filesize: 0,
+            external_id: "ext1.png".to_string(),
         };
         let picture_data: PictureData = picture.into();
         assert_eq!(&123, &picture_data.picture_id);
@@ -128,6 +277,7 @@ mod tests {
             path: "/1.png".to_string(),
             sha1: "".to_string(),
             filesize: 0,
+            external_id: "ext2.png".to_string(),
         }).unwrap();
         let mut response = client.get(format!("/picture/data/{}", &picture.id)).dispatch();
         let parsed: PictureData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
@@ -135,6 +285,117 @@ mod tests {
         assert_eq!(parsed, picture.into());
     }
 
+    #[test]
+    fn data_with_metadata() {
+        let client = setup();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.id,
+            format: "jpg".to_string(),
+            path: "/1.jpg".to_string(),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: "ext1.jpg".to_string(),
+        }).unwrap();
+        let metadata = PictureMetadata {
+            picture_id: picture.id,
+            taken_at: Some("2020:02:14 18:30:00".to_string()),
+            camera_make: Some("Canon".to_string()),
+            camera_model: Some("EOS 80D".to_string()),
+            lens: None,
+            exposure_time: Some("1/125".to_string()),
+            f_number: Some(4.0),
+            iso: Some(200),
+            focal_length: Some(35.0),
+            orientation: Some(1),
+            latitude: Some(48.8566),
+            longitude: Some(2.3522),
+        };
+        crate::database::provider::metadata::save(&metadata).unwrap();
+        let mut response = client.get(format!("/picture/data/{}", &picture.id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let parsed: PictureData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.metadata, Some(MetadataData::from(metadata)));
+    }
+
+    #[test]
+    fn raw_cache_headers() {
+        let client = setup();
+        let file = std::env::temp_dir().join("regal_raw_cache_headers.png");
+        std::fs::write(&file, b"not really a png").unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.id,
+            format: "png".to_string(),
+            path: file.to_str().unwrap().to_string(),
+            sha1: "0123456789abcdef".to_string(),
+            filesize: 16,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        let response = client.get(format!("/picture/raw/{}", &picture.id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        assert_eq!(response.headers().get_one("ETag"), Some("\"0123456789abcdef\""));
+        assert_eq!(response.headers().get_one("Cache-Control"), Some(crate::config::get().cache_control.as_str()));
+        let response = client.get(format!("/picture/raw/{}", &picture.id))
+            .header(Header::new("If-None-Match", "\"0123456789abcdef\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        let response = client.get(format!("/picture/raw/{}", &picture.id))
+            .header(Header::new("If-None-Match", "\"outdated\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn raw_range() {
+        let client = setup();
+        let file = std::env::temp_dir().join("regal_raw_range.png");
+        std::fs::write(&file, b"not really a png").unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.id,
+            format: "png".to_string(),
+            path: file.to_str().unwrap().to_string(),
+            sha1: "0123456789abcdef".to_string(),
+            filesize: 16,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        let mut response = client.get(format!("/picture/raw/{}", &picture.id))
+            .header(Header::new("Range", "bytes=4-9"))
+            .dispatch();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes 4-9/16"));
+        assert_eq!(response.body_string(), Some("really".to_string()));
+        let mut response = client.get(format!("/picture/raw/{}", &picture.id))
+            .header(Header::new("Range", "bytes=-3"))
+            .dispatch();
+        assert_eq!(response.status(), Status::PartialContent);
+        assert_eq!(response.body_string(), Some("png".to_string()));
+        let response = client.get(format!("/picture/raw/{}", &picture.id))
+            .header(Header::new("Range", "bytes=20-"))
+            .dispatch();
+        assert_eq!(response.status(), Status::RangeNotSatisfiable);
+        assert_eq!(response.headers().get_one("Content-Range"), Some("bytes */16"));
+        for ignored in &["bytes=0-1,5-6", "items=0-1", "bytes=9-4", "bytes=x-"] {
+            let mut response = client.get(format!("/picture/raw/{}", &picture.id))
+                .header(Header::new("Range", *ignored))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert_eq!(response.headers().get_one("Content-Range"), None);
+            assert_eq!(response.body_string(), Some("not really a png".to_string()));
+        }
+    }
+
     #[test]
     fn in_gallery() {
         let client = setup();
@@ -158,6 +419,7 @@ mod tests {
                 path: "/home/test/img1.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext3.png".to_string(),
             }).unwrap(),
             crate::testing::save_picture(&NewPicture {
                 name: "Img2".to_string(),
@@ -168,6 +430,7 @@ mod tests {
                 path: "/home/test/img2.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext4.png".to_string(),
             }).unwrap(),
             crate::testing::save_picture(&NewPicture {
                 name: "Img3".to_string(),
@@ -178,6 +441,7 @@ mod tests {
                 path: "/home/test/img3.png".to_string(),
                 sha1: "".to_string(),
                 filesize: 0,
+                external_id: "ext5.png".to_string(),
             }).unwrap(),
         ];
         let mut response = client.get(format!("/picture/in_gallery/{}", &gallery.id)).dispatch();
@@ -188,4 +452,58 @@ mod tests {
             assert!(parsed.contains(&picture.into()));
         }
     }
+
+    #[test]
+    fn restricted_picture_hidden() {
+        let client = setup();
+        let file = std::env::temp_dir().join("regal_restricted_picture.png");
+        std::fs::write(&file, b"not really a png").unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        crate::database::provider::gallery::set_visibility(&gallery.id, VISIBILITY_RESTRICTED).unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: gallery.id,
+            format: "png".to_string(),
+            path: file.to_str().unwrap().to_string(),
+            sha1: "0123456789abcdef".to_string(),
+            filesize: 16,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        let response = client.get(format!("/picture/raw/{}", &picture.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get(format!("/picture/in_gallery/{}", &gallery.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let response = client.get(format!("/picture/raw/{}", &picture.id))
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("private, no-cache"));
+    }
+
+    #[test]
+    fn resize_not_cached() {
+        let client = setup();
+        let file = std::env::temp_dir().join("regal_resize_not_cached.png");
+        image::RgbImage::new(40, 30).save(&file).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 40,
+            height: 30,
+            gallery_id: gallery.id,
+            format: "png".to_string(),
+            path: file.to_str().unwrap().to_string(),
+            sha1: "0123456789abcdef".to_string(),
+            filesize: 0,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        let mut response = client.get(format!("/picture/resize/{}?w=13&h=7&fit=fill", &picture.id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let resized = image::load_from_memory(&response.body_bytes().unwrap()).unwrap();
+        assert_eq!(image::GenericImageView::dimensions(&resized), (13, 7));
+        assert!(crate::database::provider::thumb::by_picture(&picture.id).unwrap().is_empty());
+    }
 }
\ No newline at end of file
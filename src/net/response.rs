@@ -0,0 +1,280 @@
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Conditional request headers sent by browsers that already cached an image.
+pub struct CacheValidators {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CacheValidators {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        Outcome::Success(CacheValidators {
+            if_none_match: headers.get_one("If-None-Match").map(ToString::to_string),
+            if_modified_since: headers.get_one("If-Modified-Since")
+                .and_then(|d| httpdate::parse_http_date(d).ok()),
+        })
+    }
+}
+
+impl CacheValidators {
+    /// Whether the cached copy of the browser is still up to date.
+    /// `If-None-Match` takes precedence over `If-Modified-Since` as required by RFC 7232.
+    pub fn is_fresh(&self, info: &CacheInfo) -> bool {
+        if let Some(if_none_match) = &self.if_none_match {
+            return if_none_match.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag.eq("*") || tag.eq(&info.quoted_etag()));
+        }
+        match (self.if_modified_since, info.last_modified) {
+            (Some(since), Some(modified)) => truncate_to_seconds(modified) <= since,
+            _ => false,
+        }
+    }
+}
+
+/// Who may cache an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cacheability {
+    /// Anyone may see the image, so shared caches may keep it as set by `Config.cache_control`.
+    Public,
+    /// Only the browser may keep the image and has to revalidate it before every use.
+    Private,
+}
+
+impl Cacheability {
+    fn header(&self) -> String {
+        match self {
+            Cacheability::Public => crate::config::get().cache_control.clone(),
+            Cacheability::Private => "private, no-cache".to_string(),
+        }
+    }
+}
+
+/// Validators describing the current version of an image.
+pub struct CacheInfo {
+    etag: String,
+    last_modified: Option<SystemTime>,
+    cacheability: Cacheability,
+}
+
+impl CacheInfo {
+    pub fn new(etag: String, last_modified: Option<SystemTime>, cacheability: Cacheability) -> Self {
+        CacheInfo {
+            etag,
+            last_modified,
+            cacheability,
+        }
+    }
+
+    /// Uses the hash and modification time of the file the image is built from.
+    pub fn for_file(etag: String, path: &str, cacheability: Cacheability) -> Self {
+        let last_modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        CacheInfo::new(etag, last_modified, cacheability)
+    }
+
+    fn quoted_etag(&self) -> String {
+        format!("\"{}\"", &self.etag)
+    }
+}
+
+/// Where an image is streamed from: a file or a rendition kept in memory.
+pub trait ImageBody: Read + Seek {}
+
+impl<T: Read + Seek> ImageBody for T {}
+
+/// Streams an image, answering `Range` requests with `206 Partial Content`.
+pub struct ImageResponse {
+    info: CacheInfo,
+    body: Option<(ContentType, Box<dyn ImageBody>)>,
+}
+
+impl ImageResponse {
+    pub fn new(info: CacheInfo, content_type: ContentType, body: impl ImageBody + 'static) -> Self {
+        ImageResponse {
+            info,
+            body: Some((content_type, Box::new(body))),
+        }
+    }
+
+    pub fn not_modified(info: CacheInfo) -> Self {
+        ImageResponse {
+            info,
+            body: None,
+        }
+    }
+}
+
+impl<'r> Responder<'r> for ImageResponse {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = Response::build();
+        response.raw_header("ETag", self.info.quoted_etag())
+            .raw_header("Cache-Control", self.info.cacheability.header())
+            .raw_header("Accept-Ranges", "bytes");
+        if let Some(last_modified) = self.info.last_modified {
+            response.raw_header("Last-Modified", httpdate::fmt_http_date(last_modified));
+        }
+        let (content_type, mut body) = match self.body {
+            Some(body) => body,
+            None => return response.status(Status::NotModified).ok(),
+        };
+        response.header(content_type);
+        let size = body.seek(SeekFrom::End(0))
+            .and_then(|size| body.seek(SeekFrom::Start(0)).map(|_| size))
+            .map_err(|_| Status::InternalServerError)?;
+        let range = request.headers().get_one("Range")
+            .filter(|_| if_range_matches(request, &self.info))
+            .and_then(|range| parse_range(range, size));
+        match range {
+            Some(ByteRange::Satisfiable(start, end)) => {
+                let window = BodyRange::new(body, start, end - start + 1)
+                    .map_err(|_| Status::InternalServerError)?;
+                response.status(Status::PartialContent)
+                    .raw_header("Content-Range", format!("bytes {}-{}/{}", start, end, size))
+                    .sized_body(window);
+            },
+            Some(ByteRange::Unsatisfiable) => {
+                response.status(Status::RangeNotSatisfiable)
+                    .raw_header("Content-Range", format!("bytes */{}", size));
+            },
+            None => {
+                response.sized_body(body);
+            },
+        }
+        response.ok()
+    }
+}
+
+/// A range is only served if the `If-Range` validator (if any) still matches.
+fn if_range_matches(request: &Request, info: &CacheInfo) -> bool {
+    match request.headers().get_one("If-Range") {
+        Some(if_range) => if_range.trim().eq(&info.quoted_etag()),
+        None => true,
+    }
+}
+
+/// Outcome of a well-formed `Range` header.
+enum ByteRange {
+    /// Inclusive start and end offsets within the file.
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=` range for a file of `size` bytes.
+/// Returns `None` for headers that have to be ignored as required by RFC 7233:
+/// malformed ones, other units and multiple ranges, which are not supported.
+fn parse_range(header: &str, size: u64) -> Option<ByteRange> {
+    let header = header.trim();
+    if !header.starts_with("bytes=") {
+        return None;
+    }
+    let spec = &header["bytes=".len()..];
+    if spec.contains(',') {
+        return None;
+    }
+    let mut parts = spec.splitn(2, '-');
+    let start = parts.next()?.trim();
+    let end = parts.next()?.trim();
+    let range = match (start.is_empty(), end.is_empty()) {
+        (true, false) => {
+            let suffix: u64 = end.parse().ok()?;
+            if suffix == 0 || size == 0 {
+                return Some(ByteRange::Unsatisfiable);
+            }
+            (size.saturating_sub(suffix), size - 1)
+        },
+        (false, true) => (start.parse().ok()?, size.saturating_sub(1)),
+        (false, false) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if start > end {
+                return None;
+            }
+            (start, end.min(size.saturating_sub(1)))
+        },
+        (true, true) => return None,
+    };
+    if range.0 >= size {
+        Some(ByteRange::Unsatisfiable)
+    } else {
+        Some(ByteRange::Satisfiable(range.0, range.1))
+    }
+}
+
+/// Window of `len` bytes starting at `start` within an image.
+/// Rocket determines the `Content-Length` of sized bodies by seeking, so the
+/// window translates all seeks to be relative to its own bounds.
+struct BodyRange {
+    body: Box<dyn ImageBody>,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl BodyRange {
+    fn new(mut body: Box<dyn ImageBody>, start: u64, len: u64) -> io::Result<Self> {
+        body.seek(SeekFrom::Start(start))?;
+        Ok(BodyRange {
+            body,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl Read for BodyRange {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        if remaining == 0 {
+            return Ok(0);
+        }
+        let max = remaining.min(buf.len() as u64) as usize;
+        let read = self.body.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for BodyRange {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::End(offset) => self.len as i64 + offset,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+        };
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of range"));
+        }
+        self.pos = (target as u64).min(self.len);
+        self.body.seek(SeekFrom::Start(self.start + self.pos))?;
+        Ok(self.pos)
+    }
+}
+
+/// Maps the format stored in `pictures.format` to its MIME type.
+pub fn content_type(format: &str) -> ContentType {
+    match format {
+        "png" => ContentType::PNG,
+        "jpg" | "jpeg" => ContentType::JPEG,
+        "gif" => ContentType::GIF,
+        "bmp" => ContentType::BMP,
+        "ico" => ContentType::Icon,
+        "tiff" => ContentType::TIFF,
+        "webp" => ContentType::WEBP,
+        _ => ContentType::Binary,
+    }
+}
+
+/// HTTP dates only have a precision of seconds.
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => UNIX_EPOCH + Duration::from_secs(duration.as_secs()),
+        Err(_) => time,
+    }
+}
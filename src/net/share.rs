@@ -0,0 +1,520 @@
+use rocket::Rocket;
+use rocket::http::{Cookie, Cookies, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status::NotFound;
+use rocket_contrib::json::Json;
+use crate::auth::login::{ClientInfo, LoginError, LoginUser};
+use crate::auth::permission;
+use crate::database::model::{Gallery, Picture, ShareLink};
+use crate::net::{api_error, ApiError};
+use crate::net::auth::authorize;
+use crate::net::csrf::{Csrf, CsrfForm};
+use crate::net::response::{Cacheability, CacheValidators, ImageResponse};
+use crate::share::{ShareError, ShareOptions, ShareTarget};
+use crate::thumb::Fit;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/share", routes![list, create, revoke, shared, gallery, thumb, preview, raw])
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ShareData {
+    share_id: i32,
+    gallery_id: Option<i32>,
+    picture_id: Option<i32>,
+    recursive: bool,
+    created: i64,
+    expires: Option<i64>,
+    has_password: bool,
+    allow_download: bool,
+}
+
+impl From<ShareLink> for ShareData {
+    fn from(link: ShareLink) -> Self {
+        ShareData {
+            share_id: link.id,
+            gallery_id: link.gallery_id,
+            picture_id: link.picture_id,
+            recursive: link.recursive,
+            created: link.created,
+            expires: link.expires,
+            has_password: link.password.is_some(),
+            allow_download: link.allow_download,
+        }
+    }
+}
+
+/// Only returned once, right after creating the link.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CreatedShareData {
+    token: String,
+    display: String,
+    data: ShareData,
+}
+
+/// What a share link shows. Exactly one of `gallery` and `picture` is set.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedData {
+    pub gallery: Option<SharedGalleryData>,
+    pub picture: Option<SharedPictureData>,
+    pub expires: Option<i64>,
+    pub allow_download: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedGalleryData {
+    pub gallery_id: i32,
+    pub gallery_name: String,
+    /// Only filled for recursive links.
+    pub galleries: Vec<SubGalleryData>,
+    pub pictures: Vec<SharedPictureData>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubGalleryData {
+    pub gallery_id: i32,
+    pub gallery_name: String,
+    pub data: String,
+    pub display: String,
+    pub thumb: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SharedPictureData {
+    pub picture_id: i32,
+    pub picture_name: String,
+    pub filename: String,
+    pub display: String,
+    pub thumb: String,
+    pub preview: String,
+    /// Only set if the link allows downloads.
+    pub raw: Option<String>,
+}
+
+impl SharedPictureData {
+    pub fn new(token: &str, link: &ShareLink, picture: Picture) -> Self {
+        SharedPictureData {
+            picture_id: picture.id,
+            filename: format!("{}.{}", &picture.name, &picture.format),
+            picture_name: picture.name,
+            display: format!("/web/share/{}/picture/{}", token, picture.id),
+            thumb: format!("/share/{}/thumb/{}", token, picture.id),
+            preview: format!("/share/{}/preview/{}", token, picture.id),
+            raw: if link.allow_download {
+                Some(format!("/share/{}/raw/{}", token, picture.id))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl SubGalleryData {
+    fn new(token: &str, gallery: Gallery) -> Self {
+        let thumb = match crate::database::provider::picture::find_thumb(&gallery.id, |_| true) {
+            Ok(Some(img)) => Some(format!("/share/{}/thumb/{}", token, img.id)),
+            _ => None,
+        };
+        SubGalleryData {
+            gallery_id: gallery.id,
+            gallery_name: gallery.name,
+            data: format!("/share/{}/gallery/{}", token, gallery.id),
+            display: format!("/web/share/{}/gallery/{}", token, gallery.id),
+            thumb,
+        }
+    }
+}
+
+pub fn shared_gallery(token: &str, link: &ShareLink, gallery: Gallery) -> Result<SharedGalleryData, ApiError> {
+    let galleries = if link.recursive {
+        crate::database::provider::gallery::by_parent(&gallery.id)
+            .map_err(|e| api_error(Status::InternalServerError, format!("Error loading galleries: {:?}", e)))?
+    } else {
+        vec![]
+    };
+    let pictures = crate::database::provider::picture::by_gallery(&gallery.id)
+        .map_err(|e| api_error(Status::InternalServerError, format!("Error loading pictures: {:?}", e)))?;
+    Ok(SharedGalleryData {
+        gallery_id: gallery.id,
+        gallery_name: gallery.name,
+        galleries: galleries.into_iter().map(|g| SubGalleryData::new(token, g)).collect(),
+        pictures: pictures.into_iter().map(|p| SharedPictureData::new(token, link, p)).collect(),
+    })
+}
+
+/// Password for a protected link, sent as `X-Share-Password` header.
+pub struct SharePassword(pub Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for SharePassword {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(SharePassword(request.headers().get_one("X-Share-Password").map(ToString::to_string)))
+    }
+}
+
+/// Remembers that the password of a link was entered in the browser. Private
+/// cookies are encrypted, so the link id can't be forged.
+pub fn unlock_cookie(link: &ShareLink) -> Cookie<'static> {
+    Cookie::new(format!("share_{}", link.id), link.id.to_string())
+}
+
+fn is_unlocked(cookies: &mut Cookies, link: &ShareLink) -> bool {
+    cookies.get_private(&format!("share_{}", link.id))
+        .map(|c| c.value() == link.id.to_string())
+        .unwrap_or(false)
+}
+
+/// Resolves `token` to its link. Responds with `404 Not Found` for unknown or
+/// expired links, `401 Unauthorized` if the link's password is missing or wrong
+/// and `429 Too Many Requests` after too many wrong passwords.
+pub fn open(token: &str, password: &SharePassword, client: &ClientInfo, cookies: &mut Cookies) -> Result<ShareLink, ApiError> {
+    let link = match crate::share::resolve(token) {
+        Ok(Some(link)) => link,
+        Ok(None) => return Err(api_error(Status::NotFound, "Share link not found")),
+        Err(e) => return Err(api_error(Status::InternalServerError, format!("Error loading share link: {:?}", e))),
+    };
+    if is_unlocked(cookies, &link) {
+        return Ok(link);
+    }
+    match crate::share::check_password(&link, password.0.as_ref().map(String::as_str), client) {
+        Ok(true) => Ok(link),
+        Ok(false) => Err(api_error(Status::Unauthorized, "Password required")),
+        Err(ShareError::Login(LoginError::Locked(seconds))) =>
+            Err(api_error(Status::TooManyRequests, format!("Too many wrong passwords. Please try again in {} seconds.", seconds))),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error checking password: {:?}", e))),
+    }
+}
+
+/// Loads a gallery, as long as the link covers it.
+pub fn covered_gallery(link: &ShareLink, gallery_id: &i32) -> Result<Gallery, ApiError> {
+    match crate::share::covers_gallery(link, gallery_id) {
+        Ok(true) => crate::database::provider::gallery::by_id(gallery_id)
+            .map_err(|_| api_error(Status::NotFound, format!("Gallery {} not found.", gallery_id))),
+        Ok(false) => Err(api_error(Status::NotFound, format!("Gallery {} not found.", gallery_id))),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error checking share link: {:?}", e))),
+    }
+}
+
+/// Loads a picture, as long as the link covers it.
+pub fn covered_picture(link: &ShareLink, picture_id: &i32) -> Result<Picture, ApiError> {
+    match crate::share::covers_picture(link, picture_id) {
+        Ok(true) => crate::database::provider::picture::by_id(picture_id)
+            .map_err(|_| api_error(Status::NotFound, format!("Picture with id {} was not found.", picture_id))),
+        Ok(false) => Err(api_error(Status::NotFound, format!("Picture with id {} was not found.", picture_id))),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error checking share link: {:?}", e))),
+    }
+}
+
+#[derive(FromForm)]
+struct NewShareForm {
+    gallery_id: Option<i32>,
+    picture_id: Option<i32>,
+    recursive: Option<bool>,
+    /// Days until the link expires. Links without it never expire.
+    expires_in: Option<u32>,
+    password: Option<String>,
+    allow_download: Option<bool>,
+}
+
+#[get("/")]
+fn list(user: Option<LoginUser>) -> Result<Json<Vec<ShareData>>, ApiError> {
+    let user = authorize(user, permission::can_manage_shares)?;
+    match crate::database::provider::share_link::by_creator(&user.id) {
+        Ok(links) => Ok(Json(links.into_iter().map(Into::into).collect())),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading share links: {:?}", e))),
+    }
+}
+
+#[post("/", data = "<form>")]
+fn create(user: Option<LoginUser>, form: CsrfForm<NewShareForm>) -> Result<Json<CreatedShareData>, ApiError> {
+    let (target, gallery_id) = match (form.gallery_id, form.picture_id) {
+        (Some(gallery_id), None) => match crate::database::provider::gallery::by_id(&gallery_id) {
+            Ok(_) => (ShareTarget::Gallery { gallery_id, recursive: form.recursive.unwrap_or(false) }, gallery_id),
+            Err(_) => return Err(api_error(Status::NotFound, format!("Gallery {} not found.", gallery_id))),
+        },
+        (None, Some(picture_id)) => match crate::database::provider::picture::by_id(&picture_id) {
+            Ok(picture) => (ShareTarget::Picture(picture_id), picture.gallery_id),
+            Err(_) => return Err(api_error(Status::NotFound, format!("Picture with id {} was not found.", picture_id))),
+        },
+        _ => return Err(api_error(Status::BadRequest, "Either gallery_id or picture_id is required")),
+    };
+    let user = authorize(user, |u| permission::can_edit_gallery(u, &gallery_id))?;
+    let options = ShareOptions {
+        lifetime: form.expires_in.map(|days| days as i64 * 24 * 60 * 60),
+        password: form.password.as_ref().map(String::as_str),
+        allow_download: form.allow_download.unwrap_or(false),
+    };
+    match crate::share::create(&user, &target, &options) {
+        Ok((link, token)) => Ok(Json(CreatedShareData {
+            display: format!("/web/share/{}", &token),
+            token,
+            data: link.into(),
+        })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error creating share link: {:?}", e))),
+    }
+}
+
+#[delete("/<share_id>")]
+fn revoke(user: Option<LoginUser>, _csrf: Csrf, share_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_manage_shares)?;
+    match crate::share::revoke(&user, &share_id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(api_error(Status::NotFound, format!("Share link [{}] not found", &share_id))),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error revoking share link: {:?}", e))),
+    }
+}
+
+#[get("/<token>")]
+fn shared(token: String, password: SharePassword, client: ClientInfo, mut cookies: Cookies) -> Result<Json<SharedData>, ApiError> {
+    let link = open(&token, &password, &client, &mut cookies)?;
+    let (gallery, picture) = match (link.gallery_id, link.picture_id) {
+        (Some(gallery_id), _) => (Some(shared_gallery(&token, &link, covered_gallery(&link, &gallery_id)?)?), None),
+        (_, Some(picture_id)) => (None, Some(SharedPictureData::new(&token, &link, covered_picture(&link, &picture_id)?))),
+        _ => return Err(api_error(Status::NotFound, "Share link not found")),
+    };
+    Ok(Json(SharedData {
+        gallery,
+        picture,
+        expires: link.expires,
+        allow_download: link.allow_download,
+    }))
+}
+
+#[get("/<token>/gallery/<gallery_id>")]
+fn gallery(token: String, gallery_id: i32, password: SharePassword, client: ClientInfo, mut cookies: Cookies) -> Result<Json<SharedGalleryData>, ApiError> {
+    let link = open(&token, &password, &client, &mut cookies)?;
+    let gallery = covered_gallery(&link, &gallery_id)?;
+    Ok(Json(shared_gallery(&token, &link, gallery)?))
+}
+
+#[get("/<token>/thumb/<img_id>")]
+fn thumb(token: String, img_id: i32, password: SharePassword, client: ClientInfo, mut cookies: Cookies, validators: CacheValidators) -> Result<ImageResponse, ApiError> {
+    let link = open(&token, &password, &client, &mut cookies)?;
+    covered_picture(&link, &img_id)?;
+    crate::net::picture::render_unchecked(img_id, &crate::thumb::thumb_rendition(), &validators, Cacheability::Private)
+        .map_err(|NotFound(e)| api_error(Status::NotFound, e))
+}
+
+#[get("/<token>/preview/<img_id>")]
+fn preview(token: String, img_id: i32, password: SharePassword, client: ClientInfo, mut cookies: Cookies, validators: CacheValidators) -> Result<ImageResponse, ApiError> {
+    let link = open(&token, &password, &client, &mut cookies)?;
+    covered_picture(&link, &img_id)?;
+    let rendition = crate::thumb::rendition("large").cloned()
+        .unwrap_or(crate::thumb::custom_rendition(1920, 1080, Fit::Contain));
+    crate::net::picture::render_unchecked(img_id, &rendition, &validators, Cacheability::Private)
+        .map_err(|NotFound(e)| api_error(Status::NotFound, e))
+}
+
+#[get("/<token>/raw/<img_id>")]
+fn raw(token: String, img_id: i32, password: SharePassword, client: ClientInfo, mut cookies: Cookies, validators: CacheValidators) -> Result<ImageResponse, ApiError> {
+    let link = open(&token, &password, &client, &mut cookies)?;
+    covered_picture(&link, &img_id)?;
+    if !link.allow_download {
+        return Err(api_error(Status::Forbidden, "Downloads are not allowed for this link"));
+    }
+    crate::net::picture::original(img_id, &validators, Cacheability::Private)
+        .map_err(|NotFound(e)| api_error(Status::NotFound, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::LoginUser;
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_VIEWER, VISIBILITY_RESTRICTED};
+    use crate::auth::token::TokenScope;
+    use crate::database::model::{Gallery, NewGallery, NewPicture, Picture};
+    use crate::net::share::{CreatedShareData, SharedData, SharedGalleryData};
+    use crate::share::{ShareOptions, ShareTarget};
+    use rocket::local::Client;
+    use rocket::http::{ContentType, Header, Status};
+
+    struct Fixture {
+        client: Client,
+        admin: LoginUser,
+        root: Gallery,
+        child: Gallery,
+        picture: Picture,
+    }
+
+    fn setup() -> Fixture {
+        crate::testing::setup_database();
+        let admin = LoginUser::from(crate::testing::save_user("admin", ROLE_ADMIN).unwrap());
+        let file = std::env::temp_dir().join("regal_shared_picture.png");
+        std::fs::write(&file, b"not really a png").unwrap();
+        let root = crate::testing::save_gallery_named("Root").unwrap();
+        crate::database::provider::gallery::set_visibility(&root.id, VISIBILITY_RESTRICTED).unwrap();
+        let child = crate::testing::save_gallery(&NewGallery {
+            name: "Child".to_string(),
+            directory: None,
+            parent: Some(root.id),
+        }).unwrap();
+        let picture = crate::testing::save_picture(&NewPicture {
+            name: "Img1".to_string(),
+            width: 0,
+            height: 0,
+            gallery_id: child.id,
+            format: "png".to_string(),
+            path: file.to_str().unwrap().to_string(),
+            sha1: "0123456789abcdef".to_string(),
+            filesize: 16,
+            external_id: "ext1.png".to_string(),
+        }).unwrap();
+        Fixture {
+            client: super::super::test_client(),
+            admin,
+            root,
+            child,
+            picture,
+        }
+    }
+
+    fn share(f: &Fixture, recursive: bool, options: ShareOptions) -> String {
+        crate::share::create(&f.admin, &ShareTarget::Gallery { gallery_id: f.root.id, recursive }, &options).unwrap().1
+    }
+
+    fn options() -> ShareOptions<'static> {
+        ShareOptions {
+            lifetime: None,
+            password: None,
+            allow_download: false,
+        }
+    }
+
+    #[test]
+    fn recursive_link_bypasses_visibility() {
+        let f = setup();
+        let token = share(&f, true, options());
+        let mut response = f.client.get(format!("/share/{}", &token)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let parsed: SharedData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        let gallery = parsed.gallery.unwrap();
+        assert_eq!(gallery.galleries.len(), 1);
+        assert_eq!(gallery.galleries[0].gallery_id, f.child.id);
+        let mut response = f.client.get(format!("/share/{}/gallery/{}", &token, f.child.id)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let parsed: SharedGalleryData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.pictures.len(), 1);
+        assert_eq!(parsed.pictures[0].raw, None);
+        let response = f.client.get(format!("/share/{}/raw/{}", &token, f.picture.id)).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = f.client.get(format!("/gallery/{}", f.child.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn flat_link_hides_sub_galleries() {
+        let f = setup();
+        let token = share(&f, false, ShareOptions {
+            allow_download: true,
+            ..options()
+        });
+        let mut response = f.client.get(format!("/share/{}", &token)).dispatch();
+        let parsed: SharedData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert!(parsed.gallery.unwrap().galleries.is_empty());
+        let response = f.client.get(format!("/share/{}/gallery/{}", &token, f.child.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = f.client.get(format!("/share/{}/raw/{}", &token, f.picture.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn password_protected_link() {
+        let f = setup();
+        let token = share(&f, true, ShareOptions {
+            password: Some("secret"),
+            allow_download: true,
+            ..options()
+        });
+        let response = f.client.get(format!("/share/{}", &token)).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = f.client.get(format!("/share/{}/raw/{}", &token, f.picture.id))
+            .header(Header::new("X-Share-Password", "wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = f.client.get(format!("/share/{}/raw/{}", &token, f.picture.id))
+            .header(Header::new("X-Share-Password", "secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Cache-Control"), Some("private, no-cache"));
+    }
+
+    #[test]
+    fn wrong_passwords_throttled() {
+        let f = setup();
+        let token = share(&f, true, ShareOptions {
+            password: Some("secret"),
+            ..options()
+        });
+        for _ in 0..crate::config::get().login_max_failures {
+            let response = f.client.get(format!("/share/{}", &token))
+                .header(Header::new("X-Share-Password", "wrong"))
+                .dispatch();
+            assert_eq!(response.status(), Status::Unauthorized);
+        }
+        let response = f.client.get(format!("/share/{}", &token))
+            .header(Header::new("X-Share-Password", "secret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn expired_link_not_found() {
+        let f = setup();
+        let token = share(&f, true, ShareOptions {
+            lifetime: Some(-1),
+            ..options()
+        });
+        let response = f.client.get(format!("/share/{}", &token)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn create_requires_admin() {
+        let f = setup();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let response = f.client.post("/share/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(user))
+            .body(format!("picture_id={}", f.picture.id))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let admin = crate::database::provider::user::by_id(&f.admin.id).unwrap();
+        let mut response = f.client.post("/share/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin))
+            .body(format!("picture_id={}&expires_in=7", f.picture.id))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let created: CreatedShareData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(created.data.picture_id, Some(f.picture.id));
+        let response = f.client.get(format!("/share/{}/thumb/{}", &created.token, f.picture.id)).dispatch();
+        assert_ne!(response.status(), Status::Unauthorized);
+        let response = f.client.get(format!("/share/{}/gallery/{}", &created.token, f.child.id)).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn read_token_cannot_manage_links() {
+        let f = setup();
+        let link = crate::share::create(&f.admin, &ShareTarget::Picture(f.picture.id), &options()).unwrap().0;
+        let (_, token) = crate::auth::token::create(&f.admin, "script", TokenScope::Read, None).unwrap();
+        let response = f.client.get("/share/")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = f.client.delete(format!("/share/{}", link.id))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(crate::database::provider::share_link::by_id(&link.id).is_ok());
+        let admin = crate::database::provider::user::by_id(&f.admin.id).unwrap();
+        let response = f.client.get("/share/")
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,516 @@
+use rocket::Rocket;
+use rocket::http::Status;
+use rocket::request::Form;
+use rocket_contrib::json::Json;
+use crate::auth::login::LoginUser;
+use crate::auth::permission;
+use crate::database::model::{NewTag, PictureTag, Tag, TagType};
+use crate::net::{api_error, ApiError};
+use crate::net::auth::{authorize, can_see_picture};
+use crate::net::csrf::{Csrf, CsrfForm};
+use crate::net::picture::PictureData;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/tag", routes![all, by_id, children, create, update, set_parent, merge, delete, pictures, attach, detach, attach_all, detach_all])
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TagData {
+    tag_id: i32,
+    tag_name: String,
+    tag_type: String,
+    parent: Option<i32>,
+    /// Names from the top level down to this tag, e.g. `Europe/France/Paris`.
+    path: String,
+    children: String,
+    picture_list: String,
+}
+
+impl From<Tag> for TagData {
+    fn from(tag: Tag) -> Self {
+        let path = crate::database::provider::tag::path(&tag).unwrap_or(tag.name.clone());
+        TagData {
+            tag_id: tag.id,
+            tag_type: tag.kind().name().to_string(),
+            tag_name: tag.name,
+            parent: tag.parent,
+            path,
+            children: format!("/tag/{}/children", tag.id),
+            picture_list: format!("/tag/{}/pictures", tag.id),
+        }
+    }
+}
+
+#[derive(FromForm)]
+struct TagQuery {
+    tag_type: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(FromForm)]
+struct NewTagForm {
+    name: String,
+    tag_type: Option<String>,
+    parent: Option<i32>,
+}
+
+#[derive(FromForm)]
+struct TagForm {
+    name: String,
+    tag_type: Option<String>,
+}
+
+#[derive(FromForm)]
+struct ParentForm {
+    /// Tags without parent move to the top level.
+    parent: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PictureIdsData {
+    picture_ids: Vec<i32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ChangedData {
+    /// Number of pictures that actually gained or lost the tag.
+    changed: usize,
+}
+
+fn load_tag(tag_id: &i32) -> Result<Tag, ApiError> {
+    crate::database::provider::tag::by_id(tag_id)
+        .map_err(|_| api_error(Status::NotFound, format!("Tag [{}] does not exists or could not be loaded", tag_id)))
+}
+
+fn tag_list(tags: Vec<Tag>) -> Json<Vec<TagData>> {
+    Json(tags.into_iter().map(Into::into).collect())
+}
+
+fn load_error(e: crate::database::Error) -> ApiError {
+    api_error(Status::InternalServerError, format!("Error loading tags: {:?}", e))
+}
+
+fn parse_type(tag_type: &Option<String>) -> Result<Option<TagType>, ApiError> {
+    match tag_type {
+        Some(tag_type) => TagType::from_name(tag_type)
+            .map(Some)
+            .ok_or(api_error(Status::BadRequest, format!("Unknown tag type '{}'", tag_type))),
+        None => Ok(None),
+    }
+}
+
+/// Names are trimmed and have to be unique among the tags with the same
+/// parent, ignoring the tag being renamed.
+fn check_name(name: &str, parent: Option<&i32>, tag_id: Option<i32>) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(api_error(Status::BadRequest, "Tag name must not be empty"));
+    }
+    if name.contains('/') {
+        return Err(api_error(Status::BadRequest, "Tag name must not contain '/'"));
+    }
+    match crate::database::provider::tag::by_name_in(name, parent) {
+        Ok(Some(existing)) if Some(existing.id) != tag_id => Err(api_error(Status::Conflict, format!("Tag '{}' already exists", name))),
+        Ok(_) => Ok(name.to_string()),
+        Err(e) => Err(load_error(e)),
+    }
+}
+
+/// The parent has to exist and can't be the tag itself or one of its descendants.
+fn check_parent(tag_id: Option<i32>, parent: Option<i32>) -> Result<(), ApiError> {
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+    load_tag(&parent)?;
+    if let Some(tag_id) = tag_id {
+        let descendants = crate::database::provider::tag::descendant_ids(&tag_id).map_err(load_error)?;
+        if descendants.contains(&parent) {
+            return Err(api_error(Status::BadRequest, "A tag cannot be moved below itself"));
+        }
+    }
+    Ok(())
+}
+
+/// Pictures the user can't see are reported as missing.
+fn check_pictures(user: &LoginUser, picture_ids: &[i32]) -> Result<(), ApiError> {
+    let user = Some(user.clone());
+    match picture_ids.iter().find(|id| !can_see_picture(&user, id)) {
+        Some(id) => Err(api_error(Status::NotFound, format!("Picture [{}] does not exists or could not be loaded", id))),
+        None => Ok(()),
+    }
+}
+
+/// Lists all tags, only the tags of one type or the tag at `path`.
+#[get("/?<query..>")]
+fn all(query: Form<TagQuery>) -> Result<Json<Vec<TagData>>, ApiError> {
+    let tag_type = parse_type(&query.tag_type)?;
+    let tags = match (&query.path, tag_type) {
+        (Some(path), _) => crate::database::provider::tag::by_path(path)
+            .map(|tag| tag.into_iter().filter(|t| tag_type.map_or(true, |tt| t.kind() == tt)).collect()),
+        (None, Some(tag_type)) => crate::database::provider::tag::by_type(&tag_type.id()),
+        (None, None) => crate::database::provider::tag::all(),
+    };
+    tags.map(tag_list).map_err(load_error)
+}
+
+#[get("/<tag_id>")]
+fn by_id(tag_id: i32) -> Result<Json<TagData>, ApiError> {
+    Ok(Json(load_tag(&tag_id)?.into()))
+}
+
+#[get("/<tag_id>/children")]
+fn children(tag_id: i32) -> Result<Json<Vec<TagData>>, ApiError> {
+    load_tag(&tag_id)?;
+    crate::database::provider::tag::children(&tag_id).map(tag_list).map_err(load_error)
+}
+
+#[post("/", data = "<form>")]
+fn create(user: Option<LoginUser>, form: CsrfForm<NewTagForm>) -> Result<Json<TagData>, ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    let tag_type = parse_type(&form.tag_type)?.unwrap_or(TagType::FreeForm);
+    check_parent(None, form.parent)?;
+    let name = check_name(&form.name, form.parent.as_ref(), None)?;
+    let new_tag = NewTag {
+        tag_type: tag_type.id(),
+        name: name.clone(),
+        parent: form.parent,
+    };
+    if let Err(e) = crate::database::provider::tag::insert(&new_tag) {
+        return Err(api_error(Status::InternalServerError, format!("Error creating tag: {:?}", e)));
+    }
+    match crate::database::provider::tag::by_name_in(&name, form.parent.as_ref()) {
+        Ok(Some(tag)) => Ok(Json(tag.into())),
+        Ok(None) => Err(api_error(Status::InternalServerError, "Created tag could not be loaded")),
+        Err(e) => Err(load_error(e)),
+    }
+}
+
+/// Renames the tag and changes its type if one is given.
+#[put("/<tag_id>", data = "<form>")]
+fn update(user: Option<LoginUser>, tag_id: i32, form: CsrfForm<TagForm>) -> Result<Json<TagData>, ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    let mut tag = load_tag(&tag_id)?;
+    let tag_type = parse_type(&form.tag_type)?;
+    let name = check_name(&form.name, tag.parent.as_ref(), Some(tag_id))?;
+    if let Err(e) = crate::database::provider::tag::rename(&tag_id, &name) {
+        return Err(api_error(Status::InternalServerError, format!("Error renaming tag: {:?}", e)));
+    }
+    tag.name = name;
+    if let Some(tag_type) = tag_type {
+        if let Err(e) = crate::database::provider::tag::set_type(&tag_id, tag_type.id()) {
+            return Err(api_error(Status::InternalServerError, format!("Error changing tag type: {:?}", e)));
+        }
+        tag.tag_type = tag_type.id();
+    }
+    Ok(Json(tag.into()))
+}
+
+/// Moves the tag with all its descendants below another tag.
+#[put("/<tag_id>/parent", data = "<form>")]
+fn set_parent(user: Option<LoginUser>, tag_id: i32, form: CsrfForm<ParentForm>) -> Result<Json<TagData>, ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    let mut tag = load_tag(&tag_id)?;
+    check_parent(Some(tag_id), form.parent)?;
+    check_name(&tag.name, form.parent.as_ref(), Some(tag_id))?;
+    match crate::database::provider::tag::set_parent(&tag_id, form.parent) {
+        Ok(_) => {
+            tag.parent = form.parent;
+            Ok(Json(tag.into()))
+        },
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error moving tag: {:?}", e))),
+    }
+}
+
+/// Moves all pictures and child tags of the tag to `target_id` and deletes the tag.
+/// Fails with `409 Conflict` if a child tag's name is already taken below the target.
+#[post("/<tag_id>/merge/<target_id>")]
+fn merge(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32, target_id: i32) -> Result<Json<TagData>, ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    load_tag(&tag_id)?;
+    let target = load_tag(&target_id)?;
+    let descendants = crate::database::provider::tag::descendant_ids(&tag_id).map_err(load_error)?;
+    if descendants.contains(&target_id) {
+        return Err(api_error(Status::BadRequest, "A tag cannot be merged into itself or its descendants"));
+    }
+    let children = crate::database::provider::tag::children(&tag_id).map_err(load_error)?;
+    for child in children {
+        match crate::database::provider::tag::by_name_in(&child.name, Some(&target_id)) {
+            Ok(Some(_)) => return Err(api_error(Status::Conflict, format!("Tag '{}' already exists below the target", &child.name))),
+            Ok(None) => {},
+            Err(e) => return Err(load_error(e)),
+        }
+    }
+    match crate::database::provider::tag::merge(&tag_id, &target_id) {
+        Ok(_) => Ok(Json(target.into())),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error merging tags: {:?}", e))),
+    }
+}
+
+/// Child tags of the deleted tag move up to its parent. Answers `409 Conflict`
+/// if a tag with the same name as one of them already exists there.
+#[delete("/<tag_id>")]
+fn delete(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32) -> Result<(), ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    let tag = load_tag(&tag_id)?;
+    let children = crate::database::provider::tag::children(&tag_id).map_err(load_error)?;
+    for child in children {
+        match crate::database::provider::tag::by_name_in(&child.name, tag.parent.as_ref()) {
+            Ok(Some(existing)) if existing.id != tag_id => return Err(api_error(Status::Conflict, format!("Tag '{}' already exists below the parent", &child.name))),
+            Ok(_) => {},
+            Err(e) => return Err(load_error(e)),
+        }
+    }
+    match crate::database::provider::tag::delete(&tag_id) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error deleting tag: {:?}", e))),
+    }
+}
+
+/// With `descendants=true`, pictures of all tags below this one are included.
+#[get("/<tag_id>/pictures?<descendants>")]
+fn pictures(user: Option<LoginUser>, tag_id: i32, descendants: Option<bool>) -> Result<Json<Vec<PictureData>>, ApiError> {
+    load_tag(&tag_id)?;
+    let tag_ids = if descendants.unwrap_or(false) {
+        crate::database::provider::tag::descendant_ids(&tag_id).map_err(load_error)?
+    } else {
+        vec![tag_id]
+    };
+    match crate::database::provider::picture_tag::pictures_by_tags(&tag_ids) {
+        Ok(pictures) => Ok(Json(pictures.into_iter()
+            .filter(|p| can_see_picture(&user, &p.id))
+            .map(Into::into)
+            .collect())),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading pictures: {:?}", e))),
+    }
+}
+
+#[put("/<tag_id>/picture/<picture_id>")]
+fn attach(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32, picture_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_edit_tags)?;
+    load_tag(&tag_id)?;
+    check_pictures(&user, &[picture_id])?;
+    let picture_tag = PictureTag {
+        tag_id,
+        picture_id,
+    };
+    match crate::database::provider::picture_tag::attach(&picture_tag) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error attaching tag: {:?}", e))),
+    }
+}
+
+#[delete("/<tag_id>/picture/<picture_id>")]
+fn detach(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32, picture_id: i32) -> Result<(), ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    match crate::database::provider::picture_tag::detach(&tag_id, &picture_id) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error detaching tag: {:?}", e))),
+    }
+}
+
+#[post("/<tag_id>/attach", data = "<data>")]
+fn attach_all(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32, data: Json<PictureIdsData>) -> Result<Json<ChangedData>, ApiError> {
+    let user = authorize(user, permission::can_edit_tags)?;
+    load_tag(&tag_id)?;
+    check_pictures(&user, &data.picture_ids)?;
+    match crate::database::provider::picture_tag::attach_all(&tag_id, &data.picture_ids) {
+        Ok(changed) => Ok(Json(ChangedData { changed })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error attaching tag: {:?}", e))),
+    }
+}
+
+#[post("/<tag_id>/detach", data = "<data>")]
+fn detach_all(user: Option<LoginUser>, _csrf: Csrf, tag_id: i32, data: Json<PictureIdsData>) -> Result<Json<ChangedData>, ApiError> {
+    authorize(user, permission::can_edit_tags)?;
+    match crate::database::provider::picture_tag::detach_all(&tag_id, &data.picture_ids) {
+        Ok(changed) => Ok(Json(ChangedData { changed })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error detaching tag: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::{ROLE_EDITOR, ROLE_VIEWER};
+    use crate::database::model::{NewPicture, Picture, PictureTag, User};
+    use crate::net::picture::PictureData;
+    use crate::net::tag::{ChangedData, TagData};
+    use rocket::http::{ContentType, Status};
+    use rocket::local::Client;
+
+    fn setup() -> Client {
+        crate::testing::setup_database();
+        super::super::test_client()
+    }
+
+    fn save_picture(gallery_id: i32, name: &str) -> Picture {
+        crate::testing::save_picture(&NewPicture {
+            name: name.to_string(),
+            width: 0,
+            height: 0,
+            gallery_id,
+            format: "png".to_string(),
+            path: format!("/{}.png", name),
+            sha1: "".to_string(),
+            filesize: 0,
+            external_id: format!("{}.png", name),
+        }).unwrap()
+    }
+
+    #[test]
+    fn create_and_rename() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let mut response = client.post("/tag/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Paris")
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let created: TagData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(created.tag_name, "Paris");
+        let response = client.post("/tag/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Paris")
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        let mut response = client.put(format!("/tag/{}", &created.tag_id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Lyon")
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        let renamed: TagData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(renamed.tag_name, "Lyon");
+        assert_eq!(renamed.tag_id, created.tag_id);
+    }
+
+    #[test]
+    fn viewer_cannot_create() {
+        let client = setup();
+        let viewer = crate::testing::save_user("viewer", ROLE_VIEWER).unwrap();
+        let response = client.post("/tag/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body("name=Paris")
+            .private_cookie(crate::testing::session_cookie(viewer))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn bulk_attach() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let first = save_picture(gallery.id, "first");
+        let second = save_picture(gallery.id, "second");
+        let tag = crate::testing::save_tag_named("Tag1").unwrap();
+        let mut response = client.post(format!("/tag/{}/attach", &tag.id))
+            .header(ContentType::JSON)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(format!("{{\"picture_ids\": [{}, {}]}}", &first.id, &second.id))
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch();
+        let changed: ChangedData = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(changed.changed, 2);
+        let mut response = client.get(format!("/tag/{}/pictures", &tag.id)).dispatch();
+        let parsed: Vec<PictureData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed, vec![first.clone().into(), second.into()]);
+        let response = client.delete(format!("/tag/{}/picture/{}", &tag.id, &first.id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let mut response = client.get(format!("/tag/{}/pictures", &tag.id)).dispatch();
+        let parsed: Vec<PictureData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed.len(), 1);
+    }
+
+    fn create_tag(client: &Client, editor: &User, body: &str) -> TagData {
+        let mut response = client.post("/tag/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(body.to_string())
+            .private_cookie(crate::testing::session_cookie(editor.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn hierarchy() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let picture = save_picture(gallery.id, "first");
+        let europe = create_tag(&client, &editor, "name=Europe&tag_type=place");
+        let france = create_tag(&client, &editor, &format!("name=France&tag_type=place&parent={}", &europe.tag_id));
+        assert_eq!(france.tag_type, "place");
+        assert_eq!(france.path, "Europe/France");
+        crate::database::provider::picture_tag::attach(&PictureTag {
+            tag_id: france.tag_id,
+            picture_id: picture.id,
+        }).unwrap();
+        let mut response = client.get(format!("/tag/{}/pictures", &europe.tag_id)).dispatch();
+        let parsed: Vec<PictureData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert!(parsed.is_empty());
+        let mut response = client.get(format!("/tag/{}/pictures?descendants=true", &europe.tag_id)).dispatch();
+        let parsed: Vec<PictureData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed, vec![picture.into()]);
+        let mut response = client.get("/tag/?path=Europe/France").dispatch();
+        let parsed: Vec<TagData> = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(parsed, vec![france]);
+        let response = client.put(format!("/tag/{}/parent", &europe.tag_id))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(format!("parent={}", &parsed[0].tag_id))
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn merge_conflicting_children() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let source = create_tag(&client, &editor, "name=France&tag_type=place");
+        let target = create_tag(&client, &editor, "name=Frankreich&tag_type=place");
+        create_tag(&client, &editor, &format!("name=Paris&tag_type=place&parent={}", &source.tag_id));
+        create_tag(&client, &editor, &format!("name=Paris&tag_type=place&parent={}", &target.tag_id));
+        let response = client.post(format!("/tag/{}/merge/{}", &source.tag_id, &target.tag_id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        assert!(crate::database::provider::tag::by_id(&source.tag_id).is_ok());
+    }
+
+    #[test]
+    fn delete_conflicting_children() {
+        let client = setup();
+        let editor = crate::testing::save_user("editor", ROLE_EDITOR).unwrap();
+        let france = create_tag(&client, &editor, "name=France&tag_type=place");
+        create_tag(&client, &editor, "name=Paris&tag_type=place");
+        create_tag(&client, &editor, &format!("name=Paris&tag_type=place&parent={}", &france.tag_id));
+        let response = client.delete(format!("/tag/{}", &france.tag_id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(editor))
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        assert!(crate::database::provider::tag::by_id(&france.tag_id).is_ok());
+    }
+}
\ No newline at end of file
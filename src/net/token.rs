@@ -0,0 +1,192 @@
+use rocket::Rocket;
+use rocket::http::Status;
+use rocket_contrib::json::Json;
+use crate::auth::login::LoginUser;
+use crate::auth::permission;
+use crate::auth::token::TokenScope;
+use crate::database::model::ApiToken;
+use crate::net::{api_error, ApiError};
+use crate::net::auth::authorize;
+use crate::net::csrf::{Csrf, CsrfForm};
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/token", routes![list, create, revoke])
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TokenData {
+    token_id: i32,
+    name: String,
+    scope: String,
+    created: i64,
+    expires: Option<i64>,
+    last_used: Option<i64>,
+}
+
+impl From<ApiToken> for TokenData {
+    fn from(token: ApiToken) -> Self {
+        TokenData {
+            token_id: token.id,
+            name: token.name,
+            scope: token.scope,
+            created: token.created,
+            expires: token.expires,
+            last_used: token.last_used,
+        }
+    }
+}
+
+/// Only returned once, right after creating the token.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CreatedTokenData {
+    token: String,
+    data: TokenData,
+}
+
+#[derive(FromForm)]
+struct NewTokenForm {
+    name: String,
+    scope: String,
+    /// Days until the token expires. Tokens without it never expire.
+    expires_in: Option<u32>,
+}
+
+#[get("/")]
+fn list(user: Option<LoginUser>) -> Result<Json<Vec<TokenData>>, ApiError> {
+    let user = authorize(user, permission::can_manage_tokens)?;
+    match crate::database::provider::api_token::by_user(&user.id) {
+        Ok(tokens) => Ok(Json(tokens.into_iter().map(Into::into).collect())),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error loading tokens: {:?}", e))),
+    }
+}
+
+#[post("/", data = "<form>")]
+fn create(user: Option<LoginUser>, form: CsrfForm<NewTokenForm>) -> Result<Json<CreatedTokenData>, ApiError> {
+    let user = authorize(user, permission::can_manage_tokens)?;
+    let scope = TokenScope::from_name(&form.scope)
+        .ok_or(api_error(Status::BadRequest, format!("Unknown scope '{}'", &form.scope)))?;
+    let lifetime = form.expires_in.map(|days| days as i64 * 24 * 60 * 60);
+    match crate::auth::token::create(&user, &form.name, scope, lifetime) {
+        Ok((stored, token)) => Ok(Json(CreatedTokenData {
+            token,
+            data: stored.into(),
+        })),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error creating token: {:?}", e))),
+    }
+}
+
+#[delete("/<token_id>")]
+fn revoke(user: Option<LoginUser>, _csrf: Csrf, token_id: i32) -> Result<(), ApiError> {
+    let user = authorize(user, permission::can_manage_tokens)?;
+    match crate::auth::token::revoke(&user, &token_id) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(api_error(Status::NotFound, format!("Token [{}] not found", &token_id))),
+        Err(e) => Err(api_error(Status::InternalServerError, format!("Error revoking token: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::{ROLE_ADMIN, VISIBILITY_LOGGED_IN};
+    use crate::net::token::CreatedTokenData;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::Client;
+
+    fn setup() -> Client {
+        crate::testing::setup_database();
+        super::super::test_client()
+    }
+
+    fn create_token(client: &Client, scope: &str) -> CreatedTokenData {
+        let admin = crate::database::provider::user::by_username("admin").unwrap().unwrap();
+        let mut response = client.post("/token/")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .body(format!("name=script&scope={}", scope))
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        serde_json::from_str(&response.body_string().unwrap()).unwrap()
+    }
+
+    fn bearer(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    #[test]
+    fn read_token() {
+        let client = setup();
+        crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        crate::database::provider::gallery::set_visibility(&gallery.id, VISIBILITY_LOGGED_IN).unwrap();
+        let created = create_token(&client, "read");
+        let response = client.get(format!("/gallery/{}", &gallery.id))
+            .header(bearer(&created.token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(bearer(&created.token))
+            .body("name=Gal2")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.post("/token/")
+            .header(ContentType::Form)
+            .header(bearer(&created.token))
+            .body("name=other&scope=full")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn upload_token_creates_gallery() {
+        let client = setup();
+        crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let created = create_token(&client, "upload");
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(bearer(&created.token))
+            .body("name=Gal1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(crate::database::provider::gallery::by_name("Gal1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn basic_auth_needs_csrf_token() {
+        let client = setup();
+        crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(Header::new("Authorization", "Basic YWRtaW46c2VjcmV0"))
+            .body("name=Gal1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(bearer("rgl_unknown"))
+            .body("name=Gal1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn revoked_token_rejected() {
+        let client = setup();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let created = create_token(&client, "full");
+        let response = client.delete(format!("/token/{}", &created.data.token_id))
+            .header(crate::testing::csrf_cookie())
+            .header(crate::testing::csrf_header())
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.post("/gallery/new")
+            .header(ContentType::Form)
+            .header(bearer(&created.token))
+            .body("name=Gal1")
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
\ No newline at end of file
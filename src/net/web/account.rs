@@ -0,0 +1,183 @@
+use askama::Template;
+use rocket::Rocket;
+use rocket::response::Redirect;
+use rocket::response::status::NotFound;
+use crate::auth::registration;
+use crate::auth::registration::RegistrationError;
+use crate::net::csrf::{CsrfForm, CsrfToken};
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/web", routes![register, register_submit, verify, reset, reset_submit, reset_password, reset_password_submit])
+}
+
+#[derive(Template)]
+#[template(path = "web/register.html")]
+struct RegisterPage {
+    error: String,
+    csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "web/reset_request.html")]
+struct ResetRequestPage {
+    csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "web/reset_password.html")]
+struct ResetPasswordPage {
+    error: String,
+    csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "web/message.html")]
+struct MessagePage {
+    title: &'static str,
+    message: &'static str,
+}
+
+#[derive(FromForm)]
+struct Registration {
+    username: String,
+    email: String,
+    password: String,
+}
+
+#[derive(FromForm)]
+struct ResetRequest {
+    email: String,
+}
+
+#[derive(FromForm)]
+struct NewPassword {
+    password: String,
+}
+
+fn registration_enabled() -> Result<(), NotFound<&'static str>> {
+    if crate::config::get().registration && crate::mail::enabled() {
+        Ok(())
+    } else {
+        Err(NotFound("Registration is disabled"))
+    }
+}
+
+fn reset_enabled() -> Result<(), NotFound<&'static str>> {
+    if crate::mail::enabled() {
+        Ok(())
+    } else {
+        Err(NotFound("Password reset is disabled"))
+    }
+}
+
+#[get("/register")]
+fn register(csrf: CsrfToken) -> Result<RegisterPage, NotFound<&'static str>> {
+    registration_enabled()?;
+    Ok(RegisterPage {
+        error: String::new(),
+        csrf_token: csrf.0,
+    })
+}
+
+#[post("/register", data = "<form>")]
+fn register_submit(form: CsrfForm<Registration>, csrf: CsrfToken) -> Result<Result<MessagePage, RegisterPage>, NotFound<&'static str>> {
+    registration_enabled()?;
+    if form.username.is_empty() || form.email.is_empty() || form.password.is_empty() {
+        return Ok(Err(RegisterPage {
+            error: "Please fill in all fields.".to_string(),
+            csrf_token: csrf.0,
+        }));
+    }
+    Ok(match registration::register_and_send(&form.username, &form.email, &form.password) {
+        Ok(_) => Ok(MessagePage {
+            title: "Registration",
+            message: "We sent you a mail. Please follow the link in it to verify your email address.",
+        }),
+        Err(RegistrationError::InvalidUsername) => Err(RegisterPage {
+            error: "Usernames must not contain an @.".to_string(),
+            csrf_token: csrf.0,
+        }),
+        Err(RegistrationError::AlreadyExists) => Err(RegisterPage {
+            error: "Username or email address already taken.".to_string(),
+            csrf_token: csrf.0,
+        }),
+        Err(e) => {
+            eprintln!("Cannot register user {}: {:?}", &form.username, e);
+            Err(RegisterPage {
+                error: "Registration failed. Please try again later.".to_string(),
+                csrf_token: csrf.0,
+            })
+        },
+    })
+}
+
+#[get("/verify/<token>")]
+fn verify(token: String) -> Result<Redirect, MessagePage> {
+    match registration::verify(&token) {
+        Ok(_) => Ok(Redirect::to("/web/login")),
+        Err(_) => Err(MessagePage {
+            title: "Verification",
+            message: "This verification link is invalid or was already used.",
+        }),
+    }
+}
+
+#[get("/reset")]
+fn reset(csrf: CsrfToken) -> Result<ResetRequestPage, NotFound<&'static str>> {
+    reset_enabled()?;
+    Ok(ResetRequestPage {
+        csrf_token: csrf.0,
+    })
+}
+
+#[post("/reset", data = "<form>")]
+fn reset_submit(form: CsrfForm<ResetRequest>) -> Result<MessagePage, NotFound<&'static str>> {
+    reset_enabled()?;
+    let result = registration::request_password_reset(&form.email)
+        .and_then(|token| match token {
+            Some(token) => registration::send_password_reset(&form.email, &token),
+            None => Ok(()),
+        });
+    if let Err(e) = result {
+        eprintln!("Cannot reset password for {}: {:?}", &form.email, e);
+    }
+    // Same answer for unknown addresses, so this can't be used to find accounts
+    Ok(MessagePage {
+        title: "Reset password",
+        message: "If an account with this email address exists, we sent you a mail with a link to choose a new password.",
+    })
+}
+
+#[get("/reset/<_token>")]
+fn reset_password(_token: String, csrf: CsrfToken) -> Result<ResetPasswordPage, NotFound<&'static str>> {
+    reset_enabled()?;
+    Ok(ResetPasswordPage {
+        error: String::new(),
+        csrf_token: csrf.0,
+    })
+}
+
+#[post("/reset/<token>", data = "<form>")]
+fn reset_password_submit(token: String, form: CsrfForm<NewPassword>, csrf: CsrfToken) -> Result<Result<Redirect, ResetPasswordPage>, NotFound<&'static str>> {
+    reset_enabled()?;
+    if form.password.is_empty() {
+        return Ok(Err(ResetPasswordPage {
+            error: "Please enter a password.".to_string(),
+            csrf_token: csrf.0,
+        }));
+    }
+    Ok(match registration::reset_password(&token, &form.password) {
+        Ok(_) => Ok(Redirect::to("/web/login")),
+        Err(RegistrationError::InvalidToken) => Err(ResetPasswordPage {
+            error: "This link is invalid or expired.".to_string(),
+            csrf_token: csrf.0,
+        }),
+        Err(e) => {
+            eprintln!("Cannot reset password: {:?}", e);
+            Err(ResetPasswordPage {
+                error: "Resetting the password failed. Please try again later.".to_string(),
+                csrf_token: csrf.0,
+            })
+        },
+    })
+}
\ No newline at end of file
@@ -1,10 +1,16 @@
 use askama::Template;
 use rocket::Rocket;
-use rocket::response::status::NotFound;
+use rocket::response::Redirect;
+use rocket::http::Status;
+use rocket::response::status::{Custom, NotFound};
+use crate::auth::login::LoginUser;
 use crate::database::model::Gallery;
+use crate::auth::permission;
+use crate::net::auth::can_see_gallery;
+use crate::net::csrf::CsrfToken;
 
 pub fn mount(rocket: Rocket) -> Rocket {
-    rocket.mount("/web/gallery", routes![by_id, new])
+    rocket.mount("/web/gallery", routes![by_id, new, new_logged_out])
 }
 
 #[derive(Template)]
@@ -32,19 +38,31 @@ impl From<Gallery> for GalleryPage {
 }
 
 #[get("/<gallery_id>")]
-fn by_id(gallery_id: i32) -> Result<GalleryPage, NotFound<String>> {
-    if let Ok(gallery) = crate::database::provider::gallery::by_id(&gallery_id) {
-        Ok(gallery.into())
-    } else {
-        Err(NotFound(format!("Gallery {} not found.", gallery_id)))
+fn by_id(user: Option<LoginUser>, gallery_id: i32) -> Result<Result<GalleryPage, Redirect>, NotFound<String>> {
+    match crate::database::provider::gallery::by_id(&gallery_id) {
+        Ok(ref gallery) if can_see_gallery(&user, gallery) => Ok(Ok(gallery.clone().into())),
+        Ok(_) if user.is_none() => Ok(Err(super::login_redirect(&format!("/web/gallery/{}", gallery_id)))),
+        _ => Err(NotFound(format!("Gallery {} not found.", gallery_id))),
     }
 }
 
 #[derive(Template)]
 #[template(path = "web/gallery_new.html")]
-struct NewGalleryPage {}
+struct NewGalleryPage {
+    csrf_token: String,
+}
 
 #[get("/new")]
-fn new() -> NewGalleryPage {
-    NewGalleryPage {}
+fn new(user: LoginUser, csrf: CsrfToken) -> Result<NewGalleryPage, Custom<&'static str>> {
+    match permission::can_create_gallery(&user) {
+        Ok(true) => Ok(NewGalleryPage {
+            csrf_token: csrf.0,
+        }),
+        _ => Err(Custom(Status::Forbidden, "You may not create galleries")),
+    }
+}
+
+#[get("/new", rank = 2)]
+fn new_logged_out() -> Redirect {
+    super::login_redirect("/web/gallery/new")
 }
\ No newline at end of file
@@ -1,18 +1,26 @@
 use askama::Template;
 use rocket::Rocket;
 use rocket_contrib::serve::StaticFiles;
-use crate::auth::login::{LoginUser, LoginIdentifier};
+use crate::auth::login::{ClientInfo, LoginError, LoginUser, LoginIdentifier};
+use crate::net::csrf::{CsrfForm, CsrfToken};
 use rocket::response::Redirect;
-use rocket::request::{FromForm, Form};
-use rocket::http::{Cookie, Cookies};
+use rocket::request::{FormItems, FromForm};
+use rocket::http::{Cookie, Cookies, SameSite};
+use rocket::http::uri::Uri;
 
+mod account;
 mod gallery;
 mod picture;
+mod share;
+mod totp;
 
 pub fn mount(rocket: Rocket) -> Rocket {
-    let rocket = rocket.mount("/web", routes![index, login_logged_in, login, login_check]);
+    let rocket = rocket.mount("/web", routes![index, login_logged_in, login, login_check, login_totp, login_totp_check, logout, logout_all]);
+    let rocket = account::mount(rocket);
     let rocket = gallery::mount(rocket);
     let rocket = picture::mount(rocket);
+    let rocket = share::mount(rocket);
+    let rocket = totp::mount(rocket);
     rocket.mount("/static", StaticFiles::from("web"))
 }
 
@@ -27,31 +35,310 @@ fn index() -> Index {
 
 #[derive(Template)]
 #[template(path = "web/login.html")]
-struct LoginPage;
+struct LoginPage {
+    error: String,
+    next: String,
+    csrf_token: String,
+}
+
+/// Redirects to the login page, coming back to `next` after logging in.
+pub fn login_redirect(next: &str) -> Redirect {
+    Redirect::to(format!("/web/login?next={}", Uri::percent_encode(next)))
+}
+
+/// `Lax` keeps users logged in when following links from other sites, but
+/// leaves the session out of cross-site form posts.
+fn session_cookie(session_id: String) -> Cookie<'static> {
+    Cookie::build("session", session_id)
+        .path("/")
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .finish()
+}
+
+/// Only allows local paths as redirect targets after logging in. Browsers treat
+/// `\` like `/` and drop tabs and line breaks, so `/\host` or `/<tab>/host`
+/// would leave the site just like `//host`.
+fn redirect_target(next: &Option<String>) -> String {
+    match next {
+        Some(next) if is_local_path(next) => next.clone(),
+        _ => "/".to_string(),
+    }
+}
+
+fn is_local_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.starts_with("/\\")
+        && !path.contains("://")
+        && !path.chars().any(char::is_control)
+}
+
+#[get("/login?<next>")]
+fn login_logged_in(_user: LoginUser, next: Option<String>) -> Redirect {
+    Redirect::to(redirect_target(&next))
+}
+
+#[get("/login?<next>", rank = 2)]
+fn login(next: Option<String>, csrf: CsrfToken) -> LoginPage {
+    LoginPage {
+        error: String::new(),
+        next: redirect_target(&next),
+        csrf_token: csrf.0,
+    }
+}
 
-#[get("/login")]
-fn login_logged_in(_user: LoginUser) -> Redirect {
-    Redirect::to("/")
+#[derive(Template)]
+#[template(path = "web/login_totp.html")]
+struct LoginTotpPage {
+    error: String,
+    next: String,
+    csrf_token: String,
+}
+
+/// Seconds a user has to enter the TOTP code after entering the password.
+const PENDING_LOGIN_LIFETIME: i64 = 5 * 60;
+
+/// Remembers a user that entered the correct password but still has to enter
+/// a TOTP code. Private cookies are encrypted, so the user id can't be forged.
+fn set_pending_login(cookies: &mut Cookies, user_id: i32) {
+    let value = format!("{}:{}", user_id, crate::auth::now() + PENDING_LOGIN_LIFETIME);
+    cookies.add_private(Cookie::new("pending_login", value));
 }
 
-#[get("/login", rank = 2)]
-fn login() -> LoginPage {
-    LoginPage
+fn pending_login(cookies: &mut Cookies) -> Option<i32> {
+    let cookie = cookies.get_private("pending_login")?;
+    let mut parts = cookie.value().splitn(2, ':');
+    let user_id = parts.next()?.parse::<i32>().ok()?;
+    let expires = parts.next()?.parse::<i64>().ok()?;
+    if expires > crate::auth::now() {
+        Some(user_id)
+    } else {
+        None
+    }
 }
 
 #[derive(FromForm)]
 struct Credentials {
-    username: String,
+    login: String,
     password: String,
+    next: Option<String>,
 }
 
 #[post("/login", data = "<credentials>")]
-fn login_check(credentials: Form<Credentials>, mut cookies: Cookies) -> Redirect {
-    match crate::auth::login::create_session(LoginIdentifier::Username(credentials.username.clone()), &credentials.password) {
+fn login_check(credentials: CsrfForm<Credentials>, client: ClientInfo, csrf: CsrfToken, mut cookies: Cookies) -> Result<Redirect, LoginPage> {
+    let next = redirect_target(&credentials.next);
+    let error = match crate::auth::login::create_session(LoginIdentifier::from(credentials.login.as_str()), &credentials.password, &client) {
         Ok(Some(s)) => {
-            cookies.add_private(Cookie::new("session", s));
-            Redirect::to("/")
+            cookies.add_private(session_cookie(s));
+            return Ok(Redirect::to(next));
+        },
+        Ok(None) => "Wrong username, email or password.".to_string(),
+        Err(LoginError::SecondFactorRequired(user_id)) => {
+            set_pending_login(&mut cookies, user_id);
+            return Ok(Redirect::to(format!("/web/login/totp?next={}", Uri::percent_encode(&next))));
         },
-        _ => Redirect::to("/web/login"),
+        Err(LoginError::NotVerified) => "Please verify your email address first.".to_string(),
+        Err(LoginError::Locked(seconds)) => format!("Too many failed logins. Please try again in {} seconds.", seconds),
+        Err(e) => {
+            eprintln!("Cannot log in: {:?}", e);
+            "Login failed. Please try again later.".to_string()
+        },
+    };
+    Err(LoginPage {
+        error,
+        next,
+        csrf_token: csrf.0,
+    })
+}
+
+#[get("/login/totp?<next>")]
+fn login_totp(next: Option<String>, csrf: CsrfToken) -> LoginTotpPage {
+    LoginTotpPage {
+        error: String::new(),
+        next: redirect_target(&next),
+        csrf_token: csrf.0,
+    }
+}
+
+#[derive(FromForm)]
+struct TotpCode {
+    code: String,
+    next: Option<String>,
+}
+
+#[post("/login/totp", data = "<form>")]
+fn login_totp_check(form: CsrfForm<TotpCode>, client: ClientInfo, csrf: CsrfToken, mut cookies: Cookies) -> Result<Redirect, LoginTotpPage> {
+    let next = redirect_target(&form.next);
+    let user = match pending_login(&mut cookies).map(|id| crate::database::provider::user::by_id(&id)) {
+        Some(Ok(user)) => user,
+        _ => return Ok(login_redirect(&next)),
+    };
+    let result = crate::auth::totp::verify_login(&user, &form.code, &client)
+        .and_then(|valid| if valid {
+            crate::auth::login::start_session(&user, &client).map(Some)
+        } else {
+            Ok(None)
+        });
+    let error = match result {
+        Ok(Some(s)) => {
+            cookies.remove_private(Cookie::named("pending_login"));
+            cookies.add_private(session_cookie(s));
+            return Ok(Redirect::to(next));
+        },
+        Ok(None) => "Wrong code.".to_string(),
+        Err(LoginError::Locked(seconds)) => format!("Too many failed logins. Please try again in {} seconds.", seconds),
+        Err(e) => {
+            eprintln!("Cannot log in {}: {:?}", &user.username, e);
+            "Login failed. Please try again later.".to_string()
+        },
+    };
+    Err(LoginTotpPage {
+        error,
+        next,
+        csrf_token: csrf.0,
+    })
+}
+
+/// Logging out takes no fields besides the CSRF token checked by `CsrfForm`.
+struct LogoutForm;
+
+impl<'f> FromForm<'f> for LogoutForm {
+    type Error = ();
+
+    fn from_form(_items: &mut FormItems<'f>, _strict: bool) -> Result<Self, Self::Error> {
+        Ok(LogoutForm)
+    }
+}
+
+#[post("/logout", data = "<_form>")]
+fn logout(_form: CsrfForm<LogoutForm>, mut cookies: Cookies) -> Redirect {
+    if let Some(session) = cookies.get_private("session") {
+        if let Err(e) = crate::auth::login::end_session(session.value()) {
+            eprintln!("Cannot end session: {:?}", e);
+        }
+        cookies.remove_private(session);
+    }
+    Redirect::to("/web/login")
+}
+
+#[post("/logout_all", data = "<_form>")]
+fn logout_all(user: Option<LoginUser>, _form: CsrfForm<LogoutForm>, mut cookies: Cookies) -> Redirect {
+    if let Some(user) = user {
+        if let Err(e) = crate::auth::login::end_all_sessions(&user) {
+            eprintln!("Cannot end sessions of user {}: {:?}", &user.id, e);
+        }
+    }
+    cookies.remove_private(Cookie::named("session"));
+    Redirect::to("/web/login")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::permission::ROLE_VIEWER;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::Client;
+
+    fn setup() -> Client {
+        crate::testing::setup_database();
+        let user = crate::testing::save_user("user", ROLE_VIEWER).unwrap();
+        let password = crate::auth::login::hash_pw("secret").unwrap();
+        crate::database::provider::user::update_password(&user.id, &password).unwrap();
+        super::super::test_client()
+    }
+
+    #[test]
+    fn login_by_email_redirects_to_next() {
+        let client = setup();
+        let response = client.post("/web/login")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body(format!("login=user%40example.com&password=secret&next=%2Fweb%2Fgallery%2F1&csrf_token={}", crate::testing::CSRF_TOKEN))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/web/gallery/1"));
+    }
+
+    #[test]
+    fn login_rejects_foreign_redirect() {
+        let client = setup();
+        for next in &["%2F%2Fexample.com", "%2F%5Cexample.com", "%2F%09%2Fexample.com", "https%3A%2F%2Fexample.com", "%2Fredirect%3Fto%3Dhttps%3A%2F%2Fexample.com"] {
+            let response = client.post("/web/login")
+                .header(ContentType::Form)
+                .header(crate::testing::csrf_cookie())
+                .body(format!("login=user&password=secret&next={}&csrf_token={}", next, crate::testing::CSRF_TOKEN))
+                .dispatch();
+            assert_eq!(response.headers().get_one("Location"), Some("/"));
+        }
+    }
+
+    #[test]
+    fn failed_login_shows_error() {
+        let client = setup();
+        for login in vec!["user", "nobody"] {
+            let mut response = client.post("/web/login")
+                .header(ContentType::Form)
+                .header(crate::testing::csrf_cookie())
+                .body(format!("login={}&password=wrong&csrf_token={}", login, crate::testing::CSRF_TOKEN))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert!(response.body_string().unwrap().contains("Wrong username, email or password."));
+        }
+    }
+
+    #[test]
+    fn login_requires_csrf_token() {
+        let client = setup();
+        let response = client.post("/web/login")
+            .header(ContentType::Form)
+            .body("login=user&password=secret")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.post("/web/login")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body("login=user&password=secret&csrf_token=forged")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn session_cookie_same_site() {
+        let client = setup();
+        let response = client.post("/web/login")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body(format!("login=user&password=secret&csrf_token={}", crate::testing::CSRF_TOKEN))
+            .dispatch();
+        let session = response.headers().get("Set-Cookie")
+            .find(|c| c.starts_with("session="))
+            .unwrap();
+        assert!(session.contains("SameSite=Lax"));
+    }
+
+    #[test]
+    fn logout_requires_post_with_csrf_token() {
+        let client = setup();
+        let user = crate::database::provider::user::by_username("user").unwrap().unwrap();
+        let session = crate::testing::session_cookie(user);
+        let session_id = session.value().to_string();
+        let response = client.get("/web/logout")
+            .private_cookie(session.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.post("/web/logout")
+            .header(ContentType::Form)
+            .private_cookie(session.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(crate::database::provider::session::by_id(&session_id).unwrap().is_some());
+        let response = client.post("/web/logout")
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body(format!("csrf_token={}", crate::testing::CSRF_TOKEN))
+            .private_cookie(session)
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert!(crate::database::provider::session::by_id(&session_id).unwrap().is_none());
     }
 }
\ No newline at end of file
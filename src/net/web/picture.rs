@@ -1,7 +1,10 @@
 use askama::Template;
 use rocket::Rocket;
+use rocket::response::Redirect;
 use rocket::response::status::NotFound;
-use crate::database::model::Picture;
+use crate::auth::login::LoginUser;
+use crate::database::model::{Picture, PictureMetadata};
+use crate::net::auth::can_see_picture;
 
 pub fn mount(rocket: Rocket) -> Rocket {
     rocket.mount("/web/picture", routes![by_id])
@@ -13,8 +16,15 @@ struct PicturePage {
     picture_id: i32,
     picture_name: String,
     raw: String,
+    preview: String,
     gallery: String,
     filename: String,
+    details: Vec<Detail>,
+}
+
+struct Detail {
+    label: &'static str,
+    value: String,
 }
 
 impl From<Picture> for PicturePage {
@@ -23,20 +33,54 @@ impl From<Picture> for PicturePage {
         let filename = format!("{}.{}", &picture.name, &picture.format);
         let name = picture.name;
         let raw = format!("/picture/raw/{}", &id);
+        let preview = match crate::thumb::rendition("large") {
+            Some(_) => format!("/picture/rendition/{}/large", &id),
+            None => raw.clone(),
+        };
+        let details = match crate::database::provider::metadata::by_picture(&id) {
+            Ok(Some(meta)) => details(meta),
+            _ => vec![],
+        };
         PicturePage {
             picture_id: id,
             picture_name: name,
             raw,
+            preview,
             gallery: format!("/web/gallery/{}", &picture.gallery_id),
             filename,
+            details,
         }
     }
 }
 
+fn details(meta: PictureMetadata) -> Vec<Detail> {
+    let camera = match (meta.camera_make, meta.camera_model) {
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    let location = match (meta.latitude, meta.longitude) {
+        (Some(lat), Some(lon)) => Some(format!("{:.6}, {:.6}", lat, lon)),
+        _ => None,
+    };
+    vec![
+        ("Taken", meta.taken_at),
+        ("Camera", camera),
+        ("Lens", meta.lens),
+        ("Exposure", meta.exposure_time.map(|e| format!("{} s", e))),
+        ("Aperture", meta.f_number.map(|f| format!("f/{:.1}", f))),
+        ("ISO", meta.iso.map(|i| i.to_string())),
+        ("Focal length", meta.focal_length.map(|f| format!("{} mm", f))),
+        ("Location", location),
+    ].into_iter()
+        .filter_map(|(label, value)| value.map(|value| Detail { label, value }))
+        .collect()
+}
+
 #[get("/<id>")]
-fn by_id(id: i32) -> Result<PicturePage, NotFound<String>> {
+fn by_id(user: Option<LoginUser>, id: i32) -> Result<Result<PicturePage, Redirect>, NotFound<String>> {
     match crate::database::provider::picture::by_id(&id) {
-        Ok(picture) => Ok(picture.into()),
+        Ok(picture) if can_see_picture(&user, &id) => Ok(Ok(picture.into())),
+        Ok(_) if user.is_none() => Ok(Err(super::login_redirect(&format!("/web/picture/{}", id)))),
         _ => Err(NotFound(format!("Picture {} not found", &id))),
     }
 }
@@ -0,0 +1,212 @@
+use askama::Template;
+use rocket::Rocket;
+use rocket::http::{Cookies, Status};
+use rocket::response::Redirect;
+use rocket::response::status::NotFound;
+use crate::auth::login::{ClientInfo, LoginError};
+use crate::database::model::ShareLink;
+use crate::net::csrf::{CsrfForm, CsrfToken};
+use crate::net::share::{SharedPictureData, SharePassword, SubGalleryData};
+use crate::share::ShareError;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/web/share", routes![shared, unlock, gallery, picture])
+}
+
+#[derive(Template)]
+#[template(path = "web/share_gallery.html")]
+struct SharedGalleryPage {
+    gallery_name: String,
+    parent: String,
+    galleries: Vec<SubGalleryData>,
+    pictures: Vec<SharedPictureData>,
+}
+
+#[derive(Template)]
+#[template(path = "web/share_picture.html")]
+struct SharedPicturePage {
+    picture_name: String,
+    preview: String,
+    raw: String,
+    filename: String,
+    gallery: String,
+}
+
+#[derive(Template)]
+#[template(path = "web/share_password.html")]
+struct SharePasswordPage {
+    token: String,
+    error: String,
+    csrf_token: String,
+}
+
+#[derive(Responder)]
+enum SharePage {
+    Gallery(SharedGalleryPage),
+    Picture(SharedPicturePage),
+    Password(SharePasswordPage),
+    NotFound(NotFound<String>),
+}
+
+#[derive(FromForm)]
+struct SharePasswordForm {
+    password: String,
+}
+
+/// Resolves the link, asking for its password unless it was already entered.
+fn open(token: &str, csrf: CsrfToken, cookies: &mut Cookies) -> Result<ShareLink, SharePage> {
+    // Without a password nothing is throttled, so the client doesn't matter.
+    match crate::net::share::open(token, &SharePassword(None), &ClientInfo::default(), cookies) {
+        Ok(link) => Ok(link),
+        Err(e) if e.0 == Status::Unauthorized => Err(SharePage::Password(SharePasswordPage {
+            token: token.to_string(),
+            error: String::new(),
+            csrf_token: csrf.0,
+        })),
+        Err(_) => Err(not_found()),
+    }
+}
+
+fn not_found() -> SharePage {
+    SharePage::NotFound(NotFound("Share link not found".to_string()))
+}
+
+fn gallery_page(token: &str, link: &ShareLink, gallery_id: i32) -> SharePage {
+    let gallery = match crate::net::share::covered_gallery(link, &gallery_id) {
+        Ok(gallery) => gallery,
+        Err(_) => return not_found(),
+    };
+    let parent = match gallery.parent {
+        Some(parent) if link.gallery_id != Some(gallery.id) => format!("/web/share/{}/gallery/{}", token, parent),
+        _ => String::new(),
+    };
+    match crate::net::share::shared_gallery(token, link, gallery) {
+        Ok(data) => SharePage::Gallery(SharedGalleryPage {
+            gallery_name: data.gallery_name,
+            parent,
+            galleries: data.galleries,
+            pictures: data.pictures,
+        }),
+        Err(_) => not_found(),
+    }
+}
+
+fn picture_page(token: &str, link: &ShareLink, picture_id: i32) -> SharePage {
+    let picture = match crate::net::share::covered_picture(link, &picture_id) {
+        Ok(picture) => picture,
+        Err(_) => return not_found(),
+    };
+    let gallery = if link.gallery_id.is_some() {
+        format!("/web/share/{}/gallery/{}", token, picture.gallery_id)
+    } else {
+        String::new()
+    };
+    let data = SharedPictureData::new(token, link, picture);
+    SharePage::Picture(SharedPicturePage {
+        picture_name: data.picture_name,
+        preview: data.preview,
+        raw: data.raw.unwrap_or(String::new()),
+        filename: data.filename,
+        gallery,
+    })
+}
+
+#[get("/<token>")]
+fn shared(token: String, csrf: CsrfToken, mut cookies: Cookies) -> SharePage {
+    let link = match open(&token, csrf, &mut cookies) {
+        Ok(link) => link,
+        Err(page) => return page,
+    };
+    match (link.gallery_id, link.picture_id) {
+        (Some(gallery_id), _) => gallery_page(&token, &link, gallery_id),
+        (_, Some(picture_id)) => picture_page(&token, &link, picture_id),
+        _ => not_found(),
+    }
+}
+
+#[post("/<token>", data = "<form>")]
+fn unlock(token: String, form: CsrfForm<SharePasswordForm>, csrf: CsrfToken, client: ClientInfo, mut cookies: Cookies) -> Result<Redirect, SharePage> {
+    let link = match crate::share::resolve(&token) {
+        Ok(Some(link)) => link,
+        _ => return Err(not_found()),
+    };
+    match crate::share::check_password(&link, Some(form.password.as_str()), &client) {
+        Ok(true) => {
+            cookies.add_private(crate::net::share::unlock_cookie(&link));
+            Ok(Redirect::to(format!("/web/share/{}", &token)))
+        },
+        Ok(false) => Err(SharePage::Password(SharePasswordPage {
+            token,
+            error: "Wrong password.".to_string(),
+            csrf_token: csrf.0,
+        })),
+        Err(ShareError::Login(LoginError::Locked(seconds))) => Err(SharePage::Password(SharePasswordPage {
+            token,
+            error: format!("Too many wrong passwords. Please try again in {} seconds.", seconds),
+            csrf_token: csrf.0,
+        })),
+        Err(e) => {
+            eprintln!("Cannot check password of share link {}: {:?}", &link.id, e);
+            Err(SharePage::Password(SharePasswordPage {
+                token,
+                error: "Please try again later.".to_string(),
+                csrf_token: csrf.0,
+            }))
+        },
+    }
+}
+
+#[get("/<token>/gallery/<gallery_id>")]
+fn gallery(token: String, gallery_id: i32, csrf: CsrfToken, mut cookies: Cookies) -> SharePage {
+    match open(&token, csrf, &mut cookies) {
+        Ok(link) => gallery_page(&token, &link, gallery_id),
+        Err(page) => page,
+    }
+}
+
+#[get("/<token>/picture/<picture_id>")]
+fn picture(token: String, picture_id: i32, csrf: CsrfToken, mut cookies: Cookies) -> SharePage {
+    match open(&token, csrf, &mut cookies) {
+        Ok(link) => picture_page(&token, &link, picture_id),
+        Err(page) => page,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::LoginUser;
+    use crate::auth::permission::ROLE_ADMIN;
+    use crate::share::{ShareOptions, ShareTarget};
+    use rocket::http::{ContentType, Status};
+
+    #[test]
+    fn password_unlocks_link() {
+        crate::testing::setup_database();
+        let admin = LoginUser::from(crate::testing::save_user("admin", ROLE_ADMIN).unwrap());
+        let gallery = crate::testing::save_gallery_named("Shared gallery").unwrap();
+        let options = ShareOptions {
+            lifetime: None,
+            password: Some("secret"),
+            allow_download: false,
+        };
+        let (_, token) = crate::share::create(&admin, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options).unwrap();
+        let client = super::super::super::test_client();
+        let mut response = client.get(format!("/web/share/{}", &token)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(!response.body_string().unwrap().contains("Shared gallery"));
+        let response = client.post(format!("/web/share/{}", &token))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body(format!("password=wrong&csrf_token={}", crate::testing::CSRF_TOKEN))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.post(format!("/web/share/{}", &token))
+            .header(ContentType::Form)
+            .header(crate::testing::csrf_cookie())
+            .body(format!("password=secret&csrf_token={}", crate::testing::CSRF_TOKEN))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let mut response = client.get(format!("/web/share/{}", &token)).dispatch();
+        assert!(response.body_string().unwrap().contains("Shared gallery"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,155 @@
+use askama::Template;
+use rocket::Rocket;
+use rocket::http::{Cookie, Cookies, Status};
+use rocket::response::Redirect;
+use rocket::response::status::Custom;
+use crate::auth::login::{ClientInfo, LoginError, LoginUser};
+use crate::auth::{permission, totp};
+use crate::database::model::User;
+use crate::net::csrf::{CsrfForm, CsrfToken};
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/web/totp", routes![settings, settings_logged_out, enable, disable])
+}
+
+#[derive(Template)]
+#[template(path = "web/totp.html")]
+struct TotpPage {
+    enabled: bool,
+    uri: String,
+    qr: String,
+    error: String,
+    csrf_token: String,
+}
+
+#[derive(Template)]
+#[template(path = "web/totp_codes.html")]
+struct RecoveryCodesPage {
+    codes: Vec<String>,
+}
+
+#[derive(FromForm)]
+struct TotpCode {
+    code: String,
+}
+
+/// Shows the enrollment page for `secret`. The secret is only kept in a private
+/// cookie until the user confirmed it with a valid code.
+fn enrollment(cookies: &mut Cookies, csrf: CsrfToken, username: &str, secret: String, error: &str) -> TotpPage {
+    let uri = totp::provisioning_uri(&secret, username);
+    let qr = totp::qr_svg(&uri).unwrap_or(String::new());
+    cookies.add_private(Cookie::new("totp_pending", secret));
+    TotpPage {
+        enabled: false,
+        uri,
+        qr,
+        error: error.to_string(),
+        csrf_token: csrf.0,
+    }
+}
+
+/// Loads the account, unless the request came with a token that may not change
+/// how the account is secured.
+fn account(user: &LoginUser) -> Result<User, Custom<String>> {
+    match permission::can_secure_account(user) {
+        Ok(true) => crate::database::provider::user::by_id(&user.id)
+            .map_err(|e| Custom(Status::NotFound, format!("Cannot load user: {:?}", e))),
+        _ => Err(Custom(Status::Forbidden, "Two-factor authentication can only be changed when logged in.".to_string())),
+    }
+}
+
+/// The page for a user who already turned TOTP on.
+fn enabled(csrf: CsrfToken, error: &str) -> TotpPage {
+    TotpPage {
+        enabled: true,
+        uri: String::new(),
+        qr: String::new(),
+        error: error.to_string(),
+        csrf_token: csrf.0,
+    }
+}
+
+#[get("/")]
+fn settings(user: LoginUser, csrf: CsrfToken, mut cookies: Cookies) -> Result<TotpPage, Custom<String>> {
+    let user = account(&user)?;
+    if user.totp_secret.is_some() {
+        Ok(enabled(csrf, ""))
+    } else {
+        Ok(enrollment(&mut cookies, csrf, &user.username, totp::generate_secret(), ""))
+    }
+}
+
+#[get("/", rank = 2)]
+fn settings_logged_out() -> Redirect {
+    super::login_redirect("/web/totp")
+}
+
+#[post("/", data = "<form>")]
+fn enable(user: LoginUser, form: CsrfForm<TotpCode>, csrf: CsrfToken, mut cookies: Cookies) -> Result<Result<RecoveryCodesPage, TotpPage>, Custom<String>> {
+    let user = account(&user)?;
+    if user.totp_secret.is_some() {
+        return Ok(Err(enabled(csrf, "Two-factor authentication is already enabled.")));
+    }
+    let secret = match cookies.get_private("totp_pending") {
+        Some(cookie) => cookie.value().to_string(),
+        None => return Ok(Err(enrollment(&mut cookies, csrf, &user.username, totp::generate_secret(), "Please scan the new code."))),
+    };
+    match totp::enable(&user, &secret, &form.code) {
+        Ok(Some(codes)) => {
+            cookies.remove_private(Cookie::named("totp_pending"));
+            Ok(Ok(RecoveryCodesPage {
+                codes,
+            }))
+        },
+        Ok(None) => Ok(Err(enrollment(&mut cookies, csrf, &user.username, secret, "Wrong code."))),
+        Err(LoginError::SecondFactorEnabled) => {
+            cookies.remove_private(Cookie::named("totp_pending"));
+            Ok(Err(enabled(csrf, "Two-factor authentication is already enabled.")))
+        },
+        Err(e) => Err(Custom(Status::NotFound, format!("Cannot enable two-factor authentication: {:?}", e))),
+    }
+}
+
+#[post("/disable", data = "<form>")]
+fn disable(user: LoginUser, form: CsrfForm<TotpCode>, client: ClientInfo, csrf: CsrfToken) -> Result<Result<Redirect, TotpPage>, Custom<String>> {
+    let user = account(&user)?;
+    match totp::verify_throttled(&user, &form.code, &client) {
+        Ok(true) => {
+            totp::disable(&user).map_err(|e| Custom(Status::NotFound, format!("Cannot disable two-factor authentication: {:?}", e)))?;
+            Ok(Ok(Redirect::to("/web/totp")))
+        },
+        Ok(false) => Ok(Err(enabled(csrf, "Wrong code."))),
+        Err(LoginError::Locked(seconds)) => Ok(Err(enabled(csrf, &format!("Too many failed logins. Please try again in {} seconds.", seconds)))),
+        Err(e) => Err(Custom(Status::NotFound, format!("Cannot disable two-factor authentication: {:?}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::LoginUser;
+    use crate::auth::permission::ROLE_ADMIN;
+    use crate::auth::token::TokenScope;
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn tokens_need_full_scope() {
+        crate::testing::setup_database();
+        let admin = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let (_, token) = crate::auth::token::create(&LoginUser::from(admin.clone()), "script", TokenScope::Read, None).unwrap();
+        let client = super::super::super::test_client();
+        let response = client.get("/web/totp")
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.post("/web/totp/disable")
+            .header(ContentType::Form)
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .body("code=000000")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.get("/web/totp")
+            .private_cookie(crate::testing::session_cookie(admin))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
\ No newline at end of file
@@ -1,14 +1,29 @@
+use std::collections::HashMap;
 use std::path::Path;
-use std::fs::DirEntry;
-use crate::database::model::{NewPicture, NewGallery};
+use std::fs::{DirEntry, File};
+use std::io::{BufReader, Read};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+use crate::database::model::{NewPicture, NewGallery, Gallery, Picture, PictureMetadata};
+use crate::metadata::ExifData;
 use serde::export::fmt::Debug;
 use crate::ScanDir;
 use sha::utils::{Digest, DigestExt};
-use image::{ImageError, GenericImageView};
+use image::{ImageError, ImageFormat};
 use colored::Colorize;
 use uuid::Uuid;
 
-static FORMATS: [&'static str; 8] = ["png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "webp"];
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+const INSERT_BATCH_SIZE: usize = 100;
+
+lazy_static! {
+    /// Files that are no supported pictures, with the size and modification time they
+    /// had when they were sniffed. They are skipped by later scans and watcher events
+    /// until they change.
+    static ref REJECTED: Mutex<HashMap<String, (u64, Option<SystemTime>)>> = Mutex::new(HashMap::new());
+}
 
 #[derive(Debug)]
 pub enum ScanError {
@@ -38,8 +53,166 @@ impl From<ImageError> for ScanError {
 
 pub type ScanResult<T> = Result<T, ScanError>;
 
-pub fn scan(scan_dir: &ScanDir, parent: Option<i32>) -> ScanResult<()> {
+struct Job {
+    file: String,
+    gallery: Arc<Gallery>,
+    existing: Option<Picture>,
+}
+
+enum Scanned {
+    New(Arc<Gallery>, NewPicture, Option<ExifData>),
+    Changed(Arc<Gallery>, i32, NewPicture, Option<ExifData>),
+    Mismatch(String, String),
+    Failed(String),
+}
+
+/// Worker pool hashing and decoding the picture files found while walking the
+/// scan directories. Results are written to the database in batched
+/// transactions by a single collector thread.
+struct Pipeline {
+    jobs: Sender<Job>,
+    workers: Vec<JoinHandle<()>>,
+    collector: JoinHandle<ScanResult<()>>,
+}
+
+impl Pipeline {
+    fn start(threads: usize) -> Self {
+        let (jobs, job_receiver) = channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (results, result_receiver) = channel::<Scanned>();
+        let workers = (0..threads.max(1)).map(|_| {
+            let job_receiver = job_receiver.clone();
+            let results = results.clone();
+            std::thread::spawn(move || work(job_receiver, results))
+        }).collect();
+        let collector = std::thread::spawn(move || collect(result_receiver));
+        Pipeline {
+            jobs,
+            workers,
+            collector,
+        }
+    }
+
+    fn submit_directory(&self, gallery: Gallery, files: Vec<String>) -> ScanResult<()> {
+        use crate::database::provider;
+        let mut existing: HashMap<String, Picture> = provider::picture::by_gallery(&gallery.id)?
+            .into_iter()
+            .map(|p| (p.path.clone(), p))
+            .collect();
+        let gallery = Arc::new(gallery);
+        for file in files {
+            let _ = self.jobs.send(Job {
+                existing: existing.remove(&file),
+                file,
+                gallery: gallery.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ScanResult<()> {
+        let Pipeline { jobs, workers, collector } = self;
+        drop(jobs);
+        for worker in workers {
+            worker.join().expect("Scan worker panicked");
+        }
+        collector.join().expect("Scan collector panicked")
+    }
+}
+
+fn work(jobs: Arc<Mutex<Receiver<Job>>>, results: Sender<Scanned>) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let Job { file, gallery, existing } = job;
+        let scanned = match check_file(&file, &gallery.id, existing.as_ref()) {
+            Ok(Some((new_picture, exif))) => {
+                if !extension_matches(&file, &new_picture.format) {
+                    let mismatch = Scanned::Mismatch(file.clone(), new_picture.format.clone());
+                    if results.send(mismatch).is_err() {
+                        return;
+                    }
+                }
+                match existing {
+                    Some(existing) => Scanned::Changed(gallery, existing.id, new_picture, exif),
+                    None => Scanned::New(gallery, new_picture, exif),
+                }
+            },
+            Ok(None) | Err(ScanError::UnknownFormat(_)) => continue,
+            Err(_) => Scanned::Failed(file),
+        };
+        if results.send(scanned).is_err() {
+            return;
+        }
+    }
+}
+
+fn collect(results: Receiver<Scanned>) -> ScanResult<()> {
     use crate::database::provider;
+    let mut batch: Vec<(Arc<Gallery>, NewPicture, Option<ExifData>)> = vec![];
+    let mut mismatches = 0;
+    for scanned in results.iter() {
+        match scanned {
+            Scanned::New(gallery, new_picture, exif) => {
+                batch.push((gallery, new_picture, exif));
+                if batch.len() >= INSERT_BATCH_SIZE {
+                    flush(&mut batch)?;
+                }
+            },
+            Scanned::Changed(gallery, picture_id, new_picture, exif) => {
+                println!("{} [{}] {}", "~".blue(), gallery.name.blue(), new_picture.name.blue());
+                provider::picture::update(&picture_id, &new_picture)?;
+                save_metadata(&picture_id, exif)?;
+            },
+            Scanned::Mismatch(file, format) => {
+                mismatches += 1;
+                eprintln!("{} [{}] is {}", "! Extension does not match content:".yellow(), file.yellow(), format.yellow());
+            },
+            Scanned::Failed(file) => eprintln!("{} [{}]", "! Error scanning file:".yellow(), file.yellow()),
+        }
+    }
+    if mismatches > 0 {
+        eprintln!("{} {}", "! Files with mismatching extension:".yellow(), mismatches);
+    }
+    flush(&mut batch)
+}
+
+fn flush(batch: &mut Vec<(Arc<Gallery>, NewPicture, Option<ExifData>)>) -> ScanResult<()> {
+    use crate::database::provider;
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let new_pictures: Vec<NewPicture> = batch.iter().map(|(_, p, _)| p.clone()).collect();
+    let inserted = provider::picture::insert_batch(&new_pictures)?;
+    let mut metadata: Vec<PictureMetadata> = vec![];
+    // Skipped pictures were added meanwhile, e.g. by the file watcher, which also saved their metadata
+    for ((gallery, new_picture, exif), inserted) in batch.drain(..).zip(inserted) {
+        if !inserted {
+            continue;
+        }
+        println!("{} [{}] {}", "+".green(), gallery.name.green(), new_picture.name.green());
+        if let Some(exif) = exif {
+            if let Some(picture) = provider::picture::by_path(&new_picture.path)? {
+                metadata.push(exif.into_metadata(picture.id));
+            }
+        }
+    }
+    provider::metadata::save_batch(&metadata)?;
+    Ok(())
+}
+
+fn save_metadata(picture_id: &i32, exif: Option<ExifData>) -> ScanResult<()> {
+    use crate::database::provider;
+    match exif {
+        Some(exif) => provider::metadata::save(&exif.into_metadata(picture_id.clone()))?,
+        None => provider::metadata::delete_by_picture(picture_id)?,
+    }
+    Ok(())
+}
+
+pub fn scan(scan_dir: &ScanDir, parent: Option<i32>) -> ScanResult<()> {
     let dir = &scan_dir.path;
     let gallery_id = match crate::database::provider::gallery::by_directory(dir).unwrap() {
         Some(gallery) => gallery.id,
@@ -57,47 +230,25 @@ pub fn scan(scan_dir: &ScanDir, parent: Option<i32>) -> ScanResult<()> {
 
     let found = files_in_directory(dir)?;
 
-    for file in found {
-        let img = provider::picture::by_path(&file)?;
-        if img.is_some() && img.clone().unwrap().filesize.eq(&(std::fs::metadata(&file)?.len() as i32)) {
-            continue;
-        }
-        let sha1 = sha::sha1::Sha1::default().digest(&std::fs::read(Path::new(&file)).unwrap()).to_hex();
-        if img.is_none() || img.unwrap().sha1.ne(&sha1) {
-            match scan_picture(&file, &gallery_id, sha1) {
-                Ok(img) => {
-                    println!("{} [{}] {}", "+".green(), gallery.name.green(), img.name.green());
-                    provider::picture::insert(&img)?;
-                },
-                Err(_) => eprintln!("{} [{}]", "! Error scanning file:".yellow(), file.yellow()),
-            }
-        }
-    }
-    Ok(())
+    let pipeline = Pipeline::start(crate::config::get().scan_threads);
+    pipeline.submit_directory(gallery, found)?;
+    pipeline.finish()
 }
 
 pub fn scan_recursively(scan_dir: &str, parents: &Vec<String>) -> ScanResult<()> {
+    let pipeline = Pipeline::start(crate::config::get().scan_threads);
+    let walked = walk(scan_dir, parents, &pipeline);
+    let finished = pipeline.finish();
+    walked.and(finished)
+}
+
+fn walk(scan_dir: &str, parents: &Vec<String>, pipeline: &Pipeline) -> ScanResult<()> {
     use crate::database::provider;
     let picture_files = files_in_directory(scan_dir)?;
     if !picture_files.is_empty() {
         create_parents(scan_dir, parents)?;
         let gallery = provider::gallery::by_directory(scan_dir)?.unwrap();
-        for picture_file in picture_files {
-            let img = provider::picture::by_path(&picture_file)?;
-            if img.is_some() && img.clone().unwrap().filesize.eq(&(std::fs::metadata(&picture_file)?.len() as i32)) {
-                continue;
-            }
-            let sha1 = sha::sha1::Sha1::default().digest(&std::fs::read(Path::new(&picture_file)).unwrap()).to_hex();
-            if img.is_none() || img.unwrap().sha1.ne(&sha1) {
-                let name = name_from_path(&picture_file);
-                println!("{} [{}] {}", "+".green(), gallery.name.green(), name.green());
-                if let Ok(new_picture) = scan_picture(&picture_file, &gallery.id, sha1) {
-                    provider::picture::insert(&new_picture)?;
-                } else {
-                    eprintln!("{} {}", "! Error scanning file:".yellow(), picture_file.yellow());
-                }
-            }
-        }
+        pipeline.submit_directory(gallery, picture_files)?;
     }
     let sub_dirs = directories_in_directory(scan_dir)?;
     if !sub_dirs.is_empty() {
@@ -105,12 +256,180 @@ pub fn scan_recursively(scan_dir: &str, parents: &Vec<String>) -> ScanResult<()>
         new_parents.push(scan_dir.to_string());
         for sub_dir in sub_dirs {
             println!("Checking dir {}", &sub_dir);
-            scan_recursively(&sub_dir, &new_parents)?;
+            walk(&sub_dir, &new_parents, pipeline)?;
         }
     }
     Ok(())
 }
 
+/// Scans a single file that lives in the directory `dir`.
+/// Missing galleries for `dir` and its `parents` are created on the way.
+/// Returns the stored picture if the file is a picture and was new or has changed.
+pub fn scan_single(file: &str, dir: &str, parents: &Vec<String>) -> ScanResult<Option<Picture>> {
+    use crate::database::provider;
+    let existing = provider::picture::by_path(file)?;
+    let gallery_id = match &existing {
+        Some(existing) => existing.gallery_id,
+        None => {
+            create_parents(dir, parents)?;
+            provider::gallery::by_directory(dir)?.unwrap().id
+        },
+    };
+    let (new_picture, exif) = match check_file(file, &gallery_id, existing.as_ref()) {
+        Ok(Some(scanned)) => scanned,
+        Ok(None) | Err(ScanError::UnknownFormat(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !extension_matches(file, &new_picture.format) {
+        eprintln!("{} [{}] is {}", "! Extension does not match content:".yellow(), file.yellow(), new_picture.format.yellow());
+    }
+    let gallery = provider::gallery::by_id(&gallery_id)?;
+    let picture = if let Some(existing) = existing {
+        println!("{} [{}] {}", "~".blue(), gallery.name.blue(), new_picture.name.blue());
+        provider::picture::update(&existing.id, &new_picture)?;
+        provider::picture::by_id(&existing.id)?
+    } else {
+        println!("{} [{}] {}", "+".green(), gallery.name.green(), new_picture.name.green());
+        provider::picture::insert(&new_picture)?;
+        provider::picture::by_path(file)?.unwrap()
+    };
+    save_metadata(&picture.id, exif)?;
+    Ok(Some(picture))
+}
+
+/// Sniffs, hashes and decodes `file` unless it is unchanged compared to `existing`.
+/// Files that are no supported pictures fail with `ScanError::UnknownFormat`.
+fn check_file(file: &str, gallery_id: &i32, existing: Option<&Picture>) -> ScanResult<Option<(NewPicture, Option<ExifData>)>> {
+    if let Some(existing) = existing {
+        if existing.filesize.eq(&(std::fs::metadata(file)?.len() as i32)) {
+            return Ok(None);
+        }
+    }
+    if existing.is_none() && is_rejected(file) {
+        return Err(ScanError::UnknownFormat(file.to_string()));
+    }
+    let (format, sha1) = match sniff_and_hash(file) {
+        Err(ScanError::UnknownFormat(file)) => {
+            reject(&file);
+            return Err(ScanError::UnknownFormat(file));
+        },
+        sniffed => sniffed?,
+    };
+    if existing.map(|e| e.sha1.eq(&sha1)).unwrap_or(false) {
+        return Ok(None);
+    }
+    let exif = crate::metadata::extract(file);
+    let orientation = exif.as_ref().and_then(|e| e.orientation);
+    Ok(Some((scan_picture(file, gallery_id, format, sha1, orientation)?, exif)))
+}
+
+fn file_stamp(file: &str) -> Option<(u64, Option<SystemTime>)> {
+    std::fs::metadata(file).ok().map(|m| (m.len(), m.modified().ok()))
+}
+
+fn is_rejected(file: &str) -> bool {
+    match REJECTED.lock().unwrap().get(file) {
+        Some(stamp) => file_stamp(file).map(|s| s.eq(stamp)).unwrap_or(false),
+        None => false,
+    }
+}
+
+fn reject(file: &str) {
+    if let Some(stamp) = file_stamp(file) {
+        REJECTED.lock().unwrap().insert(file.to_string(), stamp);
+    }
+}
+
+/// Detects the real format of `file` from the magic bytes of its first chunk
+/// and streams the whole file through the SHA-1 hasher without loading it
+/// into memory as a whole.
+fn sniff_and_hash(file: &str) -> ScanResult<(ImageFormat, String)> {
+    let mut reader = File::open(file)?;
+    let mut hasher = sha::sha1::Sha1::default();
+    let mut buffer = vec![0u8; HASH_CHUNK_SIZE];
+    let mut format = None;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if format.is_none() {
+            format = image::guess_format(&buffer[..read])
+                .ok()
+                .filter(|f| format_name(f).is_some());
+            if format.is_none() {
+                return Err(ScanError::UnknownFormat(file.to_string()));
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        hasher.digest(&buffer[..read]);
+    }
+    Ok((format.unwrap(), hasher.to_hex()))
+}
+
+/// Name of a supported picture format as stored in `pictures.format`.
+pub fn format_name(format: &ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("png"),
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::Bmp => Some("bmp"),
+        ImageFormat::Ico => Some("ico"),
+        ImageFormat::Tiff => Some("tiff"),
+        ImageFormat::WebP => Some("webp"),
+        _ => None,
+    }
+}
+
+/// Files without extension can't contradict the sniffed format, so they always match.
+fn extension_matches(file: &str, format: &str) -> bool {
+    let extension = match Path::new(file).extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension.to_lowercase(),
+        None => return true,
+    };
+    match extension.as_str() {
+        "jpeg" | "jpe" => format.eq("jpg"),
+        "tif" => format.eq("tiff"),
+        e => format.eq(e),
+    }
+}
+
+/// Removes the picture or gallery stored for `path` from the database.
+/// Galleries are removed together with all their pictures and sub galleries.
+pub fn remove_path(path: &str) -> Result<(), crate::database::Error> {
+    use crate::database::provider;
+    REJECTED.lock().unwrap().remove(path);
+    if let Some(picture) = provider::picture::by_path(path)? {
+        let gallery = provider::gallery::by_id(&picture.gallery_id)?;
+        remove_picture(&gallery, &picture)?;
+    } else if let Some(gallery) = provider::gallery::by_directory(path)? {
+        remove_gallery(&gallery)?;
+    }
+    Ok(())
+}
+
+fn remove_gallery(gallery: &Gallery) -> Result<(), crate::database::Error> {
+    use crate::database::provider;
+    for child in provider::gallery::by_parent(&gallery.id)? {
+        remove_gallery(&child)?;
+    }
+    for picture in provider::picture::by_gallery(&gallery.id)? {
+        remove_picture(gallery, &picture)?;
+    }
+    println!("{} [{}]", "-".red(), gallery.name.red());
+    crate::audit::record_local(crate::audit::GALLERY_REMOVE, crate::audit::Target::Gallery(gallery.id), gallery.directory.clone());
+    provider::gallery::delete(gallery)
+}
+
+fn remove_picture(gallery: &Gallery, picture: &Picture) -> Result<(), crate::database::Error> {
+    use crate::database::provider;
+    println!("{} [{}] {}", "-".red(), gallery.name.red(), &picture.name.red());
+    crate::thumb::remove(picture)?;
+    provider::metadata::delete_by_picture(&picture.id)?;
+    provider::picture_tag::delete_by_picture(&picture.id)?;
+    crate::audit::record_local(crate::audit::PICTURE_REMOVE, crate::audit::Target::Picture(picture.id), Some(picture.path.clone()));
+    provider::picture::delete(picture)
+}
+
 fn create_parents(dir: &str, parents: &Vec<String>) -> ScanResult<()> {
     use crate::database::provider;
     let mut last: Option<i32> = None;
@@ -149,13 +468,6 @@ fn files_in_directory(dir: &str) -> ScanResult<Vec<String>> {
         dir.into_iter()
             .filter(|d| d.file_type().unwrap().is_file())
             .map(|d| d.path().to_str().unwrap().to_string())
-            .filter(|p| {
-                if let Some(extension) = Path::new(p).extension() {
-                    FORMATS.contains(&extension.to_str().unwrap().to_lowercase().as_str())
-                } else {
-                    false
-                }
-            })
             .collect()
     )
 }
@@ -171,6 +483,7 @@ pub fn check_gallery(gallery_id: &i32) -> Result<(), crate::database::Error> {
     if let Some(path) = gallery.directory.clone() {
         let path = Path::new(&path);
         if !path.exists() {
+            crate::audit::record_local(crate::audit::GALLERY_REMOVE, crate::audit::Target::Gallery(gallery.id), gallery.directory.clone());
             crate::database::provider::gallery::delete(&gallery)?;
             return Ok(());
         }
@@ -179,20 +492,23 @@ pub fn check_gallery(gallery_id: &i32) -> Result<(), crate::database::Error> {
     for picture in pictures {
         let path = Path::new(&picture.path);
         if !path.exists() {
-            println!("{} [{}] {}", "-".red(), gallery.name.red(), &picture.name.red());
-            crate::database::provider::picture::delete(&picture)?;
+            remove_picture(&gallery, &picture)?;
         }
     }
     Ok(())
 }
 
-fn scan_picture(file: &str, gallery_id: &i32, sha1: String) -> ScanResult<NewPicture> {
+fn scan_picture(file: &str, gallery_id: &i32, image_format: ImageFormat, sha1: String, orientation: Option<i32>) -> ScanResult<NewPicture> {
     let path = Path::new(file);
     let name = path.file_stem().unwrap().to_str().unwrap().to_string();
-    let picture = image::open(path)?;
-    let width = picture.width() as i32;
-    let height = picture.height() as i32;
-    let format = path.extension().unwrap().to_str().unwrap().to_lowercase();
+    let reader = image::io::Reader::new(BufReader::new(File::open(path)?)).with_format(image_format);
+    let (mut width, mut height) = reader.into_dimensions()?;
+    if crate::metadata::swaps_dimensions(orientation) {
+        std::mem::swap(&mut width, &mut height);
+    }
+    let width = width as i32;
+    let height = height as i32;
+    let format = format_name(&image_format).unwrap().to_string();
     let filesize = std::fs::metadata(path)?.len() as i32;
     let external_id = format!("{}.{}", Uuid::new_v4().to_simple().encode_lower(&mut Uuid::encode_buffer()), &format);
     Ok(NewPicture {
@@ -206,4 +522,72 @@ fn scan_picture(file: &str, gallery_id: &i32, sha1: String) -> ScanResult<NewPic
         filesize,
         external_id,
     })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Job, Scanned};
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn extension_matches() {
+        assert!(super::extension_matches("/a/img.png", "png"));
+        assert!(super::extension_matches("/a/img.PNG", "png"));
+        assert!(super::extension_matches("/a/img.jpeg", "jpg"));
+        assert!(super::extension_matches("/a/img.jpe", "jpg"));
+        assert!(super::extension_matches("/a/img.tif", "tiff"));
+        assert!(!super::extension_matches("/a/img.jpg", "png"));
+        assert!(super::extension_matches("/a/img", "png"));
+    }
+
+    #[test]
+    fn reports_mismatch() {
+        crate::testing::setup_database();
+        let gallery = Arc::new(crate::testing::save_gallery_named("Gal1").unwrap());
+        let png = std::env::temp_dir().join("regal_scan_mismatch.png");
+        let jpg = std::env::temp_dir().join("regal_scan_mismatch.jpg");
+        image::RgbImage::new(4, 3).save(&png).unwrap();
+        std::fs::rename(&png, &jpg).unwrap();
+        let (jobs, job_receiver) = channel();
+        let (results, result_receiver) = channel();
+        jobs.send(Job {
+            file: jpg.to_str().unwrap().to_string(),
+            gallery: gallery.clone(),
+            existing: None,
+        }).unwrap();
+        drop(jobs);
+        super::work(Arc::new(Mutex::new(job_receiver)), results);
+        let scanned: Vec<Scanned> = result_receiver.iter().collect();
+        assert_eq!(scanned.len(), 2);
+        let mismatch = match &scanned[0] {
+            Scanned::Mismatch(file, format) => file.ends_with("regal_scan_mismatch.jpg") && format.eq("png"),
+            _ => false,
+        };
+        assert!(mismatch);
+        let new = match &scanned[1] {
+            Scanned::New(_, picture, _) => picture.format.eq("png"),
+            _ => false,
+        };
+        assert!(new);
+    }
+
+    #[test]
+    fn skips_rejected_files() {
+        crate::testing::setup_database();
+        let file = std::env::temp_dir().join("regal_scan_rejected.txt");
+        std::fs::write(&file, b"no picture").unwrap();
+        let file = file.to_str().unwrap();
+        let unknown = match super::check_file(file, &0, None) {
+            Err(super::ScanError::UnknownFormat(_)) => true,
+            _ => false,
+        };
+        assert!(unknown);
+        assert!(super::is_rejected(file));
+        std::fs::write(file, b"still no picture").unwrap();
+        assert!(!super::is_rejected(file));
+        super::reject(file);
+        super::remove_path(file).unwrap();
+        assert!(!super::is_rejected(file));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,349 @@
+use crate::auth::{now, random_string};
+use crate::auth::login::{hash_pw, verify_pw, ClientInfo, LoginError, LoginUser};
+use crate::auth::permission::{self, PermissionError};
+use crate::auth::throttle;
+use crate::database::model::{NewShareLink, ShareLink};
+use crate::database::provider;
+use sha::utils::{Digest, DigestExt};
+use std::collections::HashSet;
+
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug)]
+pub enum ShareError {
+    Database(crate::database::Error),
+    Login(LoginError),
+    Permission(PermissionError),
+}
+
+impl From<crate::database::Error> for ShareError {
+    fn from(e: crate::database::Error) -> Self {
+        ShareError::Database(e)
+    }
+}
+
+impl From<LoginError> for ShareError {
+    fn from(e: LoginError) -> Self {
+        ShareError::Login(e)
+    }
+}
+
+impl From<PermissionError> for ShareError {
+    fn from(e: PermissionError) -> Self {
+        ShareError::Permission(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ShareError>;
+
+pub enum ShareTarget {
+    Gallery {
+        gallery_id: i32,
+        recursive: bool,
+    },
+    Picture(i32),
+}
+
+pub struct ShareOptions<'a> {
+    /// Seconds until the link expires. Links without lifetime never expire.
+    pub lifetime: Option<i64>,
+    pub password: Option<&'a str>,
+    pub allow_download: bool,
+}
+
+/// Creates a share link. Returns the stored link and its token, which can't be
+/// recovered later.
+pub fn create(user: &LoginUser, target: &ShareTarget, options: &ShareOptions) -> Result<(ShareLink, String)> {
+    let token = random_string(TOKEN_LENGTH);
+    let (gallery_id, picture_id, recursive) = match target {
+        ShareTarget::Gallery { gallery_id, recursive } => (Some(*gallery_id), None, *recursive),
+        ShareTarget::Picture(picture_id) => (None, Some(*picture_id), false),
+    };
+    let password = match options.password {
+        Some(password) if !password.is_empty() => Some(hash_pw(password)?),
+        _ => None,
+    };
+    let now = now();
+    let link = provider::share_link::insert(&NewShareLink {
+        token_hash: hash_token(&token),
+        gallery_id,
+        picture_id,
+        recursive,
+        created_by: user.id,
+        created: now,
+        expires: options.lifetime.map(|l| now + l),
+        password,
+        allow_download: options.allow_download,
+    })?;
+    Ok((link, token))
+}
+
+/// Finds the link for `token`, unless it is unknown, expired or its creator
+/// couldn't share the target anymore.
+pub fn resolve(token: &str) -> Result<Option<ShareLink>> {
+    let link = match provider::share_link::by_hash(&hash_token(token))? {
+        Some(link) => link,
+        None => return Ok(None),
+    };
+    if link.expires.map(|e| e <= now()).unwrap_or(false) {
+        return Ok(None);
+    }
+    if !creator_may_share(&link)? {
+        return Ok(None);
+    }
+    Ok(Some(link))
+}
+
+/// Links act on behalf of their creator, so they stop working once the creator
+/// is deleted, demoted or can't see the shared gallery anymore.
+fn creator_may_share(link: &ShareLink) -> Result<bool> {
+    let creator = match provider::user::by_id(&link.created_by) {
+        Ok(user) => LoginUser::from(user),
+        Err(crate::database::Error::Diesel(diesel::NotFound)) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let gallery_id = match (link.gallery_id, link.picture_id) {
+        (Some(gallery_id), _) => gallery_id,
+        (_, Some(picture_id)) => match provider::picture::by_id(&picture_id) {
+            Ok(picture) => picture.gallery_id,
+            Err(crate::database::Error::Diesel(diesel::NotFound)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        },
+        _ => return Ok(false),
+    };
+    let gallery = match provider::gallery::by_id(&gallery_id) {
+        Ok(gallery) => gallery,
+        Err(crate::database::Error::Diesel(diesel::NotFound)) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(permission::can_edit_gallery(&creator, &gallery_id)?
+        && permission::can_show_gallery(Some(&creator), &gallery)?)
+}
+
+/// Links without password are always unlocked. Wrong passwords are throttled
+/// like failed logins, per link and per client address.
+pub fn check_password(link: &ShareLink, password: Option<&str>, client: &ClientInfo) -> Result<bool> {
+    let (hash, password) = match (&link.password, password) {
+        (None, _) => return Ok(true),
+        (Some(hash), Some(password)) => (hash, password),
+        (Some(_), None) => return Ok(false),
+    };
+    let subjects = throttle::share_subjects(&link.id, client);
+    if let Some(until) = throttle::locked_until(&subjects)? {
+        return Err(LoginError::Locked(until - now()).into());
+    }
+    if verify_pw(hash, password)? {
+        throttle::succeeded(&subjects)?;
+        Ok(true)
+    } else {
+        throttle::failed(&subjects)?;
+        Ok(false)
+    }
+}
+
+/// Whether the link grants access to the gallery. Links to a single picture
+/// don't grant access to its gallery.
+pub fn covers_gallery(link: &ShareLink, gallery_id: &i32) -> Result<bool> {
+    let root = match link.gallery_id {
+        Some(root) => root,
+        None => return Ok(false),
+    };
+    if root == *gallery_id {
+        return Ok(true);
+    }
+    if !link.recursive {
+        return Ok(false);
+    }
+    let mut current = match provider::gallery::by_id(gallery_id) {
+        Ok(gallery) => gallery,
+        Err(crate::database::Error::Diesel(diesel::NotFound)) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    let mut visited = HashSet::new();
+    while let Some(parent) = current.parent {
+        if parent == root {
+            return Ok(true);
+        }
+        if !visited.insert(parent) {
+            // Broken hierarchy where a gallery is its own ancestor.
+            return Ok(false);
+        }
+        current = provider::gallery::by_id(&parent)?;
+    }
+    Ok(false)
+}
+
+pub fn covers_picture(link: &ShareLink, picture_id: &i32) -> Result<bool> {
+    if link.picture_id == Some(*picture_id) {
+        return Ok(true);
+    }
+    match provider::picture::by_id(picture_id) {
+        Ok(picture) => covers_gallery(link, &picture.gallery_id),
+        Err(crate::database::Error::Diesel(diesel::NotFound)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Revokes one of the user's own links. Returns `false` if there is no such link.
+pub fn revoke(user: &LoginUser, link_id: &i32) -> Result<bool> {
+    match provider::share_link::by_id(link_id) {
+        Ok(link) if link.created_by == user.id => {
+            provider::share_link::delete(link_id)?;
+            Ok(true)
+        },
+        Ok(_) | Err(crate::database::Error::Diesel(diesel::NotFound)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    sha::sha1::Sha1::default().digest(token.as_bytes()).to_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::login::{ClientInfo, LoginError, LoginUser};
+    use crate::auth::permission::{ROLE_ADMIN, ROLE_EDITOR, ROLE_VIEWER, VISIBILITY_RESTRICTED};
+    use crate::database::model::NewGallery;
+    use crate::database::provider;
+    use crate::share::ShareError;
+    use crate::share::{ShareOptions, ShareTarget};
+
+    fn options() -> ShareOptions<'static> {
+        ShareOptions {
+            lifetime: None,
+            password: None,
+            allow_download: false,
+        }
+    }
+
+    fn admin() -> LoginUser {
+        LoginUser::from(crate::testing::save_user("admin", ROLE_ADMIN).unwrap())
+    }
+
+    #[test]
+    fn recursive_share_covers_sub_galleries() {
+        crate::testing::setup_database();
+        let user = admin();
+        let root = crate::testing::save_gallery_named("Root").unwrap();
+        let child = crate::testing::save_gallery(&NewGallery {
+            name: "Child".to_string(),
+            directory: None,
+            parent: Some(root.id),
+        }).unwrap();
+        let other = crate::testing::save_gallery_named("Other").unwrap();
+        let (flat, _) = super::create(&user, &ShareTarget::Gallery { gallery_id: root.id, recursive: false }, &options()).unwrap();
+        let (deep, token) = super::create(&user, &ShareTarget::Gallery { gallery_id: root.id, recursive: true }, &options()).unwrap();
+        assert!(super::covers_gallery(&flat, &root.id).unwrap());
+        assert!(!super::covers_gallery(&flat, &child.id).unwrap());
+        assert!(super::covers_gallery(&deep, &child.id).unwrap());
+        assert!(!super::covers_gallery(&deep, &other.id).unwrap());
+        assert_eq!(Some(deep), super::resolve(&token).unwrap());
+    }
+
+    #[test]
+    fn expired_link_not_resolved() {
+        crate::testing::setup_database();
+        let user = admin();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let options = ShareOptions {
+            lifetime: Some(-1),
+            ..options()
+        };
+        let (_, token) = super::create(&user, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options).unwrap();
+        assert!(super::resolve(&token).unwrap().is_none());
+        assert!(super::resolve("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn password() {
+        crate::testing::setup_database();
+        let user = admin();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let options = ShareOptions {
+            password: Some("secret"),
+            ..options()
+        };
+        let (link, _) = super::create(&user, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options).unwrap();
+        let client = ClientInfo::default();
+        assert!(!super::check_password(&link, None, &client).unwrap());
+        assert!(!super::check_password(&link, Some("wrong"), &client).unwrap());
+        assert!(super::check_password(&link, Some("secret"), &client).unwrap());
+    }
+
+    #[test]
+    fn wrong_passwords_lock_link() {
+        crate::testing::setup_database();
+        let user = admin();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let options = ShareOptions {
+            password: Some("secret"),
+            ..options()
+        };
+        let (link, _) = super::create(&user, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options).unwrap();
+        let client = ClientInfo {
+            user_agent: None,
+            ip: Some("10.0.0.1".to_string()),
+        };
+        for _ in 0..crate::config::get().login_max_failures {
+            assert!(!super::check_password(&link, Some("wrong"), &client).unwrap());
+        }
+        match super::check_password(&link, Some("secret"), &client) {
+            Err(ShareError::Login(LoginError::Locked(seconds))) => assert!(seconds > 0),
+            other => panic!("Expected locked link, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn creator_losing_access_disables_link() {
+        crate::testing::setup_database();
+        let editor = LoginUser::from(crate::testing::save_user("editor", ROLE_EDITOR).unwrap());
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let (_, demoted) = super::create(&editor, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options()).unwrap();
+        assert!(super::resolve(&demoted).unwrap().is_some());
+        provider::user::set_role(&editor.id, ROLE_VIEWER).unwrap();
+        assert!(super::resolve(&demoted).unwrap().is_none());
+        provider::user::set_role(&editor.id, ROLE_EDITOR).unwrap();
+        assert!(super::resolve(&demoted).unwrap().is_some());
+        provider::gallery::set_visibility(&gallery.id, VISIBILITY_RESTRICTED).unwrap();
+        assert!(super::resolve(&demoted).unwrap().is_none());
+    }
+
+    #[test]
+    fn deleted_creator_disables_link() {
+        crate::testing::setup_database();
+        let user = crate::testing::save_user("admin", ROLE_ADMIN).unwrap();
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let (_, token) = super::create(&LoginUser::from(user.clone()), &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options()).unwrap();
+        provider::user::delete(&user).unwrap();
+        assert!(super::resolve(&token).unwrap().is_none());
+    }
+
+    #[test]
+    fn parent_cycle_not_covered() {
+        crate::testing::setup_database();
+        let user = admin();
+        let root = crate::testing::save_gallery_named("Root").unwrap();
+        let first = crate::testing::save_gallery_named("First").unwrap();
+        let second = crate::testing::save_gallery(&NewGallery {
+            name: "Second".to_string(),
+            directory: None,
+            parent: Some(first.id),
+        }).unwrap();
+        provider::gallery::update(&first.id, None, None, Some(Some(&second.id))).unwrap();
+        let (link, _) = super::create(&user, &ShareTarget::Gallery { gallery_id: root.id, recursive: true }, &options()).unwrap();
+        assert!(!super::covers_gallery(&link, &second.id).unwrap());
+    }
+
+    #[test]
+    fn only_creator_revokes() {
+        crate::testing::setup_database();
+        let user = admin();
+        let other = LoginUser::from(crate::testing::save_user("other", ROLE_ADMIN).unwrap());
+        let gallery = crate::testing::save_gallery_named("Gal1").unwrap();
+        let (link, token) = super::create(&user, &ShareTarget::Gallery { gallery_id: gallery.id, recursive: false }, &options()).unwrap();
+        assert!(!super::revoke(&other, &link.id).unwrap());
+        assert!(super::revoke(&user, &link.id).unwrap());
+        assert!(super::resolve(&token).unwrap().is_none());
+    }
+}
\ No newline at end of file
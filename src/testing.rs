@@ -1,6 +1,7 @@
 use crate::database::{connection, Error};
 use diesel::migration::RunMigrationsError;
-use crate::database::model::{NewGallery, Gallery, NewPicture, Picture, NewTag, Tag};
+use crate::database::model::{NewGallery, Gallery, NewPicture, Picture, NewTag, Tag, TagType, NewUser, User, NewUserGroup, UserGroup};
+use rocket::http::{Cookie, Header};
 
 embed_migrations!();
 
@@ -13,9 +14,24 @@ impl From<RunMigrationsError> for Error {
 pub fn setup_database() {
     let conn = connection().unwrap();
     embedded_migrations::run(&*conn).unwrap();
+    crate::database::provider::api_token::clear_all();
+    crate::database::provider::audit_log::clear_all();
+    crate::database::provider::gallery_access::clear_all();
+    crate::database::provider::gallery_group_access::clear_all();
+    crate::database::provider::group_member::clear_all();
     crate::database::provider::gallery::clear_all();
     crate::database::provider::picture::clear_all();
+    crate::database::provider::metadata::clear_all();
+    crate::database::provider::thumb::clear_all();
+    crate::database::provider::picture_tag::clear_all();
     crate::database::provider::tag::clear_all();
+    crate::database::provider::login_failure::clear_all();
+    crate::database::provider::password_reset::clear_all();
+    crate::database::provider::recovery_code::clear_all();
+    crate::database::provider::session::clear_all();
+    crate::database::provider::share_link::clear_all();
+    crate::database::provider::user_group::clear_all();
+    crate::database::provider::user::clear_all();
 }
 
 pub fn save_gallery(new: &NewGallery) -> Result<Gallery, Error> {
@@ -50,7 +66,7 @@ pub fn save_picture(new: &NewPicture) -> Result<Picture, Error> {
 pub fn save_tag(new: &NewTag) -> Result<Tag, Error> {
     use crate::database::provider;
     provider::tag::insert(new)?;
-    if let Some(tag) = provider::tag::by_name(&new.name)? {
+    if let Some(tag) = provider::tag::by_name_in(&new.name, new.parent.as_ref())? {
         Ok(tag)
     } else {
         Err(Error::Unknown(None))
@@ -59,7 +75,52 @@ pub fn save_tag(new: &NewTag) -> Result<Tag, Error> {
 
 pub fn save_tag_named(name: &str) -> Result<Tag, Error> {
     save_tag(&NewTag {
-        tag_type: 1,
+        tag_type: TagType::FreeForm.id(),
         name: name.to_string(),
+        parent: None,
     })
+}
+
+pub fn save_user(username: &str, role: &str) -> Result<User, Error> {
+    use crate::database::provider;
+    provider::user::insert(&NewUser {
+        username: username.to_string(),
+        email: format!("{}@example.com", username),
+        password: String::new(),
+        verification: None,
+        role: role.to_string(),
+    })?;
+    if let Some(user) = provider::user::by_username(username)? {
+        Ok(user)
+    } else {
+        Err(Error::Unknown(None))
+    }
+}
+
+pub fn save_group(name: &str) -> Result<UserGroup, Error> {
+    use crate::database::provider;
+    provider::user_group::insert(&NewUserGroup {
+        name: name.to_string(),
+    })?;
+    if let Some(group) = provider::user_group::by_name(name)? {
+        Ok(group)
+    } else {
+        Err(Error::Unknown(None))
+    }
+}
+
+pub fn session_cookie(user: User) -> Cookie<'static> {
+    Cookie::new("session", crate::auth::login::session_for(user))
+}
+
+pub const CSRF_TOKEN: &'static str = "csrf-test-token";
+
+/// Local requests keep cookies out of the `Cookie` header, where the CSRF
+/// guards look for the token, so the header is set by hand.
+pub fn csrf_cookie() -> Header<'static> {
+    Header::new("Cookie", format!("csrf={}", CSRF_TOKEN))
+}
+
+pub fn csrf_header() -> Header<'static> {
+    Header::new("X-CSRF-Token", CSRF_TOKEN)
 }
\ No newline at end of file
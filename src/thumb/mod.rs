@@ -1,14 +1,29 @@
+use crate::config::Rendition;
 use crate::database::model::{Thumb, Picture};
-use crate::disk::get_thumbs_dir;
+use crate::disk::{get_thumbs_dir, rendition_path};
 use colored::Colorize;
-use image::ImageError;
+use image::{DynamicImage, ImageError, ImageFormat, ImageOutputFormat};
 use image::imageops::FilterType::Triangle;
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+lazy_static! {
+    static ref RESIZED: Mutex<ResizeCache> = Mutex::new(ResizeCache::default());
+}
+
+/// Renders of sizes that aren't configured which are running right now.
+static RENDERING: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub enum ThumbError {
     Database(crate::database::Error),
     Image(ImageError),
     Io(std::io::Error),
+    /// `Config.resize_max_renders` renders are running already.
+    Busy,
 }
 
 impl From<crate::database::Error> for ThumbError {
@@ -31,46 +46,278 @@ impl From<std::io::Error> for ThumbError {
 
 pub type Result<T> = std::result::Result<T, ThumbError>;
 
-pub fn load(pic_id: &i32) -> Result<Option<Vec<u8>>> {
-    if crate::database::provider::thumb::by_picture(pic_id)? .is_some(){
-        Ok(Some(crate::disk::load_thumb(pic_id)?))
+/// How a picture is fitted into the requested rendition size.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Scale the whole picture into the size, keeping the aspect ratio.
+    Contain,
+    /// Scale and crop the picture to cover the whole size.
+    Cover,
+    /// Scale the picture to exactly the size, ignoring the aspect ratio.
+    Fill,
+}
+
+impl Fit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fit::Contain => "contain",
+            Fit::Cover => "cover",
+            Fit::Fill => "fill",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Fit> {
+        match name {
+            "contain" => Some(Fit::Contain),
+            "cover" => Some(Fit::Cover),
+            "fill" => Some(Fit::Fill),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Fit {
+    fn default() -> Self {
+        Fit::Contain
+    }
+}
+
+/// Rendition used for the thumbnail grids.
+pub const THUMB_RENDITION: &'static str = "small";
+
+/// Looks up a rendition configured by name.
+pub fn rendition(name: &str) -> Option<&'static Rendition> {
+    crate::config::get().renditions.iter().find(|r| r.name.eq(name))
+}
+
+/// Builds an unnamed rendition for the requested size, keyed by its dimensions.
+pub fn custom_rendition(width: u32, height: u32, fit: Fit) -> Rendition {
+    Rendition {
+        name: format!("{}x{}_{}", width, height, fit.name()),
+        width,
+        height,
+        fit,
+        pregenerate: false,
+    }
+}
+
+pub fn thumb_rendition() -> Rendition {
+    rendition(THUMB_RENDITION).cloned().unwrap_or(Rendition {
+        name: THUMB_RENDITION.to_string(),
+        width: 100,
+        height: 100,
+        fit: Fit::Contain,
+        pregenerate: true,
+    })
+}
+
+/// Loads the rendition of `pic` if it was generated from the current version of the picture.
+pub fn load(pic: &Picture, rendition: &Rendition) -> Result<Option<File>> {
+    if is_current(pic, rendition)? {
+        Ok(Some(crate::disk::open_rendition(&pic.id, &rendition.name)?))
     } else {
         Ok(None)
     }
 }
 
-pub fn load_or_generate(pic: &Picture) -> Result<Option<Vec<u8>>> {
-    if let Some(thumb) = load(&pic.id)? {
-        Ok(Some(thumb))
+pub fn load_or_generate(pic: &Picture, rendition: &Rendition) -> Result<File> {
+    if let Some(file) = load(pic, rendition)? {
+        Ok(file)
     } else {
-        generate(&pic)?;
-        load(&pic.id)
+        generate(pic, rendition)?;
+        Ok(crate::disk::open_rendition(&pic.id, &rendition.name)?)
     }
 }
 
+/// Generates the thumbnail and all other renditions configured with `pregenerate`
+/// unless they are up to date.
 pub fn generate_if_needed(pic: &Picture) -> Result<()> {
-    let thumb = crate::database::provider::thumb::by_picture(&pic.id)?;
-    if thumb.is_none() || thumb.unwrap().picture_hash.ne(&pic.sha1) {
-        generate(&pic)
-    } else {
-        Ok(())
+    let mut renditions: Vec<Rendition> = crate::config::get().renditions.iter()
+        .filter(|r| r.pregenerate)
+        .cloned()
+        .collect();
+    if !renditions.iter().any(|r| r.name.eq(THUMB_RENDITION)) {
+        renditions.push(thumb_rendition());
     }
+    for rendition in renditions.iter() {
+        if !is_current(pic, rendition)? {
+            generate(pic, rendition)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_current(pic: &Picture, rendition: &Rendition) -> Result<bool> {
+    let thumb = crate::database::provider::thumb::by_picture_and_rendition(&pic.id, &rendition.name)?;
+    Ok(match thumb {
+        Some(thumb) => thumb.picture_hash.eq(&pic.sha1)
+            && thumb.width.eq(&(rendition.width as i32))
+            && thumb.height.eq(&(rendition.height as i32))
+            && thumb.fit.eq(rendition.fit.name())
+            && Path::new(&rendition_path(&pic.id, &rendition.name)).is_file(),
+        None => false,
+    })
 }
 
-pub fn generate(pic: &Picture) -> Result<()> {
-    let img = image::open(&pic.path)?;
-    let img = img.resize(100, 100, Triangle);
-    let path = thumb_path(&pic.id);
-    println!("  {} [Thumb] {}", "+".green(), path.green());
-    img.save(path)?;
+pub fn generate(pic: &Picture, rendition: &Rendition) -> Result<()> {
+    let img = render(pic, rendition)?;
+    let path = rendition_path(&pic.id, &rendition.name);
+    println!("  {} [{}] {}", "+".green(), &rendition.name, path.green());
+    let tmp = format!("{}.{}.tmp", &path, crate::auth::random_string(8));
+    if let Err(e) = img.save_with_format(&tmp, ImageFormat::Png) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    std::fs::rename(&tmp, &path)?;
     let thumb = Thumb {
         picture_id: pic.id.clone(),
+        rendition: rendition.name.clone(),
+        width: rendition.width as i32,
+        height: rendition.height as i32,
+        fit: rendition.fit.name().to_string(),
         picture_hash: pic.sha1.clone(),
     };
-    crate::database::provider::thumb::insert(&thumb)?;
+    crate::database::provider::thumb::save(&thumb)?;
     Ok(())
 }
 
-fn thumb_path(pic_id: &i32) -> String {
-    format!("{}/{}.png", get_thumbs_dir(), pic_id)
+/// Renders a size that isn't configured as rendition. These are only kept in memory,
+/// so arbitrary sizes cannot fill the disk and the database.
+pub fn load_or_render_resized(pic: &Picture, rendition: &Rendition) -> Result<Arc<[u8]>> {
+    let conf = crate::config::get();
+    let key = format!("{}-{}-{}", pic.id, &pic.sha1, &rendition.name);
+    if let Some(data) = RESIZED.lock().unwrap().get(&key) {
+        return Ok(data);
+    }
+    let _slot = RenderSlot::acquire(conf.resize_max_renders).ok_or(ThumbError::Busy)?;
+    let img = render(pic, rendition)?;
+    let mut data = vec![];
+    img.write_to(&mut data, ImageOutputFormat::Png)?;
+    let data: Arc<[u8]> = Arc::from(data);
+    RESIZED.lock().unwrap().insert(key, data.clone(), conf.resize_cache_size);
+    Ok(data)
+}
+
+/// Resized pictures, evicting the least recently used ones once they take more
+/// than the allowed number of bytes.
+#[derive(Default)]
+struct ResizeCache {
+    entries: VecDeque<(String, Arc<[u8]>)>,
+    size: usize,
+}
+
+impl ResizeCache {
+    fn get(&mut self, key: &str) -> Option<Arc<[u8]>> {
+        let index = self.entries.iter().position(|(k, _)| k.eq(key))?;
+        let entry = self.entries.remove(index)?;
+        let data = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(data)
+    }
+
+    fn insert(&mut self, key: String, data: Arc<[u8]>, max_size: usize) {
+        if let Some(index) = self.entries.iter().position(|(k, _)| k.eq(&key)) {
+            if let Some((_, old)) = self.entries.remove(index) {
+                self.size -= old.len();
+            }
+        }
+        if data.len() > max_size {
+            return;
+        }
+        self.size += data.len();
+        self.entries.push_back((key, data));
+        while self.size > max_size {
+            match self.entries.pop_front() {
+                Some((_, old)) => self.size -= old.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// One of the `Config.resize_max_renders` renders allowed at the same time,
+/// released when dropped.
+struct RenderSlot;
+
+impl RenderSlot {
+    fn acquire(max: usize) -> Option<RenderSlot> {
+        if RENDERING.fetch_add(1, Ordering::SeqCst) >= max {
+            RENDERING.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(RenderSlot)
+    }
+}
+
+impl Drop for RenderSlot {
+    fn drop(&mut self) {
+        RENDERING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn render(pic: &Picture, rendition: &Rendition) -> Result<DynamicImage> {
+    let img = image::io::Reader::open(&pic.path)?.with_guessed_format()?.decode()?;
+    let orientation = crate::database::provider::metadata::by_picture(&pic.id)?.and_then(|m| m.orientation);
+    let img = crate::metadata::apply_orientation(img, orientation);
+    Ok(match rendition.fit {
+        Fit::Contain => img.resize(rendition.width, rendition.height, Triangle),
+        Fit::Cover => img.resize_to_fill(rendition.width, rendition.height, Triangle),
+        Fit::Fill => img.resize_exact(rendition.width, rendition.height, Triangle),
+    })
+}
+
+/// Removes all renditions of `pic` from the database and the cache directory.
+pub fn remove(pic: &Picture) -> std::result::Result<(), crate::database::Error> {
+    for thumb in crate::database::provider::thumb::by_picture(&pic.id)? {
+        crate::database::provider::thumb::delete(&thumb)?;
+        let _ = std::fs::remove_file(rendition_path(&pic.id, &thumb.rendition));
+    }
+    Ok(())
+}
+
+/// Deletes cached files that are not served anymore: sizes cached by `/picture/resize`
+/// before only configured renditions were stored, thumbnails of the old `{id}.png`
+/// layout and files left behind by interrupted writes.
+pub fn remove_orphans() -> Result<()> {
+    use crate::database::provider;
+    let thumb = thumb_rendition();
+    let mut kept = HashSet::new();
+    for stored in provider::thumb::all()? {
+        if stored.rendition.eq(&thumb.name) || rendition(&stored.rendition).is_some() {
+            kept.insert(rendition_path(&stored.picture_id, &stored.rendition));
+        } else {
+            provider::thumb::delete(&stored)?;
+        }
+    }
+    for entry in std::fs::read_dir(get_thumbs_dir())?.filter_map(std::result::Result::ok) {
+        let path = entry.path();
+        let path_str = path.to_str().unwrap_or("").to_string();
+        if path.is_file() && !kept.contains(&path_str) {
+            println!("  {} {}", "-".red(), path_str.red());
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    #[test]
+    fn resize_cache_evicts_least_recently_used() {
+        let mut cache = super::ResizeCache::default();
+        let data = |len: usize| -> Arc<[u8]> { Arc::from(vec![0u8; len]) };
+        cache.insert("first".to_string(), data(4), 10);
+        cache.insert("second".to_string(), data(4), 10);
+        assert!(cache.get("first").is_some());
+        cache.insert("third".to_string(), data(4), 10);
+        assert!(cache.get("second").is_none());
+        assert!(cache.get("first").is_some());
+        assert!(cache.get("third").is_some());
+        cache.insert("huge".to_string(), data(11), 10);
+        assert!(cache.get("huge").is_none());
+        assert_eq!(cache.size, 8);
+    }
 }
@@ -0,0 +1,297 @@
+use crate::config::{Config, ScanDir};
+use colored::Colorize;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum WatchError {
+    Notify(notify::Error),
+    Scan(crate::scan::ScanError),
+    Database(crate::database::Error),
+    Thumb(crate::thumb::ThumbError),
+}
+
+impl From<notify::Error> for WatchError {
+    fn from(e: notify::Error) -> Self {
+        WatchError::Notify(e)
+    }
+}
+
+impl From<crate::scan::ScanError> for WatchError {
+    fn from(e: crate::scan::ScanError) -> Self {
+        WatchError::Scan(e)
+    }
+}
+
+impl From<crate::database::Error> for WatchError {
+    fn from(e: crate::database::Error) -> Self {
+        WatchError::Database(e)
+    }
+}
+
+impl From<crate::thumb::ThumbError> for WatchError {
+    fn from(e: crate::thumb::ThumbError) -> Self {
+        WatchError::Thumb(e)
+    }
+}
+
+pub type WatchResult<T> = Result<T, WatchError>;
+
+/// Starts a background thread watching all configured scan directories.
+/// File system events are debounced by `Config.watch_delay` seconds, so copying
+/// a large batch of files only results in one event per file.
+pub fn start(conf: &'static Config) {
+    std::thread::spawn(move || {
+        let (tx, rx) = channel();
+        let _watcher = match watcher(conf, tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("{} {:?}", "! Cannot create file watcher:".yellow(), e);
+                return;
+            }
+        };
+        for event in rx.iter() {
+            if let Err(e) = handle(conf, event) {
+                eprintln!("{} {:?}", "! Error handling file event:".yellow(), e);
+            }
+        }
+    });
+}
+
+/// Creates a watcher sending the debounced events of all scan directories to `tx`.
+/// Events stop as soon as the returned watcher is dropped.
+fn watcher(conf: &Config, tx: Sender<DebouncedEvent>) -> notify::Result<RecommendedWatcher> {
+    let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_secs(conf.watch_delay))?;
+    for dir in conf.scan_dirs.iter() {
+        let mode = if dir.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        match watcher.watch(&dir.path, mode) {
+            Ok(_) => println!("{} [{}]", "Watching".blue(), dir.path.magenta()),
+            Err(e) => eprintln!("{} [{}] {:?}", "! Cannot watch directory".yellow(), dir.path.yellow(), e),
+        }
+    }
+    Ok(watcher)
+}
+
+fn handle(conf: &Config, event: DebouncedEvent) -> WatchResult<()> {
+    use DebouncedEvent::*;
+    match event {
+        Create(path) | Write(path) => added(conf, &path),
+        Remove(path) => removed(&path),
+        Rename(from, to) => {
+            removed(&from)?;
+            added(conf, &to)
+        },
+        Rescan => rescan(conf),
+        Error(e, path) => {
+            eprintln!("{} {:?} [{:?}]", "! File watcher error:".yellow(), e, path);
+            Ok(())
+        },
+        _ => Ok(()),
+    }
+}
+
+fn added(conf: &Config, path: &PathBuf) -> WatchResult<()> {
+    let scan_dir = match scan_dir_for(conf, path) {
+        Some(scan_dir) => scan_dir,
+        None => return Ok(()),
+    };
+    let path_str = path.to_str().unwrap();
+    if path.is_dir() {
+        if scan_dir.recursive {
+            crate::scan::scan_recursively(path_str, &parents(scan_dir, path))?;
+            generate_thumbs(path_str)?;
+        }
+    } else if path.is_file() {
+        let dir = path.parent().unwrap();
+        if !scan_dir.recursive && dir.ne(Path::new(&scan_dir.path)) {
+            return Ok(());
+        }
+        if let Some(picture) = crate::scan::scan_single(path_str, dir.to_str().unwrap(), &parents(scan_dir, dir))? {
+            crate::thumb::generate_if_needed(&picture)?;
+        }
+    }
+    Ok(())
+}
+
+fn removed(path: &PathBuf) -> WatchResult<()> {
+    crate::scan::remove_path(path.to_str().unwrap())?;
+    Ok(())
+}
+
+fn rescan(conf: &Config) -> WatchResult<()> {
+    println!("{}", "Rescanning watched directories".blue());
+    for gallery in crate::database::provider::gallery::all()? {
+        crate::scan::check_gallery(&gallery.id)?;
+    }
+    for dir in conf.scan_dirs.iter() {
+        if dir.recursive {
+            crate::scan::scan_recursively(&dir.path, &vec![])?;
+        } else {
+            crate::scan::scan(dir, None)?;
+        }
+        generate_thumbs(&dir.path)?;
+    }
+    Ok(())
+}
+
+fn generate_thumbs(dir: &str) -> WatchResult<()> {
+    use crate::database::provider;
+    if let Some(gallery) = provider::gallery::by_directory(dir)? {
+        for pic in provider::picture::by_gallery(&gallery.id)? {
+            crate::thumb::generate_if_needed(&pic)?;
+        }
+    }
+    for sub_dir in std::fs::read_dir(dir).map_err(crate::scan::ScanError::from)?.filter_map(Result::ok) {
+        if sub_dir.path().is_dir() {
+            generate_thumbs(sub_dir.path().to_str().unwrap())?;
+        }
+    }
+    Ok(())
+}
+
+fn scan_dir_for<'a>(conf: &'a Config, path: &Path) -> Option<&'a ScanDir> {
+    conf.scan_dirs.iter().find(|d| path.starts_with(&d.path))
+}
+
+/// Lists the directories between the scan directory root and `dir` as expected
+/// by `scan::scan_recursively`, starting with the root and excluding `dir` itself.
+fn parents(scan_dir: &ScanDir, dir: &Path) -> Vec<String> {
+    let root = Path::new(&scan_dir.path);
+    let mut parents: Vec<String> = dir.ancestors()
+        .skip(1)
+        .take_while(|p| p.starts_with(root))
+        .map(|p| p.to_str().unwrap().to_string())
+        .collect();
+    parents.reverse();
+    parents
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ScanDir};
+    use crate::database::provider;
+    use notify::DebouncedEvent;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    fn setup(name: &str, recursive: bool) -> (PathBuf, Config) {
+        crate::testing::setup_database();
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let conf = crate::config::with_scan_dirs(vec![ScanDir {
+            path: dir.to_str().unwrap().to_string(),
+            recursive,
+        }]);
+        (dir, conf)
+    }
+
+    fn write_png(path: &Path, width: u32, height: u32) {
+        image::RgbImage::new(width, height).save(path).unwrap();
+    }
+
+    fn width_of(path: &Path) -> Option<i32> {
+        provider::picture::by_path(path.to_str().unwrap()).unwrap().map(|p| p.width)
+    }
+
+    #[test]
+    fn create_and_write() {
+        let (dir, conf) = setup("regal_watch_create", false);
+        let file = dir.join("img1.png");
+        write_png(&file, 4, 3);
+        super::handle(&conf, DebouncedEvent::Create(file.clone())).unwrap();
+        assert_eq!(width_of(&file), Some(4));
+        let gallery = provider::gallery::by_directory(dir.to_str().unwrap()).unwrap().unwrap();
+        write_png(&file, 6, 5);
+        super::handle(&conf, DebouncedEvent::Write(file.clone())).unwrap();
+        assert_eq!(width_of(&file), Some(6));
+        assert_eq!(provider::picture::by_gallery(&gallery.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rename() {
+        let (dir, conf) = setup("regal_watch_rename", false);
+        let from = dir.join("img1.png");
+        let to = dir.join("img2.png");
+        write_png(&from, 4, 3);
+        super::handle(&conf, DebouncedEvent::Create(from.clone())).unwrap();
+        std::fs::rename(&from, &to).unwrap();
+        super::handle(&conf, DebouncedEvent::Rename(from.clone(), to.clone())).unwrap();
+        assert_eq!(width_of(&from), None);
+        assert_eq!(width_of(&to), Some(4));
+    }
+
+    #[test]
+    fn remove() {
+        let (dir, conf) = setup("regal_watch_remove", true);
+        let sub_dir = dir.join("sub");
+        std::fs::create_dir(&sub_dir).unwrap();
+        let file = dir.join("img1.png");
+        let sub_file = sub_dir.join("img2.png");
+        write_png(&file, 4, 3);
+        write_png(&sub_file, 4, 3);
+        super::handle(&conf, DebouncedEvent::Create(file.clone())).unwrap();
+        super::handle(&conf, DebouncedEvent::Create(sub_dir.clone())).unwrap();
+        assert_eq!(width_of(&sub_file), Some(4));
+        std::fs::remove_file(&file).unwrap();
+        super::handle(&conf, DebouncedEvent::Remove(file.clone())).unwrap();
+        assert_eq!(width_of(&file), None);
+        std::fs::remove_dir_all(&sub_dir).unwrap();
+        super::handle(&conf, DebouncedEvent::Remove(sub_dir.clone())).unwrap();
+        assert_eq!(width_of(&sub_file), None);
+        assert!(provider::gallery::by_directory(sub_dir.to_str().unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn ignores_unwatched_files() {
+        let (dir, conf) = setup("regal_watch_unwatched", false);
+        let sub_dir = dir.join("sub");
+        std::fs::create_dir(&sub_dir).unwrap();
+        let nested = sub_dir.join("img1.png");
+        write_png(&nested, 4, 3);
+        super::handle(&conf, DebouncedEvent::Create(nested.clone())).unwrap();
+        let outside = std::env::temp_dir().join("regal_watch_outside.png");
+        write_png(&outside, 4, 3);
+        super::handle(&conf, DebouncedEvent::Create(outside.clone())).unwrap();
+        assert_eq!(width_of(&nested), None);
+        assert_eq!(width_of(&outside), None);
+    }
+
+    #[test]
+    fn debounces_events() {
+        let (dir, mut conf) = setup("regal_watch_debounce", false);
+        conf.watch_delay = 1;
+        let (tx, rx) = channel();
+        let _watcher = super::watcher(&conf, tx).unwrap();
+        let file = dir.join("img1.png");
+        write_png(&file, 4, 3);
+        for size in 5..8 {
+            std::thread::sleep(Duration::from_millis(100));
+            write_png(&file, size, size);
+        }
+        std::thread::sleep(Duration::from_secs(3));
+        let events: Vec<DebouncedEvent> = rx.try_iter()
+            .filter(|e| match e {
+                DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => false,
+                _ => true,
+            })
+            .collect();
+        assert_eq!(events.len(), 1);
+        let is_create = match &events[0] {
+            DebouncedEvent::Create(path) => path.ends_with("img1.png"),
+            _ => false,
+        };
+        assert!(is_create);
+        for event in events {
+            super::handle(&conf, event).unwrap();
+        }
+        assert_eq!(width_of(&file), Some(7));
+    }
+}